@@ -0,0 +1,6 @@
+-- Add migration script here
+-- The default is volatile, so existing rows each get their own token too.
+ALTER TABLE subscriptions
+    ADD COLUMN unsubscribe_token TEXT NOT NULL UNIQUE
+        DEFAULT replace(gen_random_uuid()::text, '-', ''),
+    ADD COLUMN unsubscribed_at timestamptz NULL;
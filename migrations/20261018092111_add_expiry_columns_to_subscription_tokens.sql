@@ -0,0 +1,5 @@
+-- Add migration script here
+-- Tokens issued before this migration start their TTL from the moment it runs.
+ALTER TABLE subscription_tokens
+    ADD COLUMN created_at timestamptz NOT NULL DEFAULT now(),
+    ADD COLUMN consumed_at timestamptz NULL;
@@ -0,0 +1,3 @@
+-- The language confirmation emails and newsletter issues are sent in.
+-- NULL means the default locale.
+ALTER TABLE subscriptions ADD COLUMN locale TEXT;
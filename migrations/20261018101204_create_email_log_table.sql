@@ -0,0 +1,16 @@
+-- One row per attempt at sending an email to a subscriber.
+CREATE TABLE email_log (
+    id uuid NOT NULL PRIMARY KEY,
+    subscriber_id uuid NOT NULL REFERENCES subscriptions (id),
+    -- `confirmation` or `newsletter_issue`.
+    email_type TEXT NOT NULL,
+    newsletter_issue_id uuid REFERENCES newsletter_issues (newsletter_issue_id),
+    -- `sent`, `deferred` (failed, will be retried) or `failed`.
+    outcome TEXT NOT NULL,
+    provider_message_id TEXT,
+    submitted_at timestamptz,
+    error_code BIGINT,
+    error_message TEXT,
+    created_at timestamptz NOT NULL DEFAULT now()
+);
+CREATE INDEX email_log_subscriber_id_idx ON email_log (subscriber_id, created_at);
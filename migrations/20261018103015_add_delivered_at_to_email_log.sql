@@ -0,0 +1,3 @@
+-- Set when the provider reports that the receiving server accepted the email.
+ALTER TABLE email_log ADD COLUMN delivered_at timestamptz;
+CREATE INDEX email_log_provider_message_id_idx ON email_log (provider_message_id);
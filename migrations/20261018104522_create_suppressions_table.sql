@@ -0,0 +1,14 @@
+-- Addresses we must never email, whatever their subscription status.
+CREATE TABLE suppressions (
+    -- Lowercased.
+    email TEXT NOT NULL PRIMARY KEY,
+    reason TEXT NOT NULL,
+    -- Who asked for the address to be blocked, e.g. `legal` or `support`.
+    source TEXT NOT NULL,
+    added_by UUID REFERENCES users (user_id) ON DELETE SET NULL,
+    created_at timestamptz NOT NULL DEFAULT now()
+);
+
+
+-- Emails skipped because of a suppression are recorded in `email_log` with the outcome
+-- `suppressed`, the reason being stored in `error_message`.
@@ -0,0 +1,26 @@
+-- Proof of how and when each subscriber consented to our emails. Rows are never
+-- changed or removed once written.
+CREATE TABLE consent_events (
+    id uuid NOT NULL PRIMARY KEY,
+    subscriber_id uuid NOT NULL REFERENCES subscriptions (id),
+    -- `subscribed` (signup form submitted) or `confirmed` (confirmation link followed).
+    event_type TEXT NOT NULL,
+    ip_address TEXT,
+    user_agent TEXT,
+    -- The page the request came from.
+    source_page TEXT,
+    -- The privacy policy in force when consent was given.
+    policy_version TEXT NOT NULL,
+    occurred_at timestamptz NOT NULL DEFAULT now()
+);
+CREATE INDEX consent_events_subscriber_id_idx ON consent_events (subscriber_id, occurred_at);
+
+CREATE FUNCTION reject_consent_event_changes() RETURNS trigger AS $$
+BEGIN
+    RAISE EXCEPTION 'consent_events is append-only';
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER consent_events_append_only
+    BEFORE UPDATE OR DELETE ON consent_events
+    FOR EACH ROW EXECUTE FUNCTION reject_consent_event_changes();
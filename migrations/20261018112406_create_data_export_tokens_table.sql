@@ -0,0 +1,7 @@
+-- Tokens of the links subscribers download their data with. A link can be used any
+-- number of times until it expires.
+CREATE TABLE data_export_tokens (
+    data_export_token TEXT NOT NULL PRIMARY KEY,
+    subscriber_id uuid NOT NULL REFERENCES subscriptions (id),
+    created_at timestamptz NOT NULL DEFAULT now()
+);
@@ -0,0 +1,50 @@
+-- Erased subscribers keep their row, so that aggregate delivery statistics survive,
+-- but lose everything that identifies them and get the status `erased`.
+ALTER TABLE subscriptions ADD COLUMN erased_at timestamptz NULL;
+
+-- Tokens of the links subscribers confirm the erasure of their data with.
+CREATE TABLE erasure_tokens (
+    erasure_token TEXT NOT NULL PRIMARY KEY,
+    subscriber_id uuid NOT NULL REFERENCES subscriptions (id),
+    created_at timestamptz NOT NULL DEFAULT now()
+);
+
+-- One row per erasure, for audits. `email_hash` is a keyed hash of the lowercased
+-- address: it tells whether an address was erased without revealing it.
+CREATE TABLE erasures (
+    id uuid NOT NULL PRIMARY KEY,
+    subscriber_id uuid NOT NULL REFERENCES subscriptions (id),
+    email_hash TEXT NOT NULL,
+    -- `subscriber` or `admin`.
+    requested_by TEXT NOT NULL,
+    admin_user_id uuid REFERENCES users (user_id) ON DELETE SET NULL,
+    erased_at timestamptz NOT NULL DEFAULT now()
+);
+
+-- Suppressions of erased addresses, keyed by the same hash. They are turned back into
+-- regular suppressions if the address signs up again.
+CREATE TABLE erased_suppressions (
+    email_hash TEXT NOT NULL PRIMARY KEY,
+    reason TEXT NOT NULL,
+    source TEXT NOT NULL,
+    created_at timestamptz NOT NULL
+);
+
+-- Erasure scrubs the personal data of consent events. Any other change is rejected.
+CREATE OR REPLACE FUNCTION reject_consent_event_changes() RETURNS trigger AS $$
+BEGIN
+    IF TG_OP = 'UPDATE'
+        AND NEW.id = OLD.id
+        AND NEW.subscriber_id = OLD.subscriber_id
+        AND NEW.event_type = OLD.event_type
+        AND NEW.policy_version = OLD.policy_version
+        AND NEW.occurred_at = OLD.occurred_at
+        AND NEW.ip_address IS NULL
+        AND NEW.user_agent IS NULL
+        AND NEW.source_page IS NULL
+    THEN
+        RETURN NEW;
+    END IF;
+    RAISE EXCEPTION 'consent_events is append-only';
+END;
+$$ LANGUAGE plpgsql;
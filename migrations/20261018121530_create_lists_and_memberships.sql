@@ -0,0 +1,92 @@
+-- Subscribers can join several lists. `subscriptions` keeps one row per address, with
+-- the state of the address itself: `active`, `bounced`, `complained` or `erased`.
+-- `list_memberships` tracks each list the address joined.
+CREATE TABLE lists (
+    id uuid NOT NULL PRIMARY KEY,
+    -- What signup forms and the publishing API refer to the list by.
+    slug TEXT NOT NULL UNIQUE,
+    name TEXT NOT NULL,
+    -- Signups and issues that do not name a list go to the default one.
+    is_default BOOLEAN NOT NULL DEFAULT false,
+    created_at timestamptz NOT NULL DEFAULT now()
+);
+CREATE UNIQUE INDEX lists_single_default_idx ON lists (is_default) WHERE is_default;
+
+-- The list everybody subscribed to so far.
+INSERT INTO lists (id, slug, name, is_default)
+VALUES (gen_random_uuid(), 'newsletter', 'Newsletter', true);
+
+CREATE TABLE list_memberships (
+    list_id uuid NOT NULL REFERENCES lists (id),
+    subscriber_id uuid NOT NULL REFERENCES subscriptions (id),
+    -- `pending_confirmation`, `confirmed` or `unsubscribed`.
+    status TEXT NOT NULL,
+    subscribed_at timestamptz NOT NULL,
+    unsubscribed_at timestamptz NULL,
+    unsubscribe_token TEXT NOT NULL UNIQUE
+        DEFAULT replace(gen_random_uuid()::text, '-', ''),
+    PRIMARY KEY (list_id, subscriber_id)
+);
+CREATE INDEX list_memberships_subscriber_id_idx ON list_memberships (subscriber_id);
+
+-- Existing unsubscribe links keep working. Bounced and complained addresses were either
+-- pending or confirmed: a consumed confirmation token tells which.
+INSERT INTO list_memberships (
+    list_id, subscriber_id, status, subscribed_at, unsubscribed_at, unsubscribe_token
+)
+SELECT
+    (SELECT id FROM lists WHERE is_default),
+    s.id,
+    CASE
+        WHEN s.status IN ('pending_confirmation', 'confirmed', 'unsubscribed') THEN s.status
+        WHEN s.status = 'erased' THEN 'unsubscribed'
+        WHEN EXISTS (
+            SELECT 1 FROM subscription_tokens t
+            WHERE t.subscriber_id = s.id AND t.consumed_at IS NOT NULL
+        ) THEN 'confirmed'
+        ELSE 'pending_confirmation'
+    END,
+    s.subscribed_at,
+    s.unsubscribed_at,
+    s.unsubscribe_token
+FROM subscriptions s;
+
+UPDATE subscriptions SET status = 'active'
+WHERE status IN ('pending_confirmation', 'confirmed', 'unsubscribed');
+ALTER TABLE subscriptions
+    DROP COLUMN unsubscribe_token,
+    DROP COLUMN unsubscribed_at;
+
+-- Confirmation links, consent and issues are per list.
+ALTER TABLE subscription_tokens ADD COLUMN list_id uuid REFERENCES lists (id);
+UPDATE subscription_tokens SET list_id = (SELECT id FROM lists WHERE is_default);
+ALTER TABLE subscription_tokens ALTER COLUMN list_id SET NOT NULL;
+
+ALTER TABLE newsletter_issues ADD COLUMN list_id uuid REFERENCES lists (id);
+UPDATE newsletter_issues SET list_id = (SELECT id FROM lists WHERE is_default);
+ALTER TABLE newsletter_issues ALTER COLUMN list_id SET NOT NULL;
+
+ALTER TABLE consent_events ADD COLUMN list_id uuid REFERENCES lists (id);
+ALTER TABLE consent_events DISABLE TRIGGER consent_events_append_only;
+UPDATE consent_events SET list_id = (SELECT id FROM lists WHERE is_default);
+ALTER TABLE consent_events ENABLE TRIGGER consent_events_append_only;
+ALTER TABLE consent_events ALTER COLUMN list_id SET NOT NULL;
+
+CREATE OR REPLACE FUNCTION reject_consent_event_changes() RETURNS trigger AS $$
+BEGIN
+    IF TG_OP = 'UPDATE'
+        AND NEW.id = OLD.id
+        AND NEW.subscriber_id = OLD.subscriber_id
+        AND NEW.list_id = OLD.list_id
+        AND NEW.event_type = OLD.event_type
+        AND NEW.policy_version = OLD.policy_version
+        AND NEW.occurred_at = OLD.occurred_at
+        AND NEW.ip_address IS NULL
+        AND NEW.user_agent IS NULL
+        AND NEW.source_page IS NULL
+    THEN
+        RETURN NEW;
+    END IF;
+    RAISE EXCEPTION 'consent_events is append-only';
+END;
+$$ LANGUAGE plpgsql;
@@ -0,0 +1,18 @@
+-- Confirmation, data export and erasure emails are sent by a background worker, so
+-- that the endpoints answer the same way, as fast, whatever they find, and failed
+-- attempts are recorded in `email_log` even when they are retried later.
+-- The recipient is read from `subscriptions` when the email goes out.
+CREATE TABLE transactional_email_queue (
+    id uuid NOT NULL PRIMARY KEY,
+    subscriber_id uuid NOT NULL REFERENCES subscriptions (id),
+    -- As in `email_log.email_type`.
+    email_type TEXT NOT NULL,
+    subject TEXT NOT NULL,
+    html_body TEXT NOT NULL,
+    text_body TEXT NOT NULL,
+    n_retries SMALLINT NOT NULL DEFAULT 0,
+    execute_after timestamptz NOT NULL DEFAULT now(),
+    created_at timestamptz NOT NULL DEFAULT now()
+);
+CREATE INDEX transactional_email_queue_execute_after_idx
+    ON transactional_email_queue (execute_after);
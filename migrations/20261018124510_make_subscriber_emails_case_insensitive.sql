@@ -0,0 +1,73 @@
+-- Addresses are unique whatever their case, so that concurrent sign-ups for
+-- `Foo@x.com` and `foo@x.com` cannot both create a subscriber.
+--
+-- Existing duplicates are merged into the oldest subscriber with the address: their
+-- memberships, tokens, consent and delivery history move over to it.
+CREATE TEMPORARY TABLE duplicate_subscribers AS
+SELECT d.id AS duplicate_id, k.id AS keeper_id, k.email AS keeper_email, d.email AS duplicate_email
+FROM subscriptions d
+JOIN LATERAL (
+    SELECT id, email FROM subscriptions s
+    WHERE lower(s.email) = lower(d.email)
+    ORDER BY s.subscribed_at, s.id
+    LIMIT 1
+) k ON k.id <> d.id;
+
+-- A membership of the duplicate on a list the keeper is also on only counts if it
+-- went further than the keeper's.
+UPDATE list_memberships k
+SET status = 'confirmed', unsubscribed_at = NULL
+FROM list_memberships d
+JOIN duplicate_subscribers ds ON ds.duplicate_id = d.subscriber_id
+WHERE k.subscriber_id = ds.keeper_id
+    AND k.list_id = d.list_id
+    AND d.status = 'confirmed'
+    AND k.status <> 'confirmed';
+DELETE FROM list_memberships d
+USING duplicate_subscribers ds
+WHERE d.subscriber_id = ds.duplicate_id
+    AND EXISTS (
+        SELECT 1 FROM list_memberships k
+        WHERE k.subscriber_id = ds.keeper_id AND k.list_id = d.list_id
+    );
+UPDATE list_memberships m SET subscriber_id = ds.keeper_id
+FROM duplicate_subscribers ds WHERE m.subscriber_id = ds.duplicate_id;
+
+UPDATE subscription_tokens t SET subscriber_id = ds.keeper_id
+FROM duplicate_subscribers ds WHERE t.subscriber_id = ds.duplicate_id;
+UPDATE data_export_tokens t SET subscriber_id = ds.keeper_id
+FROM duplicate_subscribers ds WHERE t.subscriber_id = ds.duplicate_id;
+UPDATE erasure_tokens t SET subscriber_id = ds.keeper_id
+FROM duplicate_subscribers ds WHERE t.subscriber_id = ds.duplicate_id;
+UPDATE erasures e SET subscriber_id = ds.keeper_id
+FROM duplicate_subscribers ds WHERE e.subscriber_id = ds.duplicate_id;
+UPDATE email_log l SET subscriber_id = ds.keeper_id
+FROM duplicate_subscribers ds WHERE l.subscriber_id = ds.duplicate_id;
+UPDATE transactional_email_queue q SET subscriber_id = ds.keeper_id
+FROM duplicate_subscribers ds WHERE q.subscriber_id = ds.duplicate_id;
+
+ALTER TABLE consent_events DISABLE TRIGGER consent_events_append_only;
+UPDATE consent_events c SET subscriber_id = ds.keeper_id
+FROM duplicate_subscribers ds WHERE c.subscriber_id = ds.duplicate_id;
+ALTER TABLE consent_events ENABLE TRIGGER consent_events_append_only;
+
+-- Queued deliveries are keyed by address.
+DELETE FROM issue_delivery_queue q
+USING duplicate_subscribers ds
+WHERE q.subscriber_email = ds.duplicate_email
+    AND EXISTS (
+        SELECT 1 FROM issue_delivery_queue k
+        WHERE k.newsletter_issue_id = q.newsletter_issue_id
+            AND k.subscriber_email = ds.keeper_email
+    );
+UPDATE issue_delivery_queue q SET subscriber_email = ds.keeper_email
+FROM duplicate_subscribers ds WHERE q.subscriber_email = ds.duplicate_email;
+
+DELETE FROM subscriptions s
+USING duplicate_subscribers ds
+WHERE s.id = ds.duplicate_id;
+DROP TABLE duplicate_subscribers;
+
+-- The index also covers exact duplicates.
+ALTER TABLE subscriptions DROP CONSTRAINT subscriptions_email_key;
+CREATE UNIQUE INDEX subscriptions_lower_email_idx ON subscriptions (lower(email));
@@ -0,0 +1,4 @@
+-- A hash of the method, path and body of the request that claimed the key. A key
+-- reused for a different request is rejected instead of replaying the saved response.
+-- NULL for keys claimed before fingerprints were recorded.
+ALTER TABLE idempotency ADD COLUMN request_fingerprint TEXT;
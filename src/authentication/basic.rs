@@ -0,0 +1,65 @@
+use actix_web::http::header::{self, HeaderMap};
+use anyhow::Context;
+use base64::Engine;
+use secrecy::Secret;
+
+use super::Credentials;
+
+/// Extracts HTTP Basic credentials from the `Authorization` header.
+pub fn basic_authentication(headers: &HeaderMap) -> Result<Credentials, anyhow::Error> {
+    let header_value = headers
+        .get(header::AUTHORIZATION)
+        .context("The 'Authorization' header was missing")?
+        .to_str()
+        .context("The 'Authorization' header was not a valid UTF8 string.")?;
+    let base64encoded_segment = header_value
+        .strip_prefix("Basic ")
+        .context("The authorization scheme was not 'Basic'.")?;
+    let decoded_bytes = base64::engine::general_purpose::STANDARD
+        .decode(base64encoded_segment)
+        .context("Failed to base64-decode 'Basic' credentials.")?;
+    let decoded_credentials = String::from_utf8(decoded_bytes)
+        .context("The decoded credential string is not valid UTF8.")?;
+
+    let (username, password) = decoded_credentials
+        .split_once(':')
+        .ok_or_else(|| anyhow::anyhow!("A password must be provided in 'Basic' auth."))?;
+
+    Ok(Credentials {
+        username: username.to_string(),
+        password: Secret::new(password.to_string()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::http::header::{HeaderMap, HeaderValue, AUTHORIZATION};
+    use claims::assert_err;
+    use secrecy::ExposeSecret;
+
+    use super::basic_authentication;
+
+    fn headers(authorization: &'static str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(AUTHORIZATION, HeaderValue::from_static(authorization));
+        headers
+    }
+
+    #[test]
+    fn basic_credentials_are_decoded() {
+        // "kotleta:pass:word"
+        let credentials = basic_authentication(&headers("Basic a290bGV0YTpwYXNzOndvcmQ=")).unwrap();
+        assert_eq!(credentials.username, "kotleta");
+        assert_eq!(credentials.password.expose_secret(), "pass:word");
+    }
+
+    #[test]
+    fn other_authorization_schemes_are_rejected() {
+        assert_err!(basic_authentication(&headers("Bearer a290bGV0YQ==")));
+    }
+
+    #[test]
+    fn a_missing_header_is_rejected() {
+        assert_err!(basic_authentication(&HeaderMap::new()));
+    }
+}
@@ -0,0 +1,55 @@
+use std::ops::Deref;
+
+use actix_web::{
+    body::MessageBody,
+    dev::{ServiceRequest, ServiceResponse},
+    FromRequest, HttpMessage,
+};
+use actix_web_lab::middleware::Next;
+use uuid::Uuid;
+
+use crate::{
+    session_state::TypedSession,
+    utils::{e500, see_other},
+};
+
+#[derive(Copy, Clone, Debug)]
+pub struct UserId(Uuid);
+
+impl std::fmt::Display for UserId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl Deref for UserId {
+    type Target = Uuid;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+/// Lets the request through only if it belongs to a logged-in admin session and
+/// redirects to the login form otherwise. The id of the authenticated user is made
+/// available to handlers as `web::ReqData<UserId>`.
+pub async fn reject_anonymous_users(
+    mut req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let session = {
+        let (http_request, payload) = req.parts_mut();
+        TypedSession::from_request(http_request, payload).await
+    }?;
+
+    match session.get_user_id().map_err(e500)? {
+        Some(user_id) => {
+            req.extensions_mut().insert(UserId(user_id));
+            next.call(req).await.map(|res| res.map_into_left_body())
+        }
+        None => {
+            let response = see_other("/login");
+            Ok(req.into_response(response).map_into_right_body())
+        }
+    }
+}
@@ -0,0 +1,167 @@
+use anyhow::Context;
+use argon2::{
+    password_hash::SaltString, Algorithm, Argon2, Params, PasswordHash, PasswordHasher,
+    PasswordVerifier, Version,
+};
+use secrecy::{ExposeSecret, Secret};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::telemetry::spawn_blocking_with_tracing;
+
+/// Verified against when the username is unknown, so that a lookup for a missing user
+/// costs as much as one for an existing user with a wrong password.
+const FALLBACK_PASSWORD_HASH: &str = "$argon2id$v=19$m=15000,t=2,p=1$\
+    gZiV/M1gPc22ElAH/Jh1Hw$\
+    CWOrkoo7oJBQ/iyh7uJ0LO2aLEfrHwTWllSAxT0zRno";
+
+#[derive(Debug)]
+pub struct Credentials {
+    pub username: String,
+    pub password: Secret<String>,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum AuthError {
+    #[error("Invalid credentials.")]
+    InvalidCredentials(#[source] anyhow::Error),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+#[tracing::instrument(name = "Validate credentials", skip(credentials, pool))]
+pub async fn validate_credentials(
+    credentials: Credentials,
+    pool: &PgPool,
+) -> Result<Uuid, AuthError> {
+    let mut user_id = None;
+    let mut expected_password_hash = Secret::new(FALLBACK_PASSWORD_HASH.to_string());
+
+    if let Some((stored_user_id, stored_password_hash)) =
+        get_stored_credentials(&credentials.username, pool).await?
+    {
+        user_id = Some(stored_user_id);
+        expected_password_hash = stored_password_hash;
+    }
+
+    spawn_blocking_with_tracing(move || {
+        verify_password_hash(expected_password_hash, credentials.password)
+    })
+    .await
+    .context("Failed to spawn blocking task.")??;
+
+    user_id
+        .ok_or_else(|| anyhow::anyhow!("Unknown username."))
+        .map_err(AuthError::InvalidCredentials)
+}
+
+#[tracing::instrument(
+    name = "Verify password hash",
+    skip(expected_password_hash, password_candidate)
+)]
+fn verify_password_hash(
+    expected_password_hash: Secret<String>,
+    password_candidate: Secret<String>,
+) -> Result<(), AuthError> {
+    let expected_password_hash = PasswordHash::new(expected_password_hash.expose_secret())
+        .context("Failed to parse hash in PHC string format.")?;
+
+    Argon2::default()
+        .verify_password(
+            password_candidate.expose_secret().as_bytes(),
+            &expected_password_hash,
+        )
+        .context("Invalid password.")
+        .map_err(AuthError::InvalidCredentials)
+}
+
+#[tracing::instrument(name = "Get stored credentials", skip(username, pool))]
+async fn get_stored_credentials(
+    username: &str,
+    pool: &PgPool,
+) -> Result<Option<(Uuid, Secret<String>)>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT user_id, password_hash
+        FROM users
+        WHERE username = $1
+        "#,
+        username,
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to perform a query to retrieve stored credentials.")?
+    .map(|row| (row.user_id, Secret::new(row.password_hash)));
+    Ok(row)
+}
+
+/// Hashes `password` with Argon2id, returning the hash in PHC string format.
+pub fn compute_password_hash(password: Secret<String>) -> Result<Secret<String>, anyhow::Error> {
+    let salt = SaltString::generate(&mut rand::thread_rng());
+    let password_hash = Argon2::new(
+        Algorithm::Argon2id,
+        Version::V0x13,
+        Params::new(15000, 2, 1, None).unwrap(),
+    )
+    .hash_password(password.expose_secret().as_bytes(), &salt)?
+    .to_string();
+    Ok(Secret::new(password_hash))
+}
+
+#[tracing::instrument(name = "Create user", skip(password, pool))]
+pub async fn create_user(
+    username: &str,
+    password: Secret<String>,
+    pool: &PgPool,
+) -> Result<Uuid, anyhow::Error> {
+    let password_hash = spawn_blocking_with_tracing(move || compute_password_hash(password))
+        .await
+        .context("Failed to spawn blocking task.")??;
+    let user_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO users (user_id, username, password_hash)
+        VALUES ($1, $2, $3)
+        "#,
+        user_id,
+        username,
+        password_hash.expose_secret()
+    )
+    .execute(pool)
+    .await
+    .context("Failed to store the new user in the database.")?;
+    Ok(user_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use argon2::PasswordHash;
+    use claims::{assert_err, assert_ok};
+    use secrecy::{ExposeSecret, Secret};
+
+    use super::{compute_password_hash, verify_password_hash, FALLBACK_PASSWORD_HASH};
+
+    #[test]
+    fn hashes_are_argon2id_phc_strings() {
+        let hash = compute_password_hash(Secret::new("hunter2".into())).unwrap();
+        let parsed = PasswordHash::new(hash.expose_secret()).unwrap();
+        assert_eq!(parsed.algorithm.as_str(), "argon2id");
+    }
+
+    #[test]
+    fn the_right_password_is_accepted() {
+        let hash = compute_password_hash(Secret::new("hunter2".into())).unwrap();
+        assert_ok!(verify_password_hash(hash, Secret::new("hunter2".into())));
+    }
+
+    #[test]
+    fn a_wrong_password_is_rejected() {
+        let hash = compute_password_hash(Secret::new("hunter2".into())).unwrap();
+        assert_err!(verify_password_hash(hash, Secret::new("hunter3".into())));
+    }
+
+    #[test]
+    fn the_fallback_hash_is_a_valid_phc_string() {
+        assert_ok!(PasswordHash::new(FALLBACK_PASSWORD_HASH));
+    }
+}
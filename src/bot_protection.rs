@@ -0,0 +1,233 @@
+use std::{net::IpAddr, time::Duration};
+
+use anyhow::Context;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD as BASE64, Engine};
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use reqwest::Client;
+use secrecy::{ExposeSecret, Secret};
+use sha2::Sha256;
+
+use crate::routes::SubscriptionFormData;
+
+/// Tells signup forms filled in by people from those submitted by scripts.
+#[derive(Debug)]
+pub struct BotProtection {
+    pub form_tokens: FormTokens,
+    /// Forms submitted sooner than this after their token was issued come from bots.
+    pub min_fill_time: chrono::Duration,
+    /// Older tokens are rejected. Tokens are not single-use: this only bounds how long
+    /// a harvested token can be replayed.
+    pub form_token_ttl: chrono::Duration,
+    /// Whether forms without a token are suspect. Without it, the token is only
+    /// checked when present.
+    pub require_form_token: bool,
+    pub captcha: Option<Box<dyn CaptchaVerifier>>,
+}
+
+/// Why a submission was taken for a bot's.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BotSignal {
+    HoneypotFilled,
+    MissingFormToken,
+    InvalidFormToken,
+    FilledTooFast,
+    CaptchaFailed,
+}
+
+impl BotProtection {
+    /// Runs every check on a signup form, cheapest first, and reports the first one it
+    /// fails. Errors are failures to reach the CAPTCHA provider.
+    pub async fn check(
+        &self,
+        form: &SubscriptionFormData,
+        client_ip: Option<IpAddr>,
+    ) -> Result<Option<BotSignal>, anyhow::Error> {
+        if form.website.as_deref().is_some_and(|v| !v.is_empty()) {
+            return Ok(Some(BotSignal::HoneypotFilled));
+        }
+        match form.form_token.as_deref().filter(|t| !t.is_empty()) {
+            None if self.require_form_token => return Ok(Some(BotSignal::MissingFormToken)),
+            None => {}
+            Some(token) => {
+                let Some(issued_at) = self.form_tokens.issued_at(token) else {
+                    return Ok(Some(BotSignal::InvalidFormToken));
+                };
+                let age = Utc::now() - issued_at;
+                if age > self.form_token_ttl {
+                    return Ok(Some(BotSignal::InvalidFormToken));
+                }
+                if age < self.min_fill_time {
+                    return Ok(Some(BotSignal::FilledTooFast));
+                }
+            }
+        }
+        if let Some(captcha) = &self.captcha {
+            let Some(response) = form.captcha_response.as_deref().filter(|r| !r.is_empty()) else {
+                return Ok(Some(BotSignal::CaptchaFailed));
+            };
+            if !captcha.verify(response, client_ip).await? {
+                return Ok(Some(BotSignal::CaptchaFailed));
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// Issues and checks the tokens embedded in signup forms: the time the form was
+/// served, signed with HMAC-SHA256 so that it cannot be backdated.
+#[derive(Debug)]
+pub struct FormTokens {
+    key: Secret<String>,
+}
+
+impl FormTokens {
+    pub fn new(key: Secret<String>) -> Self {
+        Self { key }
+    }
+
+    pub fn issue(&self, now: DateTime<Utc>) -> String {
+        let issued_at = now.timestamp();
+        let signature = self.mac(issued_at).finalize().into_bytes();
+        format!("{}.{}", issued_at, BASE64.encode(signature))
+    }
+
+    /// When the token was issued, if it is one of ours.
+    pub fn issued_at(&self, token: &str) -> Option<DateTime<Utc>> {
+        let (issued_at, signature) = token.split_once('.')?;
+        let issued_at: i64 = issued_at.parse().ok()?;
+        let signature = BASE64.decode(signature).ok()?;
+        // `verify_slice` compares in constant time.
+        self.mac(issued_at).verify_slice(&signature).ok()?;
+        DateTime::from_timestamp(issued_at, 0)
+    }
+
+    fn mac(&self, issued_at: i64) -> Hmac<Sha256> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(self.key.expose_secret().as_bytes())
+            .expect("HMAC accepts keys of any length.");
+        // Keeps a form token from passing for any other value we sign with the same key.
+        mac.update(b"subscription-form-token:");
+        mac.update(issued_at.to_string().as_bytes());
+        mac
+    }
+}
+
+/// A CAPTCHA provider's server-side check of the token its widget added to the form.
+#[async_trait::async_trait]
+pub trait CaptchaVerifier: std::fmt::Debug + Send + Sync {
+    async fn verify(
+        &self,
+        response: &str,
+        client_ip: Option<IpAddr>,
+    ) -> Result<bool, anyhow::Error>;
+}
+
+/// Verifies tokens against a `siteverify` endpoint, the API shared by hCaptcha and
+/// Cloudflare Turnstile.
+#[derive(Debug)]
+pub struct HttpCaptchaVerifier {
+    http_client: Client,
+    verify_url: String,
+    secret: Secret<String>,
+}
+
+impl HttpCaptchaVerifier {
+    pub fn new(verify_url: String, secret: Secret<String>, timeout: Duration) -> Self {
+        Self {
+            http_client: Client::builder().timeout(timeout).build().unwrap(),
+            verify_url,
+            secret,
+        }
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct SiteVerifyResponse {
+    success: bool,
+    #[serde(rename = "error-codes", default)]
+    error_codes: Vec<String>,
+}
+
+#[async_trait::async_trait]
+impl CaptchaVerifier for HttpCaptchaVerifier {
+    #[tracing::instrument(name = "Verifying a CAPTCHA response", skip(self, response))]
+    async fn verify(
+        &self,
+        response: &str,
+        client_ip: Option<IpAddr>,
+    ) -> Result<bool, anyhow::Error> {
+        let client_ip = client_ip.map(|ip| ip.to_string());
+        let mut form = vec![
+            ("secret", self.secret.expose_secret().as_str()),
+            ("response", response),
+        ];
+        if let Some(client_ip) = &client_ip {
+            form.push(("remoteip", client_ip));
+        }
+        let outcome: SiteVerifyResponse = self
+            .http_client
+            .post(&self.verify_url)
+            .form(&form)
+            .send()
+            .await
+            .context("Failed to reach the CAPTCHA provider.")?
+            .error_for_status()
+            .context("The CAPTCHA provider returned an error.")?
+            .json()
+            .await
+            .context("Failed to parse the answer of the CAPTCHA provider.")?;
+        if !outcome.success {
+            tracing::info!(error_codes = ?outcome.error_codes, "CAPTCHA verification failed.");
+        }
+        Ok(outcome.success)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, SubsecRound, Utc};
+    use claims::{assert_none, assert_some_eq};
+    use secrecy::Secret;
+
+    use super::FormTokens;
+
+    fn form_tokens(key: &str) -> FormTokens {
+        FormTokens::new(Secret::new(key.into()))
+    }
+
+    #[test]
+    fn a_token_gives_back_when_it_was_issued() {
+        let tokens = form_tokens("key");
+        let now = Utc::now() - Duration::minutes(5);
+
+        let token = tokens.issue(now);
+
+        assert_some_eq!(tokens.issued_at(&token), now.trunc_subsecs(0));
+    }
+
+    #[test]
+    fn a_backdated_token_is_rejected() {
+        let tokens = form_tokens("key");
+        let token = tokens.issue(Utc::now());
+        let (_, signature) = token.split_once('.').unwrap();
+
+        let backdated = format!("{}.{}", Utc::now().timestamp() - 3600, signature);
+
+        assert_none!(tokens.issued_at(&backdated));
+    }
+
+    #[test]
+    fn a_token_signed_with_another_key_is_rejected() {
+        let token = form_tokens("another key").issue(Utc::now());
+
+        assert_none!(form_tokens("key").issued_at(&token));
+    }
+
+    #[test]
+    fn garbage_is_rejected() {
+        let tokens = form_tokens("key");
+        for token in ["", ".", "123", "abc.def", "123.!!!"] {
+            assert_none!(tokens.issued_at(token));
+        }
+    }
+}
@@ -1,12 +1,22 @@
 use std::str::FromStr;
 
+use actix_web::http::header::HeaderName;
+use anyhow::Context;
 use secrecy::Secret;
 use serde::{de, Deserialize};
 use serde_aux::field_attributes::deserialize_number_from_string;
 use sqlx::postgres::PgConnectOptions;
-use std::time::Duration;
+use std::{path::PathBuf, time::Duration};
 
-use crate::domain::SubscriberEmail;
+use crate::{
+    bot_protection::{BotProtection, FormTokens, HttpCaptchaVerifier},
+    domain::{SubscriberEmail, SubscriberEmailError},
+    email_client::{
+        EmailClient, EmailProvider, PostmarkProvider, RetryPolicy, SmtpProvider, SmtpTls,
+    },
+    email_templates::EmailTemplates,
+    rate_limit::{RateLimiter, SubscriptionRateLimits},
+};
 
 #[derive(Debug, serde::Deserialize, Clone)]
 pub struct ApplicationSettings {
@@ -14,17 +24,71 @@ pub struct ApplicationSettings {
     #[serde(deserialize_with = "deserialize_number_from_string")]
     pub port: u16,
     pub base_url: String,
+    /// Signs session and flash message cookies. Must be at least 64 bytes long.
+    pub hmac_secret: Secret<String>,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub confirmation_token_ttl_hours: i64,
+    /// How long the link to download a subscriber's data stays valid.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub data_export_link_ttl_hours: i64,
+    /// How long the link to confirm the erasure of a subscriber's data stays valid.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub erasure_link_ttl_hours: i64,
+    /// The privacy policy subscribers currently agree to, recorded with their consent.
+    pub privacy_policy_version: String,
 }
 
 #[derive(Debug, Clone)]
 pub struct DbOptions(pub PgConnectOptions);
 
+impl ApplicationSettings {
+    pub fn confirmation_token_ttl(&self) -> chrono::Duration {
+        chrono::Duration::hours(self.confirmation_token_ttl_hours)
+    }
+
+    pub fn data_export_link_ttl(&self) -> chrono::Duration {
+        chrono::Duration::hours(self.data_export_link_ttl_hours)
+    }
+
+    pub fn erasure_link_ttl(&self) -> chrono::Duration {
+        chrono::Duration::hours(self.erasure_link_ttl_hours)
+    }
+}
+
 #[derive(Debug, serde::Deserialize, Clone)]
 pub struct EmailClientSettings {
-    pub base_url: String,
+    #[serde(default)]
+    pub provider: EmailProviderKind,
     pub sender_email: String,
     pub timeout_milliseconds: u64,
+    /// Retries after a transient failure. `0` disables retries.
+    pub max_retries: u32,
+    pub initial_backoff_milliseconds: u64,
+    pub max_backoff_milliseconds: u64,
+    /// Postmark API root.
+    pub base_url: String,
+    /// Postmark server token.
     pub auth_token: Secret<String>,
+    /// Required when `provider` is `smtp`.
+    pub smtp: Option<SmtpSettings>,
+}
+
+#[derive(Debug, serde::Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum EmailProviderKind {
+    #[default]
+    Postmark,
+    Smtp,
+}
+
+#[derive(Debug, serde::Deserialize, Clone)]
+pub struct SmtpSettings {
+    pub host: String,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub port: u16,
+    pub tls: SmtpTls,
+    pub username: String,
+    pub password: Secret<String>,
 }
 
 #[derive(Debug, serde::Deserialize, Clone)]
@@ -32,6 +96,124 @@ pub struct Settings {
     pub database_url: DbOptions,
     pub application: ApplicationSettings,
     pub email_client: EmailClientSettings,
+    pub email_templates: EmailTemplateSettings,
+    pub postmark_webhook: PostmarkWebhookSettings,
+    pub rate_limit: RateLimitSettings,
+    pub bot_protection: BotProtectionSettings,
+}
+
+/// The HTTP Basic credentials Postmark is configured to send along with webhook calls.
+#[derive(Debug, serde::Deserialize, Clone)]
+pub struct PostmarkWebhookSettings {
+    pub username: String,
+    pub password: Secret<String>,
+}
+
+/// Limits on `POST /subscriptions` and on data export and erasure requests, each a
+/// token bucket.
+#[derive(Debug, serde::Deserialize, Clone)]
+pub struct RateLimitSettings {
+    /// The header the reverse proxy in front of us puts the client's IP address in,
+    /// e.g. `Fly-Client-IP`. Only set it if every request goes through that proxy:
+    /// otherwise clients can pick their own IP address.
+    pub trusted_proxy_header: Option<String>,
+    pub per_ip: TokenBucketSettings,
+    pub per_email: TokenBucketSettings,
+    pub data_requests_per_ip: TokenBucketSettings,
+}
+
+#[derive(Debug, serde::Deserialize, Clone)]
+pub struct TokenBucketSettings {
+    /// Requests allowed in a row.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub burst: u32,
+    /// How long it takes to earn back one request.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub refill_interval_seconds: u64,
+}
+
+impl RateLimitSettings {
+    pub fn subscription_limits(&self) -> Result<SubscriptionRateLimits, anyhow::Error> {
+        let trusted_proxy_header = self
+            .trusted_proxy_header
+            .as_deref()
+            .map(HeaderName::from_str)
+            .transpose()
+            .context("Invalid trusted proxy header name.")?;
+        Ok(SubscriptionRateLimits {
+            per_ip: self.per_ip.limiter(),
+            per_email: self.per_email.limiter(),
+            data_requests_per_ip: self.data_requests_per_ip.limiter(),
+            trusted_proxy_header,
+        })
+    }
+}
+
+impl TokenBucketSettings {
+    pub fn limiter(&self) -> RateLimiter {
+        RateLimiter::new(
+            self.burst,
+            Duration::from_secs(self.refill_interval_seconds),
+        )
+    }
+}
+
+#[derive(Debug, serde::Deserialize, Clone)]
+pub struct BotProtectionSettings {
+    /// Treat signups without a form token as bots. Only turn it on once every signup
+    /// form fetches a token from `GET /subscriptions/form_token`.
+    pub require_form_token: bool,
+    /// People take at least this long to fill in the form.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub min_fill_seconds: i64,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub form_token_ttl_hours: i64,
+    /// No CAPTCHA is asked for if not set.
+    pub captcha: Option<CaptchaSettings>,
+}
+
+/// An hCaptcha or Cloudflare Turnstile account.
+#[derive(Debug, serde::Deserialize, Clone)]
+pub struct CaptchaSettings {
+    /// The provider's `siteverify` endpoint.
+    pub verify_url: String,
+    pub secret: Secret<String>,
+    pub timeout_milliseconds: u64,
+}
+
+impl BotProtectionSettings {
+    /// Form tokens are signed with `hmac_secret`.
+    pub fn bot_protection(&self, hmac_secret: Secret<String>) -> BotProtection {
+        BotProtection {
+            form_tokens: FormTokens::new(hmac_secret),
+            min_fill_time: chrono::Duration::seconds(self.min_fill_seconds),
+            form_token_ttl: chrono::Duration::hours(self.form_token_ttl_hours),
+            require_form_token: self.require_form_token,
+            captcha: self.captcha.clone().map(|captcha| {
+                Box::new(HttpCaptchaVerifier::new(
+                    captcha.verify_url,
+                    captcha.secret,
+                    Duration::from_millis(captcha.timeout_milliseconds),
+                )) as _
+            }),
+        }
+    }
+}
+
+#[derive(Debug, serde::Deserialize, Clone)]
+pub struct EmailTemplateSettings {
+    /// Directory with customised email templates, one sub-directory per locale.
+    /// Templates missing from it, or all of them if it is not set, use the version
+    /// compiled into the binary.
+    pub directory: Option<PathBuf>,
+    /// Used for subscribers whose language we do not have templates for.
+    pub default_locale: String,
+}
+
+impl EmailTemplateSettings {
+    pub fn load(&self) -> Result<EmailTemplates, anyhow::Error> {
+        EmailTemplates::load(self.directory.as_deref(), &self.default_locale)
+    }
 }
 
 impl TryFrom<&str> for DbOptions {
@@ -53,13 +235,51 @@ impl<'de> Deserialize<'de> for DbOptions {
 }
 
 impl EmailClientSettings {
-    pub fn sender(&self) -> Result<SubscriberEmail, String> {
+    pub fn client(self) -> EmailClient {
+        let sender_email = self.sender().expect("Invalid sender email address.");
+        let timeout = self.timeout();
+        let retry_policy = self.retry_policy();
+        let provider: Box<dyn EmailProvider> = match self.provider {
+            EmailProviderKind::Postmark => Box::new(PostmarkProvider::new(
+                self.base_url,
+                timeout,
+                self.auth_token,
+            )),
+            EmailProviderKind::Smtp => {
+                let smtp = self
+                    .smtp
+                    .expect("The `smtp` section is required to use the SMTP email provider.");
+                Box::new(
+                    SmtpProvider::new(
+                        &smtp.host,
+                        smtp.port,
+                        smtp.tls,
+                        smtp.username,
+                        smtp.password,
+                        timeout,
+                    )
+                    .expect("Failed to configure the SMTP transport."),
+                )
+            }
+        };
+        EmailClient::new(sender_email, provider, retry_policy)
+    }
+
+    pub fn sender(&self) -> Result<SubscriberEmail, SubscriberEmailError> {
         SubscriberEmail::parse(self.sender_email.clone())
     }
 
     pub fn timeout(&self) -> Duration {
         Duration::from_millis(self.timeout_milliseconds)
     }
+
+    pub fn retry_policy(&self) -> RetryPolicy {
+        RetryPolicy {
+            max_retries: self.max_retries,
+            initial_backoff: Duration::from_millis(self.initial_backoff_milliseconds),
+            max_backoff: Duration::from_millis(self.max_backoff_milliseconds),
+        }
+    }
 }
 
 pub enum Environment {
@@ -0,0 +1,87 @@
+use actix_web::{http::header, web, HttpRequest};
+use sqlx::{Executor, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::startup::{PrivacyPolicyVersion, SignupProtection};
+
+#[derive(Debug, Clone, Copy)]
+pub enum ConsentEvent {
+    /// The signup form was submitted.
+    Subscribed,
+    /// The confirmation link was followed, completing the double opt-in.
+    Confirmed,
+}
+
+impl ConsentEvent {
+    fn as_str(&self) -> &'static str {
+        match self {
+            ConsentEvent::Subscribed => "subscribed",
+            ConsentEvent::Confirmed => "confirmed",
+        }
+    }
+}
+
+/// Who gave their consent, from where, and under which privacy policy.
+#[derive(Debug)]
+pub struct ConsentContext {
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+    pub source_page: Option<String>,
+    pub policy_version: String,
+}
+
+impl ConsentContext {
+    /// Captures the context of the request consent is given with. The source page is
+    /// the `Referer`, if the browser sent one.
+    pub fn from_request(request: &HttpRequest) -> Self {
+        let header = |name| {
+            request
+                .headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_owned)
+        };
+        let ip_address = match request.app_data::<web::Data<SignupProtection>>() {
+            Some(signup_protection) => signup_protection.rate_limits.client_ip(request),
+            None => request.peer_addr().map(|address| address.ip()),
+        };
+        let policy_version = request
+            .app_data::<web::Data<PrivacyPolicyVersion>>()
+            .expect("The privacy policy version is not registered.");
+        Self {
+            ip_address: ip_address.map(|ip| ip.to_string()),
+            user_agent: header(header::USER_AGENT),
+            source_page: header(header::REFERER),
+            policy_version: policy_version.0.clone(),
+        }
+    }
+}
+
+#[tracing::instrument(name = "Recording a consent event", skip(transaction, context))]
+pub async fn record_consent(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    list_id: Uuid,
+    event: ConsentEvent,
+    context: &ConsentContext,
+) -> Result<(), sqlx::Error> {
+    let query = sqlx::query!(
+        r#"
+        INSERT INTO consent_events (
+            id, subscriber_id, list_id, event_type, ip_address, user_agent, source_page,
+            policy_version
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+        "#,
+        Uuid::new_v4(),
+        subscriber_id,
+        list_id,
+        event.as_str(),
+        context.ip_address,
+        context.user_agent,
+        context.source_page,
+        context.policy_version
+    );
+    transaction.execute(query).await?;
+    Ok(())
+}
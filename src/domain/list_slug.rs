@@ -0,0 +1,83 @@
+const MAX_LENGTH: usize = 64;
+
+/// The identifier of a mailing list in URLs, forms and API requests, e.g. `rust-weekly`.
+#[derive(Debug, Clone)]
+pub struct ListSlug(String);
+
+#[derive(thiserror::Error, Debug, PartialEq, Eq)]
+pub enum ListSlugError {
+    #[error("The list is empty.")]
+    Empty,
+    #[error("The list is longer than {max} characters.")]
+    TooLong { max: usize },
+    #[error("The list contains the forbidden character {0:?}. Use lowercase letters, digits and dashes.")]
+    ForbiddenCharacter(char),
+}
+
+impl ListSlugError {
+    /// Stable, machine-readable identifier for API clients.
+    pub fn code(&self) -> &'static str {
+        match self {
+            ListSlugError::Empty => "empty",
+            ListSlugError::TooLong { .. } => "too_long",
+            ListSlugError::ForbiddenCharacter(_) => "forbidden_character",
+        }
+    }
+}
+
+impl ListSlug {
+    pub fn parse(s: String) -> Result<ListSlug, ListSlugError> {
+        if s.is_empty() {
+            return Err(ListSlugError::Empty);
+        }
+        if s.len() > MAX_LENGTH {
+            return Err(ListSlugError::TooLong { max: MAX_LENGTH });
+        }
+        if let Some(c) = s
+            .chars()
+            .find(|c| !(c.is_ascii_lowercase() || c.is_ascii_digit() || *c == '-'))
+        {
+            return Err(ListSlugError::ForbiddenCharacter(c));
+        }
+        Ok(Self(s))
+    }
+}
+
+impl AsRef<str> for ListSlug {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::{ListSlug, ListSlugError};
+    use claims::{assert_err_eq, assert_ok};
+
+    #[test]
+    fn lowercase_letters_digits_and_dashes_are_valid() {
+        assert_ok!(ListSlug::parse("rust-weekly-2".to_string()));
+    }
+
+    #[test]
+    fn a_64_character_slug_is_valid() {
+        assert_ok!(ListSlug::parse("s".repeat(64)));
+    }
+
+    #[test]
+    fn errors_describe_what_is_wrong_with_the_slug() {
+        assert_err_eq!(ListSlug::parse("".to_string()), ListSlugError::Empty);
+        assert_err_eq!(
+            ListSlug::parse("s".repeat(65)),
+            ListSlugError::TooLong { max: 64 }
+        );
+        assert_err_eq!(
+            ListSlug::parse("Rust".to_string()),
+            ListSlugError::ForbiddenCharacter('R')
+        );
+        assert_err_eq!(
+            ListSlug::parse("rust weekly".to_string()),
+            ListSlugError::ForbiddenCharacter(' ')
+        );
+    }
+}
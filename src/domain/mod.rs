@@ -1,7 +1,9 @@
+pub mod list_slug;
 pub mod new_subscriber;
 pub mod subscriber_email;
 pub mod subscriber_name;
 
-pub use new_subscriber::NewSubscriber;
-pub use subscriber_email::SubscriberEmail;
-pub use subscriber_name::SubscriberName;
+pub use list_slug::{ListSlug, ListSlugError};
+pub use new_subscriber::{NewSubscriber, NewSubscriberError};
+pub use subscriber_email::{SubscriberEmail, SubscriberEmailError};
+pub use subscriber_name::{SubscriberName, SubscriberNameError};
@@ -2,21 +2,45 @@ use actix_web::web::Form;
 
 use crate::routes::SubscriptionFormData;
 
-use super::{subscriber_email::SubscriberEmail, subscriber_name::SubscriberName};
+use super::{
+    list_slug::{ListSlug, ListSlugError},
+    subscriber_email::{SubscriberEmail, SubscriberEmailError},
+    subscriber_name::{SubscriberName, SubscriberNameError},
+};
 
 #[derive(Debug)]
 pub struct NewSubscriber {
     pub email: SubscriberEmail,
     pub name: SubscriberName,
+    /// The list to subscribe to. `None` for the default list.
+    pub list: Option<ListSlug>,
+}
+
+/// Every field is validated, so that all problems with a form are reported at once.
+#[derive(thiserror::Error, Debug)]
+#[error("The subscription form contains invalid fields.")]
+pub struct NewSubscriberError {
+    pub name: Option<SubscriberNameError>,
+    pub email: Option<SubscriberEmailError>,
+    pub list: Option<ListSlugError>,
 }
 
 impl TryFrom<Form<SubscriptionFormData>> for NewSubscriber {
-    type Error = String;
+    type Error = NewSubscriberError;
 
     fn try_from(value: Form<SubscriptionFormData>) -> Result<Self, Self::Error> {
-        let name = SubscriberName::parse(value.0.name)?;
-        let email = SubscriberEmail::parse(value.0.email)?;
-
-        Ok(Self { email, name })
+        let list = value.0.list.filter(|l| !l.is_empty()).map(ListSlug::parse);
+        match (
+            SubscriberName::parse(value.0.name),
+            SubscriberEmail::parse(value.0.email),
+            list.transpose(),
+        ) {
+            (Ok(name), Ok(email), Ok(list)) => Ok(Self { email, name, list }),
+            (name, email, list) => Err(NewSubscriberError {
+                name: name.err(),
+                email: email.err(),
+                list: list.err(),
+            }),
+        }
     }
 }
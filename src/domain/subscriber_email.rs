@@ -1,14 +1,34 @@
 use validator::ValidateEmail;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct SubscriberEmail(String);
 
+#[derive(thiserror::Error, Debug, PartialEq, Eq)]
+pub enum SubscriberEmailError {
+    #[error("The email address is empty.")]
+    Empty,
+    #[error("The email address is not syntactically valid.")]
+    InvalidSyntax,
+}
+
+impl SubscriberEmailError {
+    /// Stable, machine-readable identifier for API clients.
+    pub fn code(&self) -> &'static str {
+        match self {
+            SubscriberEmailError::Empty => "empty",
+            SubscriberEmailError::InvalidSyntax => "invalid_syntax",
+        }
+    }
+}
+
 impl SubscriberEmail {
-    pub fn parse(s: String) -> Result<SubscriberEmail, String> {
-        if ValidateEmail::validate_email(&s) {
+    pub fn parse(s: String) -> Result<SubscriberEmail, SubscriberEmailError> {
+        if s.trim().is_empty() {
+            Err(SubscriberEmailError::Empty)
+        } else if ValidateEmail::validate_email(&s) {
             Ok(Self(s))
         } else {
-            Err(format!("{} is not a valid subscriber email", s))
+            Err(SubscriberEmailError::InvalidSyntax)
         }
     }
 }
@@ -21,8 +41,8 @@ impl AsRef<str> for SubscriberEmail {
 
 #[cfg(test)]
 mod tests {
-    use super::SubscriberEmail;
-    use claims::assert_err;
+    use super::{SubscriberEmail, SubscriberEmailError};
+    use claims::{assert_err, assert_err_eq};
     use fake::faker::internet::en::SafeEmail;
     use fake::Fake;
     use quickcheck::Arbitrary;
@@ -40,6 +60,18 @@ mod tests {
         }
     }
 
+    #[test]
+    fn errors_tell_empty_and_malformed_addresses_apart() {
+        assert_err_eq!(
+            SubscriberEmail::parse("  ".to_string()),
+            SubscriberEmailError::Empty
+        );
+        assert_err_eq!(
+            SubscriberEmail::parse("namedomain.com".to_string()),
+            SubscriberEmailError::InvalidSyntax
+        );
+    }
+
     #[derive(Debug, Clone)]
     struct ValidEmailFixture(String);
 
@@ -1,21 +1,45 @@
 use unicode_segmentation::UnicodeSegmentation;
 
+const MAX_LENGTH: usize = 256;
+const FORBIDDEN_CHARACTERS: [char; 12] =
+    ['/', '(', ')', '"', '<', '>', '\\', '{', '}', ' ', '[', ']'];
+
 #[derive(Debug)]
 pub struct SubscriberName(String);
 
-impl SubscriberName {
-    pub fn parse(s: String) -> Result<SubscriberName, String> {
-        let is_whitespace_or_empty = s.trim().is_empty();
-        let is_too_long = s.graphemes(true).count() > 256;
+#[derive(thiserror::Error, Debug, PartialEq, Eq)]
+pub enum SubscriberNameError {
+    #[error("The name is empty.")]
+    Empty,
+    #[error("The name is longer than {max} characters.")]
+    TooLong { max: usize },
+    #[error("The name contains the forbidden character {0:?}.")]
+    ForbiddenCharacter(char),
+}
 
-        let forbidden_characters = ['/', '(', ')', '"', '<', '>', '\\', '{', '}', ' ', '[', ']'];
-        let contains_forbidden_characters = s.chars().any(|c| forbidden_characters.contains(&c));
+impl SubscriberNameError {
+    /// Stable, machine-readable identifier for API clients.
+    pub fn code(&self) -> &'static str {
+        match self {
+            SubscriberNameError::Empty => "empty",
+            SubscriberNameError::TooLong { .. } => "too_long",
+            SubscriberNameError::ForbiddenCharacter(_) => "forbidden_character",
+        }
+    }
+}
 
-        if is_whitespace_or_empty || contains_forbidden_characters || is_too_long {
-            Err(format!("{} is not a valid subscriber name!", s))
-        } else {
-            Ok(Self(s))
+impl SubscriberName {
+    pub fn parse(s: String) -> Result<SubscriberName, SubscriberNameError> {
+        if s.trim().is_empty() {
+            return Err(SubscriberNameError::Empty);
+        }
+        if s.graphemes(true).count() > MAX_LENGTH {
+            return Err(SubscriberNameError::TooLong { max: MAX_LENGTH });
+        }
+        if let Some(c) = s.chars().find(|c| FORBIDDEN_CHARACTERS.contains(c)) {
+            return Err(SubscriberNameError::ForbiddenCharacter(c));
         }
+        Ok(Self(s))
     }
 }
 
@@ -27,8 +51,8 @@ impl AsRef<str> for SubscriberName {
 
 #[cfg(test)]
 mod tests {
-    use crate::domain::SubscriberName;
-    use claims::{assert_err, assert_ok};
+    use crate::domain::{SubscriberName, SubscriberNameError};
+    use claims::{assert_err, assert_err_eq, assert_ok};
 
     #[test]
     fn valid_name_is_parsed_successfully() {
@@ -67,4 +91,20 @@ mod tests {
             assert_err!(SubscriberName::parse(name));
         }
     }
+
+    #[test]
+    fn errors_describe_what_is_wrong_with_the_name() {
+        assert_err_eq!(
+            SubscriberName::parse("".to_string()),
+            SubscriberNameError::Empty
+        );
+        assert_err_eq!(
+            SubscriberName::parse("n".repeat(257)),
+            SubscriberNameError::TooLong { max: 256 }
+        );
+        assert_err_eq!(
+            SubscriberName::parse("ursula<le>guin".to_string()),
+            SubscriberNameError::ForbiddenCharacter('<')
+        );
+    }
 }
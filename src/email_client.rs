@@ -1,206 +0,0 @@
-use std::time::Duration;
-
-use reqwest::{Client, Url};
-use secrecy::{ExposeSecret, Secret};
-use serde::Serialize;
-
-use crate::domain::SubscriberEmail;
-
-#[derive(Debug)]
-pub struct EmailClient {
-    http_client: Client,
-    base_url: String,
-    sender: SubscriberEmail,
-    auth_token: Secret<String>,
-}
-
-impl EmailClient {
-    pub fn new(
-        base_url: String,
-        sender: SubscriberEmail,
-        timeout: Duration,
-        auth_token: Secret<String>,
-    ) -> Self {
-        Self {
-            http_client: Client::builder().timeout(timeout).build().unwrap(),
-            base_url,
-            sender,
-            auth_token,
-        }
-    }
-
-    pub async fn send_email(
-        &self,
-        recipient: SubscriberEmail,
-        subject: &str,
-        html_content: &str,
-        text_content: &str,
-    ) -> Result<(), reqwest::Error> {
-        let url = Url::parse(&self.base_url).expect("Failed to parse base_url!");
-        let url = url.join("/email").expect("Failed to join with email");
-        let request_body = SendEmailRequest {
-            from: self.sender.as_ref(),
-            to: recipient.as_ref(),
-            subject,
-            html_body: html_content,
-            text_body: text_content,
-        };
-        self.http_client
-            .post(url)
-            .json(&request_body)
-            .header("X-Postmark-Server-Token", self.auth_token.expose_secret())
-            .send()
-            .await?
-            .error_for_status()?;
-        Ok(())
-    }
-}
-
-#[derive(Serialize)]
-#[serde(rename_all = "PascalCase")]
-struct SendEmailRequest<'a> {
-    from: &'a str,
-    to: &'a str,
-    subject: &'a str,
-    html_body: &'a str,
-    text_body: &'a str,
-}
-
-#[cfg(test)]
-mod tests {
-    use std::time::Duration;
-
-    use claims::{assert_err, assert_ok};
-    use fake::{
-        faker::{
-            internet::en::SafeEmail,
-            lorem::zh_tw::{Paragraph, Sentence},
-        },
-        Fake, Faker,
-    };
-    use secrecy::Secret;
-    use wiremock::{
-        matchers::{any, header, header_exists, method, path},
-        Mock, MockServer, ResponseTemplate,
-    };
-
-    use crate::{domain::SubscriberEmail, email_client::EmailClient};
-
-    struct SendEmailBodyMatcher;
-
-    impl wiremock::Match for SendEmailBodyMatcher {
-        fn matches(&self, request: &wiremock::Request) -> bool {
-            let result: Result<serde_json::Value, _> = serde_json::from_slice(&request.body);
-            if let Ok(body) = result {
-                dbg!(&body);
-                body.get("From").is_some()
-                    && body.get("To").is_some()
-                    && body.get("Subject").is_some()
-                    && body.get("HtmlBody").is_some()
-                    && body.get("TextBody").is_some()
-            } else {
-                false
-            }
-        }
-    }
-
-    fn subject() -> String {
-        Sentence(1..2).fake()
-    }
-
-    fn content() -> String {
-        Paragraph(1..10).fake()
-    }
-
-    fn email() -> SubscriberEmail {
-        SubscriberEmail::parse(SafeEmail().fake()).unwrap()
-    }
-
-    fn email_client(base_url: String) -> EmailClient {
-        EmailClient::new(
-            base_url,
-            email(),
-            Duration::from_millis(200),
-            Secret::new(Faker.fake()),
-        )
-    }
-
-    #[tokio_macros::test]
-    async fn send_email_sends_the_expected_request() {
-        let mock_server = MockServer::start().await;
-        let email_client = email_client(mock_server.uri());
-
-        Mock::given(header_exists("X-Postmark-Server-Token"))
-            .and(header("Content-Type", "application/json"))
-            .and(path("/email"))
-            .and(method("POST"))
-            .and(SendEmailBodyMatcher)
-            .respond_with(ResponseTemplate::new(200))
-            .expect(1)
-            .mount(&mock_server)
-            .await;
-
-        let _ = email_client
-            .send_email(email(), &subject(), &content(), &content())
-            .await;
-    }
-
-    #[tokio_macros::test]
-    async fn send_email_succeeds_if_the_server_returns_200() {
-        let mock_server = MockServer::start().await;
-
-        let email_client = email_client(mock_server.uri());
-
-        Mock::given(any())
-            .respond_with(ResponseTemplate::new(200))
-            .expect(1)
-            .mount(&mock_server)
-            .await;
-
-        let outcome = email_client
-            .send_email(email(), &subject(), &content(), &content())
-            .await;
-
-        assert_ok!(outcome);
-    }
-
-    #[tokio_macros::test]
-    async fn send_email_fails_if_the_server_returns_500() {
-        let mock_server = MockServer::start().await;
-
-        let email_client = email_client(mock_server.uri());
-
-        Mock::given(any())
-            .respond_with(ResponseTemplate::new(500))
-            .expect(1)
-            .mount(&mock_server)
-            .await;
-
-        let outcome = email_client
-            .send_email(email(), &subject(), &content(), &content())
-            .await;
-
-        assert_err!(outcome);
-    }
-
-    #[tokio_macros::test]
-    async fn send_email_fails_if_the_server_takes_too_long() {
-        let mock_server = MockServer::start().await;
-
-        let email_client = email_client(mock_server.uri());
-
-        let response = ResponseTemplate::new(200).set_delay(std::time::Duration::from_secs(4));
-
-        Mock::given(any())
-            .respond_with(response)
-            .expect(1)
-            .mount(&mock_server)
-            .await;
-
-        let outcome = email_client
-            .send_email(email(), &subject(), &content(), &content())
-            .await;
-
-        assert_err!(outcome);
-    }
-}
@@ -0,0 +1,267 @@
+use std::collections::BTreeMap;
+
+use crate::domain::SubscriberEmail;
+
+/// A fully addressed email, ready to be handed over to an [`EmailProvider`].
+///
+/// Built through [`EmailMessage::builder`] (or [`EmailClient::message`], which fills in
+/// our sender), so that every address is a parsed [`SubscriberEmail`] and every header
+/// has been checked before it reaches a provider.
+///
+/// [`EmailProvider`]: super::EmailProvider
+/// [`EmailClient::message`]: super::EmailClient::message
+#[derive(Debug, Clone)]
+pub struct EmailMessage {
+    pub(super) from: SubscriberEmail,
+    pub(super) to: SubscriberEmail,
+    pub(super) reply_to: Option<SubscriberEmail>,
+    pub(super) cc: Vec<SubscriberEmail>,
+    pub(super) bcc: Vec<SubscriberEmail>,
+    pub(super) subject: String,
+    pub(super) html_body: String,
+    pub(super) text_body: String,
+    /// Extra message headers, as `(name, value)` pairs.
+    pub(super) headers: Vec<(String, String)>,
+    /// Postmark-only: groups messages in Postmark's statistics and webhooks.
+    pub(super) tag: Option<String>,
+    /// Postmark-only: key/value pairs Postmark hands back in webhooks.
+    pub(super) metadata: BTreeMap<String, String>,
+    /// Postmark-only: the message stream to send through. Postmark picks the server's
+    /// default transactional stream when it is not set.
+    pub(super) message_stream: Option<String>,
+    pub(super) attachments: Vec<Attachment>,
+}
+
+impl EmailMessage {
+    pub fn builder(
+        from: SubscriberEmail,
+        to: SubscriberEmail,
+        subject: impl Into<String>,
+    ) -> EmailMessageBuilder {
+        EmailMessageBuilder {
+            message: EmailMessage {
+                from,
+                to,
+                reply_to: None,
+                cc: Vec::new(),
+                bcc: Vec::new(),
+                subject: subject.into(),
+                html_body: String::new(),
+                text_body: String::new(),
+                headers: Vec::new(),
+                tag: None,
+                metadata: BTreeMap::new(),
+                message_stream: None,
+                attachments: Vec::new(),
+            },
+        }
+    }
+
+    pub fn to(&self) -> &SubscriberEmail {
+        &self.to
+    }
+
+    pub fn subject(&self) -> &str {
+        &self.subject
+    }
+}
+
+/// A file sent along with an email.
+#[derive(Debug, Clone)]
+pub struct Attachment {
+    pub name: String,
+    /// The MIME type of `content`, e.g. `application/json`.
+    pub content_type: String,
+    pub content: Vec<u8>,
+}
+
+#[derive(Debug)]
+pub struct EmailMessageBuilder {
+    message: EmailMessage,
+}
+
+impl EmailMessageBuilder {
+    pub fn html_body(mut self, html_body: impl Into<String>) -> Self {
+        self.message.html_body = html_body.into();
+        self
+    }
+
+    pub fn text_body(mut self, text_body: impl Into<String>) -> Self {
+        self.message.text_body = text_body.into();
+        self
+    }
+
+    pub fn reply_to(mut self, reply_to: SubscriberEmail) -> Self {
+        self.message.reply_to = Some(reply_to);
+        self
+    }
+
+    pub fn cc(mut self, cc: SubscriberEmail) -> Self {
+        self.message.cc.push(cc);
+        self
+    }
+
+    pub fn bcc(mut self, bcc: SubscriberEmail) -> Self {
+        self.message.bcc.push(bcc);
+        self
+    }
+
+    pub fn header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.message.headers.push((name.into(), value.into()));
+        self
+    }
+
+    /// Adds the `List-Unsubscribe` and `List-Unsubscribe-Post` headers, so that mail
+    /// clients can offer a one-click unsubscribe button (RFC 8058) pointing at
+    /// `unsubscribe_link`.
+    pub fn list_unsubscribe(self, unsubscribe_link: &str) -> Self {
+        self.header("List-Unsubscribe", format!("<{}>", unsubscribe_link))
+            .header("List-Unsubscribe-Post", "List-Unsubscribe=One-Click")
+    }
+
+    pub fn tag(mut self, tag: impl Into<String>) -> Self {
+        self.message.tag = Some(tag.into());
+        self
+    }
+
+    pub fn metadata(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.message.metadata.insert(key.into(), value.into());
+        self
+    }
+
+    pub fn message_stream(mut self, message_stream: impl Into<String>) -> Self {
+        self.message.message_stream = Some(message_stream.into());
+        self
+    }
+
+    pub fn attachment(mut self, attachment: Attachment) -> Self {
+        self.message.attachments.push(attachment);
+        self
+    }
+
+    pub fn build(self) -> Result<EmailMessage, EmailMessageError> {
+        let message = self.message;
+        if message.html_body.is_empty() && message.text_body.is_empty() {
+            return Err(EmailMessageError::MissingBody);
+        }
+        if has_line_break(&message.subject) {
+            return Err(EmailMessageError::InvalidSubject);
+        }
+        for (name, value) in &message.headers {
+            if !is_header_name(name) {
+                return Err(EmailMessageError::InvalidHeaderName(name.clone()));
+            }
+            if has_line_break(value) {
+                return Err(EmailMessageError::InvalidHeaderValue(name.clone()));
+            }
+        }
+        for attachment in &message.attachments {
+            if attachment.name.trim().is_empty() || has_line_break(&attachment.name) {
+                return Err(EmailMessageError::InvalidAttachmentName(
+                    attachment.name.clone(),
+                ));
+            }
+        }
+        Ok(message)
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum EmailMessageError {
+    #[error("The email has neither an HTML nor a text body.")]
+    MissingBody,
+    #[error("The subject must fit on a single line.")]
+    InvalidSubject,
+    #[error("{0:?} is not a valid header name.")]
+    InvalidHeaderName(String),
+    #[error("The value of the {0:?} header must fit on a single line.")]
+    InvalidHeaderValue(String),
+    #[error("{0:?} is not a valid attachment name.")]
+    InvalidAttachmentName(String),
+}
+
+/// Header names are printable ASCII, without spaces or colons (RFC 5322 §2.2).
+fn is_header_name(name: &str) -> bool {
+    !name.is_empty() && name.bytes().all(|b| b.is_ascii_graphic() && b != b':')
+}
+
+/// A line break would let a value start a new header.
+fn has_line_break(value: &str) -> bool {
+    value.contains(['\r', '\n'])
+}
+
+#[cfg(test)]
+mod tests {
+    use claims::{assert_err, assert_ok};
+    use fake::{faker::internet::en::SafeEmail, Fake};
+
+    use super::{Attachment, EmailMessage, EmailMessageBuilder, EmailMessageError};
+    use crate::domain::SubscriberEmail;
+
+    fn email() -> SubscriberEmail {
+        SubscriberEmail::parse(SafeEmail().fake()).unwrap()
+    }
+
+    fn builder() -> EmailMessageBuilder {
+        EmailMessage::builder(email(), email(), "Welcome!").text_body("Hi")
+    }
+
+    #[test]
+    fn a_message_with_every_field_set_is_valid() {
+        let message = builder()
+            .html_body("<p>Hi</p>")
+            .reply_to(email())
+            .cc(email())
+            .bcc(email())
+            .header("List-Unsubscribe", "<https://example.com/unsubscribe>")
+            .tag("welcome")
+            .metadata("subscriber_id", "42")
+            .message_stream("outbound")
+            .attachment(Attachment {
+                name: "data.json".into(),
+                content_type: "application/json".into(),
+                content: b"{}".to_vec(),
+            })
+            .build();
+
+        assert_ok!(message);
+    }
+
+    #[test]
+    fn a_message_needs_a_body() {
+        let message = EmailMessage::builder(email(), email(), "Welcome!").build();
+
+        assert!(matches!(
+            assert_err!(message),
+            EmailMessageError::MissingBody
+        ));
+    }
+
+    #[test]
+    fn header_names_must_be_printable_ascii_without_colons() {
+        for name in ["", "X Custom", "X-Custom:", "X-Ünicode"] {
+            assert_err!(builder().header(name, "value").build());
+        }
+    }
+
+    #[test]
+    fn line_breaks_cannot_inject_headers() {
+        assert_err!(builder().header("X-Custom", "a\r\nBcc: x@y.z").build());
+        assert_err!(EmailMessage::builder(email(), email(), "Hi\nBcc: x@y.z")
+            .text_body("Hi")
+            .build());
+    }
+
+    #[test]
+    fn attachments_need_a_name() {
+        let message = builder()
+            .attachment(Attachment {
+                name: " ".into(),
+                content_type: "text/plain".into(),
+                content: Vec::new(),
+            })
+            .build();
+
+        assert_err!(message);
+    }
+}
@@ -0,0 +1,265 @@
+mod message;
+mod postmark;
+mod retry;
+mod smtp;
+
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+pub use message::{Attachment, EmailMessage, EmailMessageBuilder, EmailMessageError};
+pub use postmark::PostmarkProvider;
+pub use retry::RetryPolicy;
+pub use smtp::{SmtpProvider, SmtpTls};
+
+use crate::domain::SubscriberEmail;
+
+/// A service able to deliver an email, e.g. an HTTP API or an SMTP relay.
+#[async_trait::async_trait]
+pub trait EmailProvider: std::fmt::Debug + Send + Sync {
+    async fn send(&self, message: &EmailMessage) -> Result<SentEmail, EmailError>;
+
+    /// Sends several emails, returning one result per message, in the same order.
+    /// Providers without a batch API send them one at a time.
+    async fn send_batch(&self, messages: &[&EmailMessage]) -> Vec<Result<SentEmail, EmailError>> {
+        let mut results = Vec::with_capacity(messages.len());
+        for message in messages {
+            results.push(self.send(message).await);
+        }
+        results
+    }
+}
+
+/// What the provider told us about an email it accepted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SentEmail {
+    /// The provider's identifier for the message, to look it up in their activity logs.
+    /// `None` if the provider accepted the email without saying how it filed it.
+    pub message_id: Option<String>,
+    pub submitted_at: Option<DateTime<Utc>>,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum EmailError {
+    #[error("The email could not be turned into a valid message.")]
+    InvalidMessage(#[source] anyhow::Error),
+    /// The provider could not take the email right now (network failure, timeout,
+    /// rate limiting, server error). Sending it again later may succeed.
+    #[error("The email provider failed to accept the email. It may succeed if retried.")]
+    Transient {
+        #[source]
+        source: anyhow::Error,
+        /// How long the provider asked us to wait before trying again, if it said so.
+        retry_after: Option<Duration>,
+    },
+    /// The provider refused the email. Sending it again will fail the same way.
+    #[error("The email provider rejected the email.")]
+    Permanent {
+        #[source]
+        source: anyhow::Error,
+        /// The provider's own code for the rejection, e.g. Postmark's `ErrorCode` or
+        /// the SMTP reply code.
+        error_code: Option<i64>,
+    },
+}
+
+impl EmailError {
+    pub fn is_transient(&self) -> bool {
+        matches!(self, EmailError::Transient { .. })
+    }
+
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self {
+            EmailError::Transient { retry_after, .. } => *retry_after,
+            _ => None,
+        }
+    }
+
+    pub fn error_code(&self) -> Option<i64> {
+        match self {
+            EmailError::Permanent { error_code, .. } => *error_code,
+            _ => None,
+        }
+    }
+
+    /// A copy of the error, with the same classification, for when a single failure
+    /// (e.g. of a whole batch request) has to be reported for several emails.
+    fn duplicate(&self) -> EmailError {
+        match self {
+            EmailError::InvalidMessage(e) => EmailError::InvalidMessage(anyhow::anyhow!("{:#}", e)),
+            EmailError::Transient {
+                source,
+                retry_after,
+            } => EmailError::Transient {
+                source: anyhow::anyhow!("{:#}", source),
+                retry_after: *retry_after,
+            },
+            EmailError::Permanent { source, error_code } => EmailError::Permanent {
+                source: anyhow::anyhow!("{:#}", source),
+                error_code: *error_code,
+            },
+        }
+    }
+}
+
+/// Sends our emails on behalf of `sender` through the configured provider, retrying
+/// transient failures according to `retry_policy`.
+#[derive(Debug)]
+pub struct EmailClient {
+    sender: SubscriberEmail,
+    provider: Box<dyn EmailProvider>,
+    retry_policy: RetryPolicy,
+}
+
+impl EmailClient {
+    pub fn new(
+        sender: SubscriberEmail,
+        provider: Box<dyn EmailProvider>,
+        retry_policy: RetryPolicy,
+    ) -> Self {
+        Self {
+            sender,
+            provider,
+            retry_policy,
+        }
+    }
+
+    /// Starts a message from our sender to `recipient`.
+    pub fn message(&self, recipient: SubscriberEmail, subject: &str) -> EmailMessageBuilder {
+        EmailMessage::builder(self.sender.clone(), recipient, subject)
+    }
+
+    #[cfg(test)]
+    async fn send_email(
+        &self,
+        recipient: SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+    ) -> Result<SentEmail, EmailError> {
+        let message = self
+            .message(recipient, subject)
+            .html_body(html_content)
+            .text_body(text_content)
+            .build()
+            .map_err(|e| EmailError::InvalidMessage(e.into()))?;
+        self.send_message(&message).await
+    }
+
+    /// Sends a newsletter issue, with a one-click unsubscribe link (see
+    /// [`EmailMessageBuilder::list_unsubscribe`]).
+    #[cfg(test)]
+    async fn send_newsletter(
+        &self,
+        recipient: SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+        unsubscribe_link: &str,
+    ) -> Result<SentEmail, EmailError> {
+        let message = self
+            .message(recipient, subject)
+            .html_body(html_content)
+            .text_body(text_content)
+            .list_unsubscribe(unsubscribe_link)
+            .build()
+            .map_err(|e| EmailError::InvalidMessage(e.into()))?;
+        self.send_message(&message).await
+    }
+
+    /// Sends `message` as is. Emails to subscribers go through
+    /// [`crate::outbound_email`] instead, which honours the suppression list and
+    /// records the attempt.
+    pub(crate) async fn send_message(
+        &self,
+        message: &EmailMessage,
+    ) -> Result<SentEmail, EmailError> {
+        let mut retry = 0;
+        loop {
+            let e = match self.provider.send(message).await {
+                Ok(sent) => return Ok(sent),
+                Err(e) if !e.is_transient() => return Err(e),
+                Err(e) => e,
+            };
+            let Some(delay) = self.delay_before_retry(retry, std::iter::once(&e)) else {
+                return Err(e);
+            };
+            tracing::warn!(
+                error.cause_chain = ?e,
+                "Failed to send an email. Retrying in {:?}.",
+                delay
+            );
+            tokio::time::sleep(delay).await;
+            retry += 1;
+        }
+    }
+
+    /// Sends many emails with as few requests as the provider allows. Returns one result
+    /// per email, in the same order, so that callers can retry only the failures.
+    /// Transient failures are retried here first, following the retry policy.
+    #[cfg(test)]
+    pub(crate) async fn send_batch(
+        &self,
+        messages: &[EmailMessage],
+    ) -> Vec<Result<SentEmail, EmailError>> {
+        let mut results: Vec<Option<Result<SentEmail, EmailError>>> =
+            std::iter::repeat_with(|| None)
+                .take(messages.len())
+                .collect();
+        let mut pending: Vec<usize> = (0..messages.len()).collect();
+        let mut retry = 0;
+        while !pending.is_empty() {
+            let batch: Vec<_> = pending.iter().map(|&i| &messages[i]).collect();
+            let mut failed = Vec::new();
+            for (i, result) in pending
+                .into_iter()
+                .zip(self.provider.send_batch(&batch).await)
+            {
+                match result {
+                    Err(e) if e.is_transient() => failed.push((i, e)),
+                    result => results[i] = Some(result),
+                }
+            }
+            let delay = self.delay_before_retry(retry, failed.iter().map(|(_, e)| e));
+            match delay {
+                Some(delay) if !failed.is_empty() => {
+                    tracing::warn!(
+                        "Failed to send {} emails of a batch. Retrying in {:?}.",
+                        failed.len(),
+                        delay
+                    );
+                    tokio::time::sleep(delay).await;
+                    retry += 1;
+                }
+                _ => {
+                    for (i, e) in failed.drain(..) {
+                        results[i] = Some(Err(e));
+                    }
+                }
+            }
+            pending = failed.into_iter().map(|(i, _)| i).collect();
+        }
+        results
+            .into_iter()
+            .map(|result| result.expect("Every email of the batch has an outcome."))
+            .collect()
+    }
+
+    /// How long to wait before retrying after the given transient errors, or `None` if
+    /// we should give up and let the caller decide when to try again: either retries
+    /// are exhausted or the provider asked us to wait longer than `max_backoff`.
+    fn delay_before_retry<'a>(
+        &self,
+        retry: u32,
+        errors: impl Iterator<Item = &'a EmailError>,
+    ) -> Option<Duration> {
+        if retry >= self.retry_policy.max_retries {
+            return None;
+        }
+        let backoff = self.retry_policy.backoff(retry);
+        let delay = errors
+            .map(|e| e.retry_after().unwrap_or(backoff))
+            .max()
+            .unwrap_or(backoff);
+        (delay <= self.retry_policy.max_backoff).then_some(delay)
+    }
+}
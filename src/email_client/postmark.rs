@@ -0,0 +1,834 @@
+use std::{collections::BTreeMap, time::Duration};
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use reqwest::{header::RETRY_AFTER, Client, Response, StatusCode, Url};
+use secrecy::{ExposeSecret, Secret};
+use serde::Serialize;
+
+use super::{EmailError, EmailMessage, EmailProvider, SentEmail};
+use crate::domain::SubscriberEmail;
+
+/// Most messages Postmark accepts in a single `/email/batch` call.
+const MAX_BATCH_SIZE: usize = 500;
+
+/// Sends emails through Postmark's `/email` and `/email/batch` JSON APIs.
+#[derive(Debug)]
+pub struct PostmarkProvider {
+    http_client: Client,
+    base_url: String,
+    auth_token: Secret<String>,
+}
+
+impl PostmarkProvider {
+    pub fn new(base_url: String, timeout: Duration, auth_token: Secret<String>) -> Self {
+        Self {
+            http_client: Client::builder().timeout(timeout).build().unwrap(),
+            base_url,
+            auth_token,
+        }
+    }
+
+    fn url(&self, path: &str) -> Url {
+        let url = Url::parse(&self.base_url).expect("Failed to parse base_url!");
+        url.join(path)
+            .expect("Failed to join with the endpoint path")
+    }
+
+    async fn post<T: Serialize + ?Sized>(
+        &self,
+        path: &str,
+        body: &T,
+    ) -> Result<Response, EmailError> {
+        let response = self
+            .http_client
+            .post(self.url(path))
+            .json(body)
+            .header("X-Postmark-Server-Token", self.auth_token.expose_secret())
+            .send()
+            .await
+            .map_err(classify_request_error)?;
+        check_status(response).await
+    }
+
+    async fn send_chunk(
+        &self,
+        messages: &[&EmailMessage],
+    ) -> Result<Vec<Result<SentEmail, EmailError>>, EmailError> {
+        let request_body: Vec<_> = messages
+            .iter()
+            .map(|&message| SendEmailRequest::from(message))
+            .collect();
+        let response = self.post("/email/batch", &request_body).await?;
+        // Postmark may have sent some of the emails already: treat an unreadable answer
+        // as permanent rather than risk delivering them twice.
+        let items: Vec<SendEmailResponse> =
+            response.json().await.map_err(|e| EmailError::Permanent {
+                source: e.into(),
+                error_code: None,
+            })?;
+        let mut items = items.into_iter();
+        let results = messages
+            .iter()
+            .map(|_| match items.next() {
+                Some(item) => item.into_result(),
+                None => Err(EmailError::Permanent {
+                    source: anyhow::anyhow!("Postmark did not report an outcome for the email."),
+                    error_code: None,
+                }),
+            })
+            .collect();
+        Ok(results)
+    }
+}
+
+#[async_trait::async_trait]
+impl EmailProvider for PostmarkProvider {
+    async fn send(&self, message: &EmailMessage) -> Result<SentEmail, EmailError> {
+        let response = self
+            .post("/email", &SendEmailRequest::from(message))
+            .await?;
+        // The email is on its way at this point: an answer we cannot read only costs us
+        // its message ID.
+        match response.json::<SendEmailResponse>().await {
+            Ok(body) => body.into_result(),
+            Err(e) => {
+                tracing::warn!(
+                    error.cause_chain = ?e,
+                    "Postmark accepted the email, but its response could not be parsed."
+                );
+                Ok(SentEmail {
+                    message_id: None,
+                    submitted_at: None,
+                })
+            }
+        }
+    }
+
+    async fn send_batch(&self, messages: &[&EmailMessage]) -> Vec<Result<SentEmail, EmailError>> {
+        let mut results = Vec::with_capacity(messages.len());
+        for chunk in messages.chunks(MAX_BATCH_SIZE) {
+            match self.send_chunk(chunk).await {
+                Ok(chunk_results) => results.extend(chunk_results),
+                Err(e) => results.extend(chunk.iter().map(|_| Err(e.duplicate()))),
+            }
+        }
+        results
+    }
+}
+
+/// Failing to reach Postmark or to hear back in time is worth retrying, anything else
+/// (e.g. a body that fails to serialize) will fail again.
+fn classify_request_error(e: reqwest::Error) -> EmailError {
+    if e.is_connect() || e.is_timeout() || e.is_request() {
+        EmailError::Transient {
+            source: e.into(),
+            retry_after: None,
+        }
+    } else {
+        EmailError::Permanent {
+            source: e.into(),
+            error_code: None,
+        }
+    }
+}
+
+/// `429 Too Many Requests` and `5xx` are transient, other `4xx` are permanent.
+async fn check_status(response: Response) -> Result<Response, EmailError> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+    let retry_after = response
+        .headers()
+        .get(RETRY_AFTER)
+        .and_then(|v| v.to_str().ok())
+        .and_then(parse_retry_after);
+    let body = response.text().await.unwrap_or_default();
+    let source = anyhow::anyhow!("Postmark responded with {}: {}", status, body);
+    if status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error() {
+        Err(EmailError::Transient {
+            source,
+            retry_after,
+        })
+    } else {
+        let error_code = serde_json::from_str::<SendEmailResponse>(&body)
+            .ok()
+            .map(|body| body.error_code);
+        Err(EmailError::Permanent { source, error_code })
+    }
+}
+
+/// `Retry-After` holds either a number of seconds or an HTTP date.
+fn parse_retry_after(value: &str) -> Option<Duration> {
+    if let Ok(seconds) = value.trim().parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = chrono::DateTime::parse_from_rfc2822(value.trim()).ok()?;
+    // A date in the past means we can retry right away.
+    Some(
+        (date.with_timezone(&chrono::Utc) - chrono::Utc::now())
+            .to_std()
+            .unwrap_or_default(),
+    )
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct SendEmailRequest<'a> {
+    from: &'a str,
+    to: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cc: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    bcc: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reply_to: Option<&'a str>,
+    subject: &'a str,
+    #[serde(skip_serializing_if = "str::is_empty")]
+    html_body: &'a str,
+    #[serde(skip_serializing_if = "str::is_empty")]
+    text_body: &'a str,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    headers: Vec<Header<'a>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tag: Option<&'a str>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    metadata: &'a BTreeMap<String, String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    message_stream: Option<&'a str>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    attachments: Vec<AttachmentRequest<'a>>,
+}
+
+impl<'a> From<&'a EmailMessage> for SendEmailRequest<'a> {
+    fn from(message: &'a EmailMessage) -> Self {
+        Self {
+            from: message.from.as_ref(),
+            to: message.to.as_ref(),
+            cc: address_list(&message.cc),
+            bcc: address_list(&message.bcc),
+            reply_to: message.reply_to.as_ref().map(AsRef::as_ref),
+            subject: &message.subject,
+            html_body: &message.html_body,
+            text_body: &message.text_body,
+            headers: message
+                .headers
+                .iter()
+                .map(|(name, value)| Header { name, value })
+                .collect(),
+            tag: message.tag.as_deref(),
+            metadata: &message.metadata,
+            message_stream: message.message_stream.as_deref(),
+            attachments: message
+                .attachments
+                .iter()
+                .map(|attachment| AttachmentRequest {
+                    name: &attachment.name,
+                    content: BASE64.encode(&attachment.content),
+                    content_type: &attachment.content_type,
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Postmark takes several recipients as a single comma-separated string.
+fn address_list(addresses: &[SubscriberEmail]) -> Option<String> {
+    if addresses.is_empty() {
+        return None;
+    }
+    let addresses: Vec<&str> = addresses.iter().map(AsRef::as_ref).collect();
+    Some(addresses.join(","))
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct Header<'a> {
+    name: &'a str,
+    value: &'a str,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct AttachmentRequest<'a> {
+    name: &'a str,
+    /// Base64-encoded.
+    content: String,
+    content_type: &'a str,
+}
+
+/// Postmark answers every email, sent alone or in a batch, with this object.
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct SendEmailResponse {
+    error_code: i64,
+    #[serde(default)]
+    message: String,
+    #[serde(rename = "MessageID")]
+    message_id: Option<String>,
+    submitted_at: Option<String>,
+}
+
+impl SendEmailResponse {
+    /// `ErrorCode` is `0` for accepted emails.
+    fn into_result(self) -> Result<SentEmail, EmailError> {
+        if self.error_code != 0 {
+            return Err(EmailError::Permanent {
+                source: anyhow::anyhow!(
+                    "Postmark rejected the email with error code {}: {}",
+                    self.error_code,
+                    self.message
+                ),
+                error_code: Some(self.error_code),
+            });
+        }
+        Ok(SentEmail {
+            message_id: self.message_id,
+            submitted_at: self
+                .submitted_at
+                .and_then(|s| chrono::DateTime::parse_from_rfc3339(&s).ok())
+                .map(|date| date.with_timezone(&chrono::Utc)),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use claims::{assert_err, assert_ok};
+    use fake::{
+        faker::{
+            internet::en::SafeEmail,
+            lorem::zh_tw::{Paragraph, Sentence},
+        },
+        Fake, Faker,
+    };
+    use secrecy::Secret;
+    use wiremock::{
+        matchers::{any, header, header_exists, method, path},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    use super::{parse_retry_after, PostmarkProvider};
+    use crate::{
+        domain::SubscriberEmail,
+        email_client::{Attachment, EmailClient, EmailMessage, RetryPolicy},
+    };
+
+    struct SendEmailBodyMatcher;
+
+    impl wiremock::Match for SendEmailBodyMatcher {
+        fn matches(&self, request: &wiremock::Request) -> bool {
+            let result: Result<serde_json::Value, _> = serde_json::from_slice(&request.body);
+            if let Ok(body) = result {
+                dbg!(&body);
+                body.get("From").is_some()
+                    && body.get("To").is_some()
+                    && body.get("Subject").is_some()
+                    && body.get("HtmlBody").is_some()
+                    && body.get("TextBody").is_some()
+            } else {
+                false
+            }
+        }
+    }
+
+    fn subject() -> String {
+        Sentence(1..2).fake()
+    }
+
+    fn content() -> String {
+        Paragraph(1..10).fake()
+    }
+
+    fn email() -> SubscriberEmail {
+        SubscriberEmail::parse(SafeEmail().fake()).unwrap()
+    }
+
+    fn email_client(base_url: String) -> EmailClient {
+        let provider = PostmarkProvider::new(
+            base_url,
+            Duration::from_millis(200),
+            Secret::new(Faker.fake()),
+        );
+        EmailClient::new(email(), Box::new(provider), RetryPolicy::no_retries())
+    }
+
+    #[tokio_macros::test]
+    async fn send_email_sends_the_expected_request() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(header_exists("X-Postmark-Server-Token"))
+            .and(header("Content-Type", "application/json"))
+            .and(path("/email"))
+            .and(method("POST"))
+            .and(SendEmailBodyMatcher)
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let _ = email_client
+            .send_email(email(), &subject(), &content(), &content())
+            .await;
+    }
+
+    struct ListUnsubscribeHeadersMatcher;
+
+    impl wiremock::Match for ListUnsubscribeHeadersMatcher {
+        fn matches(&self, request: &wiremock::Request) -> bool {
+            let body: serde_json::Value = match serde_json::from_slice(&request.body) {
+                Ok(body) => body,
+                Err(_) => return false,
+            };
+            let expected = serde_json::json!([
+                {"Name": "List-Unsubscribe", "Value": "<https://example.com/unsubscribe>"},
+                {"Name": "List-Unsubscribe-Post", "Value": "List-Unsubscribe=One-Click"},
+            ]);
+            body.get("Headers") == Some(&expected)
+        }
+    }
+
+    #[tokio_macros::test]
+    async fn send_newsletter_attaches_list_unsubscribe_headers() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(path("/email"))
+            .and(method("POST"))
+            .and(SendEmailBodyMatcher)
+            .and(ListUnsubscribeHeadersMatcher)
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let outcome = email_client
+            .send_newsletter(
+                email(),
+                &subject(),
+                &content(),
+                &content(),
+                "https://example.com/unsubscribe",
+            )
+            .await;
+
+        assert_ok!(outcome);
+    }
+
+    #[tokio_macros::test]
+    async fn send_message_serialises_every_field() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+        let (to, reply_to, cc_1, cc_2, bcc) = (email(), email(), email(), email(), email());
+        let message = email_client
+            .message(to.clone(), "Your data")
+            .text_body("Attached.")
+            .reply_to(reply_to.clone())
+            .cc(cc_1.clone())
+            .cc(cc_2.clone())
+            .bcc(bcc.clone())
+            .header("X-Custom", "value")
+            .tag("data-export")
+            .metadata("subscriber_id", "42")
+            .message_stream("outbound")
+            .attachment(Attachment {
+                name: "data.json".into(),
+                content_type: "application/json".into(),
+                content: b"{}".to_vec(),
+            })
+            .build()
+            .unwrap();
+
+        Mock::given(path("/email"))
+            .and(method("POST"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        assert_ok!(email_client.send_message(&message).await);
+
+        let request = &mock_server.received_requests().await.unwrap()[0];
+        let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+        assert_eq!(body["To"], to.as_ref());
+        assert_eq!(body["ReplyTo"], reply_to.as_ref());
+        assert_eq!(body["Cc"], format!("{},{}", cc_1.as_ref(), cc_2.as_ref()));
+        assert_eq!(body["Bcc"], bcc.as_ref());
+        assert_eq!(body["TextBody"], "Attached.");
+        assert!(body.get("HtmlBody").is_none());
+        assert_eq!(
+            body["Headers"],
+            serde_json::json!([{"Name": "X-Custom", "Value": "value"}])
+        );
+        assert_eq!(body["Tag"], "data-export");
+        assert_eq!(body["Metadata"], serde_json::json!({"subscriber_id": "42"}));
+        assert_eq!(body["MessageStream"], "outbound");
+        assert_eq!(
+            body["Attachments"],
+            serde_json::json!([
+                {"Name": "data.json", "Content": "e30=", "ContentType": "application/json"}
+            ])
+        );
+    }
+
+    #[tokio_macros::test]
+    async fn send_email_succeeds_if_the_server_returns_200() {
+        let mock_server = MockServer::start().await;
+
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let outcome = email_client
+            .send_email(email(), &subject(), &content(), &content())
+            .await;
+
+        assert_ok!(outcome);
+    }
+
+    #[tokio_macros::test]
+    async fn send_email_returns_the_message_id_postmark_assigned() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "To": "receiver@example.com",
+                "SubmittedAt": "2014-02-17T07:25:01.4178645-05:00",
+                "MessageID": "0a129aee-e1cd-480d-b08d-4f48548ff48d",
+                "ErrorCode": 0,
+                "Message": "OK"
+            })))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let sent = email_client
+            .send_email(email(), &subject(), &content(), &content())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            sent.message_id.as_deref(),
+            Some("0a129aee-e1cd-480d-b08d-4f48548ff48d")
+        );
+        assert_eq!(
+            sent.submitted_at.unwrap().to_rfc3339(),
+            "2014-02-17T12:25:01.417864500+00:00"
+        );
+    }
+
+    #[tokio_macros::test]
+    async fn send_email_reports_the_error_code_of_a_rejection() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(422).set_body_json(serde_json::json!({
+                "ErrorCode": 300,
+                "Message": "Invalid email request"
+            })))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let outcome = email_client
+            .send_email(email(), &subject(), &content(), &content())
+            .await;
+
+        assert_eq!(assert_err!(outcome).error_code(), Some(300));
+    }
+
+    #[tokio_macros::test]
+    async fn send_email_fails_if_the_server_returns_500() {
+        let mock_server = MockServer::start().await;
+
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(500))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let outcome = email_client
+            .send_email(email(), &subject(), &content(), &content())
+            .await;
+
+        assert_err!(outcome);
+    }
+
+    #[tokio_macros::test]
+    async fn send_email_fails_if_the_server_takes_too_long() {
+        let mock_server = MockServer::start().await;
+
+        let email_client = email_client(mock_server.uri());
+
+        let response = ResponseTemplate::new(200).set_delay(std::time::Duration::from_secs(4));
+
+        Mock::given(any())
+            .respond_with(response)
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let outcome = email_client
+            .send_email(email(), &subject(), &content(), &content())
+            .await;
+
+        assert_err!(outcome);
+    }
+
+    fn retrying_email_client(base_url: String) -> EmailClient {
+        let provider = PostmarkProvider::new(
+            base_url,
+            Duration::from_millis(200),
+            Secret::new(Faker.fake()),
+        );
+        let retry_policy = RetryPolicy {
+            max_retries: 2,
+            initial_backoff: Duration::from_millis(10),
+            max_backoff: Duration::from_secs(2),
+        };
+        EmailClient::new(email(), Box::new(provider), retry_policy)
+    }
+
+    #[tokio_macros::test]
+    async fn send_email_retries_server_errors_until_it_succeeds() {
+        let mock_server = MockServer::start().await;
+        let email_client = retrying_email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(503))
+            .up_to_n_times(2)
+            .expect(2)
+            .mount(&mock_server)
+            .await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let outcome = email_client
+            .send_email(email(), &subject(), &content(), &content())
+            .await;
+
+        assert_ok!(outcome);
+    }
+
+    #[tokio_macros::test]
+    async fn send_email_gives_up_after_max_retries_with_a_transient_error() {
+        let mock_server = MockServer::start().await;
+        let email_client = retrying_email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(500))
+            .expect(3)
+            .mount(&mock_server)
+            .await;
+
+        let outcome = email_client
+            .send_email(email(), &subject(), &content(), &content())
+            .await;
+
+        assert!(assert_err!(outcome).is_transient());
+    }
+
+    #[tokio_macros::test]
+    async fn send_email_does_not_retry_client_errors() {
+        let mock_server = MockServer::start().await;
+        let email_client = retrying_email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(422))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let outcome = email_client
+            .send_email(email(), &subject(), &content(), &content())
+            .await;
+
+        assert!(!assert_err!(outcome).is_transient());
+    }
+
+    #[tokio_macros::test]
+    async fn send_email_waits_as_long_as_retry_after_asks() {
+        let mock_server = MockServer::start().await;
+        let email_client = retrying_email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "1"))
+            .up_to_n_times(1)
+            .mount(&mock_server)
+            .await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&mock_server)
+            .await;
+
+        let start = std::time::Instant::now();
+        let outcome = email_client
+            .send_email(email(), &subject(), &content(), &content())
+            .await;
+
+        assert_ok!(outcome);
+        assert!(start.elapsed() >= Duration::from_secs(1));
+    }
+
+    #[tokio_macros::test]
+    async fn send_email_hands_long_retry_after_back_to_the_caller() {
+        let mock_server = MockServer::start().await;
+        let email_client = retrying_email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "3600"))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let outcome = email_client
+            .send_email(email(), &subject(), &content(), &content())
+            .await;
+
+        let e = assert_err!(outcome);
+        assert!(e.is_transient());
+        assert_eq!(e.retry_after(), Some(Duration::from_secs(3600)));
+    }
+
+    #[test]
+    fn retry_after_accepts_seconds_and_http_dates() {
+        assert_eq!(parse_retry_after("120"), Some(Duration::from_secs(120)));
+        assert_eq!(
+            parse_retry_after("Wed, 21 Oct 2015 07:28:00 GMT"),
+            Some(Duration::ZERO)
+        );
+        assert_eq!(parse_retry_after("soon"), None);
+    }
+
+    /// Answers `/email/batch` calls the way Postmark does when it accepts every message.
+    struct AcceptEveryMessage;
+
+    impl wiremock::Respond for AcceptEveryMessage {
+        fn respond(&self, request: &wiremock::Request) -> ResponseTemplate {
+            let messages: Vec<serde_json::Value> = serde_json::from_slice(&request.body).unwrap();
+            let results: Vec<_> = messages
+                .iter()
+                .map(|m| serde_json::json!({"ErrorCode": 0, "Message": "OK", "To": m["To"]}))
+                .collect();
+            ResponseTemplate::new(200).set_body_json(results)
+        }
+    }
+
+    fn newsletter_issue(email_client: &EmailClient, recipient: SubscriberEmail) -> EmailMessage {
+        email_client
+            .message(recipient, "Issue #1")
+            .html_body("<p>Hi</p>")
+            .text_body("Hi")
+            .build()
+            .unwrap()
+    }
+
+    #[tokio_macros::test]
+    async fn send_batch_splits_messages_into_chunks_of_500() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+        let recipients: Vec<_> = (0..501).map(|_| email()).collect();
+        let (subject, content) = (subject(), content());
+        let emails: Vec<_> = recipients
+            .into_iter()
+            .map(|recipient| {
+                email_client
+                    .message(recipient, &subject)
+                    .html_body(&content)
+                    .text_body(&content)
+                    .build()
+                    .unwrap()
+            })
+            .collect();
+
+        Mock::given(path("/email/batch"))
+            .and(method("POST"))
+            .respond_with(AcceptEveryMessage)
+            .expect(2)
+            .mount(&mock_server)
+            .await;
+
+        let outcomes = email_client.send_batch(&emails).await;
+
+        assert_eq!(outcomes.len(), 501);
+        assert!(outcomes.iter().all(|outcome| outcome.is_ok()));
+        let requests = mock_server.received_requests().await.unwrap();
+        let chunk_sizes: Vec<usize> = requests
+            .iter()
+            .map(|r| {
+                serde_json::from_slice::<Vec<serde_json::Value>>(&r.body)
+                    .unwrap()
+                    .len()
+            })
+            .collect();
+        assert_eq!(chunk_sizes, vec![500, 1]);
+    }
+
+    #[tokio_macros::test]
+    async fn send_batch_reports_the_outcome_of_each_recipient() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+        let recipients = [email(), email()];
+        let emails: Vec<_> = recipients
+            .into_iter()
+            .map(|recipient| newsletter_issue(&email_client, recipient))
+            .collect();
+
+        Mock::given(path("/email/batch"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!([
+                {"ErrorCode": 0, "Message": "OK"},
+                {"ErrorCode": 406, "Message": "You tried to send to a recipient that has been marked as inactive."},
+            ])))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let outcomes = email_client.send_batch(&emails).await;
+
+        assert_ok!(&outcomes[0]);
+        let e = assert_err!(&outcomes[1]);
+        assert!(!e.is_transient());
+        assert_eq!(e.error_code(), Some(406));
+    }
+
+    #[tokio_macros::test]
+    async fn send_batch_retries_a_failed_batch_request() {
+        let mock_server = MockServer::start().await;
+        let email_client = retrying_email_client(mock_server.uri());
+        let recipients = [email(), email()];
+        let emails: Vec<_> = recipients
+            .into_iter()
+            .map(|recipient| newsletter_issue(&email_client, recipient))
+            .collect();
+
+        Mock::given(path("/email/batch"))
+            .respond_with(ResponseTemplate::new(503))
+            .up_to_n_times(1)
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        Mock::given(path("/email/batch"))
+            .respond_with(AcceptEveryMessage)
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let outcomes = email_client.send_batch(&emails).await;
+
+        assert!(outcomes.iter().all(|outcome| outcome.is_ok()));
+    }
+}
@@ -0,0 +1,68 @@
+use std::time::Duration;
+
+use rand::Rng;
+
+/// How many times, and how patiently, `EmailClient` retries transient failures.
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    /// Attempts made after the first one. `0` disables retries.
+    pub max_retries: u32,
+    pub initial_backoff: Duration,
+    /// Upper bound for any single wait, including one requested through `Retry-After`.
+    pub max_backoff: Duration,
+}
+
+impl RetryPolicy {
+    pub fn no_retries() -> Self {
+        Self {
+            max_retries: 0,
+            initial_backoff: Duration::ZERO,
+            max_backoff: Duration::ZERO,
+        }
+    }
+
+    /// Exponential backoff with jitter: the n-th retry (starting from 0) waits a random
+    /// duration between half and all of `initial_backoff * 2^n`, capped at `max_backoff`.
+    /// Jitter keeps clients that failed together from retrying in lockstep.
+    pub fn backoff(&self, retry: u32) -> Duration {
+        let ceiling = self
+            .initial_backoff
+            .saturating_mul(2_u32.saturating_pow(retry))
+            .min(self.max_backoff);
+        let floor = ceiling / 2;
+        floor + (ceiling - floor).mul_f64(rand::thread_rng().gen::<f64>())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::RetryPolicy;
+
+    fn policy() -> RetryPolicy {
+        RetryPolicy {
+            max_retries: 10,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(2),
+        }
+    }
+
+    #[test]
+    fn backoff_grows_exponentially_within_jitter_bounds() {
+        let policy = policy();
+        for (retry, ceiling) in [(0, 100), (1, 200), (2, 400), (3, 800)] {
+            let backoff = policy.backoff(retry);
+            assert!(backoff >= Duration::from_millis(ceiling / 2));
+            assert!(backoff <= Duration::from_millis(ceiling));
+        }
+    }
+
+    #[test]
+    fn backoff_never_exceeds_the_maximum() {
+        let policy = policy();
+        for retry in [5, 10, 31, u32::MAX] {
+            assert!(policy.backoff(retry) <= policy.max_backoff);
+        }
+    }
+}
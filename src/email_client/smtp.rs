@@ -0,0 +1,289 @@
+use std::time::Duration;
+
+use anyhow::Context;
+use chrono::Utc;
+use lettre::{
+    message::{
+        header::{ContentType, HeaderName, HeaderValue},
+        Attachment, Mailbox, MultiPart,
+    },
+    transport::smtp::authentication::Credentials,
+    AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor,
+};
+use secrecy::{ExposeSecret, Secret};
+
+use super::{EmailError, EmailMessage, EmailProvider, SentEmail};
+use crate::domain::SubscriberEmail;
+
+/// How the connection to the SMTP server is secured.
+#[derive(serde::Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SmtpTls {
+    /// Upgrade the connection with `STARTTLS` before authenticating. Refuses to send
+    /// if the server does not offer it.
+    StartTls,
+    /// Plain-text connection. Only meant for local SMTP stand-ins during development.
+    None,
+}
+
+/// Sends emails through an SMTP relay, authenticating with `AUTH`. The Postmark-only
+/// parts of a message (tag, metadata and message stream) are left out.
+#[derive(Debug)]
+pub struct SmtpProvider {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+}
+
+impl SmtpProvider {
+    pub fn new(
+        host: &str,
+        port: u16,
+        tls: SmtpTls,
+        username: String,
+        password: Secret<String>,
+        timeout: Duration,
+    ) -> Result<Self, lettre::transport::smtp::Error> {
+        let builder = match tls {
+            SmtpTls::StartTls => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(host)?,
+            SmtpTls::None => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(host),
+        };
+        let transport = builder
+            .port(port)
+            .credentials(Credentials::new(
+                username,
+                password.expose_secret().to_owned(),
+            ))
+            .timeout(Some(timeout))
+            .build();
+        Ok(Self { transport })
+    }
+}
+
+#[async_trait::async_trait]
+impl EmailProvider for SmtpProvider {
+    async fn send(&self, message: &EmailMessage) -> Result<SentEmail, EmailError> {
+        let email = build_message(message).map_err(EmailError::InvalidMessage)?;
+        // SMTP servers do not hand back an identifier of their own: the `Message-ID`
+        // header lettre generated is how the message can be traced.
+        let message_id = email
+            .headers()
+            .get_raw("Message-ID")
+            .map(|id| id.trim_matches(['<', '>']).to_owned());
+        self.transport.send(email).await.map_err(classify_error)?;
+        Ok(SentEmail {
+            message_id,
+            submitted_at: Some(Utc::now()),
+        })
+    }
+}
+
+/// Only `5xx` replies are permanent rejections. Connection problems, timeouts and `4xx`
+/// replies (e.g. greylisting, mailbox temporarily unavailable) are worth retrying.
+fn classify_error(e: lettre::transport::smtp::Error) -> EmailError {
+    if e.is_permanent() {
+        let error_code = e.status().and_then(|code| code.to_string().parse().ok());
+        EmailError::Permanent {
+            source: e.into(),
+            error_code,
+        }
+    } else {
+        EmailError::Transient {
+            source: e.into(),
+            retry_after: None,
+        }
+    }
+}
+
+fn build_message(message: &EmailMessage) -> Result<Message, anyhow::Error> {
+    let mut builder = Message::builder()
+        .from(mailbox(&message.from).context("Invalid sender.")?)
+        .to(mailbox(&message.to).context("Invalid recipient.")?)
+        .subject(&message.subject);
+    if let Some(reply_to) = &message.reply_to {
+        builder = builder.reply_to(mailbox(reply_to).context("Invalid reply-to address.")?);
+    }
+    for cc in &message.cc {
+        builder = builder.cc(mailbox(cc).context("Invalid cc address.")?);
+    }
+    // lettre leaves `Bcc` out of the headers and only uses it for the envelope.
+    for bcc in &message.bcc {
+        builder = builder.bcc(mailbox(bcc).context("Invalid bcc address.")?);
+    }
+    for (name, value) in &message.headers {
+        let name = HeaderName::new_from_ascii(name.to_owned())
+            .with_context(|| format!("Invalid header name: {}", name))?;
+        builder = builder.raw_header(HeaderValue::new(name, value.to_owned()));
+    }
+    let body = MultiPart::alternative_plain_html(
+        message.text_body.to_owned(),
+        message.html_body.to_owned(),
+    );
+    let body = if message.attachments.is_empty() {
+        body
+    } else {
+        let mut mixed = MultiPart::mixed().multipart(body);
+        for attachment in &message.attachments {
+            let content_type = ContentType::parse(&attachment.content_type)
+                .with_context(|| format!("Invalid content type: {}", attachment.content_type))?;
+            mixed = mixed.singlepart(
+                Attachment::new(attachment.name.clone())
+                    .body(attachment.content.clone(), content_type),
+            );
+        }
+        mixed
+    };
+    builder
+        .multipart(body)
+        .context("Failed to assemble the message body.")
+}
+
+fn mailbox(address: &SubscriberEmail) -> Result<Mailbox, lettre::address::AddressError> {
+    address.as_ref().parse()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use claims::{assert_err, assert_ok};
+    use fake::{faker::internet::en::SafeEmail, Fake};
+    use secrecy::Secret;
+    use tokio::{
+        io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+        net::TcpListener,
+        task::JoinHandle,
+    };
+
+    use super::{SmtpProvider, SmtpTls};
+    use crate::{
+        domain::SubscriberEmail,
+        email_client::{Attachment, EmailClient, RetryPolicy},
+    };
+
+    /// A bare-bones SMTP server accepting a single message. It resolves to every line
+    /// the client sent, so tests can inspect the whole session.
+    async fn spawn_smtp_stand_in(auth_reply: &'static str) -> (u16, JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let handle = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let (reader, mut writer) = stream.into_split();
+            let mut lines = BufReader::new(reader).lines();
+            let mut transcript = Vec::new();
+            writer.write_all(b"220 localhost ESMTP\r\n").await.unwrap();
+            let mut in_data = false;
+            while let Some(line) = lines.next_line().await.unwrap() {
+                transcript.push(line.clone());
+                let reply: &[u8] = if in_data {
+                    if line != "." {
+                        continue;
+                    }
+                    writer.write_all(b"250 OK: queued\r\n").await.unwrap();
+                    break;
+                } else if line.starts_with("EHLO") {
+                    b"250-localhost\r\n250-AUTH PLAIN LOGIN\r\n250 8BITMIME\r\n"
+                } else if line.starts_with("AUTH") {
+                    auth_reply.as_bytes()
+                } else if line == "DATA" {
+                    in_data = true;
+                    b"354 End data with <CR><LF>.<CR><LF>\r\n"
+                } else {
+                    b"250 OK\r\n"
+                };
+                writer.write_all(reply).await.unwrap();
+            }
+            transcript
+        });
+        (port, handle)
+    }
+
+    fn email() -> SubscriberEmail {
+        SubscriberEmail::parse(SafeEmail().fake()).unwrap()
+    }
+
+    fn email_client(port: u16) -> EmailClient {
+        let provider = SmtpProvider::new(
+            "127.0.0.1",
+            port,
+            SmtpTls::None,
+            "username".into(),
+            Secret::new("password".into()),
+            Duration::from_secs(2),
+        )
+        .unwrap();
+        EmailClient::new(email(), Box::new(provider), RetryPolicy::no_retries())
+    }
+
+    #[tokio_macros::test]
+    async fn send_newsletter_authenticates_and_delivers_the_message() {
+        let (port, server) = spawn_smtp_stand_in("235 2.7.0 Authentication successful\r\n").await;
+        let email_client = email_client(port);
+
+        let outcome = email_client
+            .send_newsletter(
+                email(),
+                "Issue #1",
+                "<p>Hello!</p>",
+                "Hello!",
+                "https://example.com/unsubscribe",
+            )
+            .await;
+
+        assert_ok!(outcome);
+        let transcript = server.await.unwrap();
+        assert!(transcript.iter().any(|l| l.starts_with("AUTH PLAIN")));
+        assert!(transcript.contains(&"Subject: Issue #1".to_string()));
+        assert!(
+            transcript.contains(&"List-Unsubscribe: <https://example.com/unsubscribe>".to_string())
+        );
+        assert!(
+            transcript.contains(&"List-Unsubscribe-Post: List-Unsubscribe=One-Click".to_string())
+        );
+    }
+
+    #[tokio_macros::test]
+    async fn send_message_delivers_every_recipient_and_attachment() {
+        let (port, server) = spawn_smtp_stand_in("235 2.7.0 Authentication successful\r\n").await;
+        let email_client = email_client(port);
+        let (cc, bcc, reply_to) = (email(), email(), email());
+        let message = email_client
+            .message(email(), "Your data")
+            .text_body("Attached.")
+            .reply_to(reply_to.clone())
+            .cc(cc.clone())
+            .bcc(bcc.clone())
+            .tag("data-export")
+            .attachment(Attachment {
+                name: "data.json".into(),
+                content_type: "application/json".into(),
+                content: b"{}".to_vec(),
+            })
+            .build()
+            .unwrap();
+
+        let outcome = email_client.send_message(&message).await;
+
+        assert_ok!(outcome);
+        let transcript = server.await.unwrap();
+        let rcpt_to = |address: &SubscriberEmail| format!("RCPT TO:<{}>", address.as_ref());
+        assert!(transcript.contains(&rcpt_to(&cc)));
+        assert!(transcript.contains(&rcpt_to(&bcc)));
+        assert!(transcript.contains(&format!("Cc: {}", cc.as_ref())));
+        assert!(transcript.contains(&format!("Reply-To: {}", reply_to.as_ref())));
+        assert!(!transcript.iter().any(|l| l.starts_with("Bcc:")));
+        assert!(transcript
+            .iter()
+            .any(|l| l.contains("attachment") && l.contains("data.json")));
+    }
+
+    #[tokio_macros::test]
+    async fn send_email_fails_if_authentication_is_rejected() {
+        let (port, _server) = spawn_smtp_stand_in("535 5.7.8 Authentication failed\r\n").await;
+        let email_client = email_client(port);
+
+        let outcome = email_client
+            .send_email(email(), "Welcome!", "<p>Hi</p>", "Hi")
+            .await;
+
+        assert_err!(outcome);
+    }
+}
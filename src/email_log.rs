@@ -0,0 +1,128 @@
+use std::error::Error;
+
+use sqlx::{Executor, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::email_client::{EmailError, SentEmail};
+
+/// The emails we send to subscribers, as stored in `email_log.email_type`.
+#[derive(Debug, Clone, Copy)]
+pub enum EmailType {
+    Confirmation,
+    NewsletterIssue(Uuid),
+    DataExport,
+    ErasureRequest,
+}
+
+impl EmailType {
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            EmailType::Confirmation => "confirmation",
+            EmailType::NewsletterIssue(_) => "newsletter_issue",
+            EmailType::DataExport => "data_export",
+            EmailType::ErasureRequest => "erasure_request",
+        }
+    }
+
+    /// The types of email that are not part of a newsletter issue, from their name.
+    pub(crate) fn parse_transactional(email_type: &str) -> Option<EmailType> {
+        match email_type {
+            "confirmation" => Some(EmailType::Confirmation),
+            "data_export" => Some(EmailType::DataExport),
+            "erasure_request" => Some(EmailType::ErasureRequest),
+            _ => None,
+        }
+    }
+
+    fn newsletter_issue_id(&self) -> Option<Uuid> {
+        match self {
+            EmailType::Confirmation | EmailType::DataExport | EmailType::ErasureRequest => None,
+            EmailType::NewsletterIssue(id) => Some(*id),
+        }
+    }
+}
+
+/// Records an attempt at sending an email to a subscriber, along with what the provider
+/// answered, so that support can tell whether (and when) an email went out.
+#[tracing::instrument(name = "Recording an outbound email", skip(transaction, outcome))]
+pub async fn record_email(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    email_type: EmailType,
+    outcome: Result<&SentEmail, &EmailError>,
+) -> Result<(), sqlx::Error> {
+    let (status, sent, error) = match outcome {
+        Ok(sent) => ("sent", Some(sent), None),
+        Err(e) if e.is_transient() => ("deferred", None, Some(e)),
+        Err(e) => ("failed", None, Some(e)),
+    };
+    let query = sqlx::query!(
+        r#"
+        INSERT INTO email_log (
+            id,
+            subscriber_id,
+            email_type,
+            newsletter_issue_id,
+            outcome,
+            provider_message_id,
+            submitted_at,
+            error_code,
+            error_message
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+        "#,
+        Uuid::new_v4(),
+        subscriber_id,
+        email_type.as_str(),
+        email_type.newsletter_issue_id(),
+        status,
+        sent.and_then(|sent| sent.message_id.as_deref()),
+        sent.and_then(|sent| sent.submitted_at),
+        error.and_then(EmailError::error_code),
+        error.map(error_message),
+    );
+    transaction.execute(query).await?;
+    Ok(())
+}
+
+/// Records an email we did not send because its recipient is on the suppression list.
+#[tracing::instrument(name = "Recording a suppressed email", skip(transaction))]
+pub async fn record_suppressed_email(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    email_type: EmailType,
+    reason: &str,
+) -> Result<(), sqlx::Error> {
+    let query = sqlx::query!(
+        r#"
+        INSERT INTO email_log (
+            id,
+            subscriber_id,
+            email_type,
+            newsletter_issue_id,
+            outcome,
+            error_message
+        )
+        VALUES ($1, $2, $3, $4, 'suppressed', $5)
+        "#,
+        Uuid::new_v4(),
+        subscriber_id,
+        email_type.as_str(),
+        email_type.newsletter_issue_id(),
+        reason,
+    );
+    transaction.execute(query).await?;
+    Ok(())
+}
+
+/// The error and its causes, on one line.
+fn error_message(e: &EmailError) -> String {
+    let mut message = e.to_string();
+    let mut source = e.source();
+    while let Some(cause) = source {
+        message.push_str(": ");
+        message.push_str(&cause.to_string());
+        source = cause.source();
+    }
+    message
+}
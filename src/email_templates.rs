@@ -0,0 +1,459 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Write,
+    path::Path,
+};
+
+use anyhow::Context;
+use minijinja::{
+    context, escape_formatter, AutoEscape, Environment, Output, State, Template, UndefinedBehavior,
+    Value,
+};
+
+/// The templates a locale can provide. The extension picks the escaping: `.html`
+/// templates escape their variables.
+const TEMPLATE_NAMES: [&str; 11] = [
+    "welcome.subject.txt",
+    "welcome.html",
+    "welcome.txt",
+    "newsletter.html",
+    "newsletter.txt",
+    "data_export.subject.txt",
+    "data_export.html",
+    "data_export.txt",
+    "erasure.subject.txt",
+    "erasure.html",
+    "erasure.txt",
+];
+
+macro_rules! embedded_templates {
+    ($($path:literal),* $(,)?) => {
+        &[$(($path, include_str!(concat!("../templates/email/", $path)))),*]
+    };
+}
+
+/// Templates compiled into the binary, keyed by `<locale>/<template name>`.
+const EMBEDDED_TEMPLATES: &[(&str, &str)] = embedded_templates!(
+    "en/welcome.subject.txt",
+    "en/welcome.html",
+    "en/welcome.txt",
+    "en/newsletter.html",
+    "en/newsletter.txt",
+    "en/data_export.subject.txt",
+    "en/data_export.html",
+    "en/data_export.txt",
+    "en/erasure.subject.txt",
+    "en/erasure.html",
+    "en/erasure.txt",
+    "fr/welcome.subject.txt",
+    "fr/welcome.html",
+    "fr/welcome.txt",
+    "fr/newsletter.html",
+    "fr/newsletter.txt",
+    "fr/data_export.subject.txt",
+    "fr/data_export.html",
+    "fr/data_export.txt",
+    "fr/erasure.subject.txt",
+    "fr/erasure.html",
+    "fr/erasure.txt",
+    "de/welcome.subject.txt",
+    "de/welcome.html",
+    "de/welcome.txt",
+    "de/newsletter.html",
+    "de/newsletter.txt",
+    "de/data_export.subject.txt",
+    "de/data_export.html",
+    "de/data_export.txt",
+    "de/erasure.subject.txt",
+    "de/erasure.html",
+    "de/erasure.txt",
+);
+
+#[derive(Debug)]
+pub struct RenderedEmail {
+    pub subject: String,
+    pub html_body: String,
+    pub text_body: String,
+}
+
+#[derive(Debug)]
+pub struct EmailTemplates {
+    env: Environment<'static>,
+    locales: BTreeSet<String>,
+    default_locale: String,
+}
+
+impl EmailTemplates {
+    /// Loads the templates compiled into the binary, then the ones in `directory`,
+    /// which holds one sub-directory per locale (e.g. `fr/welcome.html`). A file in
+    /// `directory` replaces the embedded template with the same name, and new
+    /// sub-directories add locales.
+    ///
+    /// `default_locale` must provide every template: other locales fall back to it
+    /// for the templates they lack.
+    ///
+    /// Every template is rendered once with sample values, so that syntax errors and
+    /// unknown variables are reported here, at startup, rather than when sending.
+    pub fn load(directory: Option<&Path>, default_locale: &str) -> Result<Self, anyhow::Error> {
+        let mut sources: BTreeMap<String, String> = EMBEDDED_TEMPLATES
+            .iter()
+            .map(|&(name, source)| (name.to_owned(), source.to_owned()))
+            .collect();
+        if let Some(directory) = directory {
+            sources.extend(read_directory(directory)?);
+        }
+        let locales: BTreeSet<String> = sources
+            .keys()
+            .filter_map(|name| name.split_once('/'))
+            .map(|(locale, _)| locale.to_owned())
+            .collect();
+        let default_locale = default_locale.to_lowercase();
+        for name in TEMPLATE_NAMES {
+            anyhow::ensure!(
+                sources.contains_key(&format!("{}/{}", default_locale, name)),
+                "The default locale `{}` has no `{}` email template",
+                default_locale,
+                name
+            );
+        }
+
+        let mut env = Environment::new();
+        env.set_undefined_behavior(UndefinedBehavior::Strict);
+        env.set_formatter(html_formatter);
+        for (name, source) in sources {
+            env.add_template_owned(name.clone(), source)
+                .with_context(|| format!("Invalid email template `{}`", name))?;
+        }
+        let templates = Self {
+            env,
+            locales,
+            default_locale,
+        };
+        for locale in &templates.locales {
+            templates
+                .welcome(
+                    locale,
+                    "Ursula",
+                    "https://example.com/subscriptions/confirm",
+                )
+                .with_context(|| format!("Failed to render the `{}` welcome email", locale))?;
+            templates
+                .newsletter(
+                    locale,
+                    "Ursula",
+                    "Issue #1",
+                    "<p>Hello!</p>",
+                    "Hello!",
+                    "https://example.com/subscriptions/unsubscribe",
+                )
+                .with_context(|| format!("Failed to render the `{}` newsletter email", locale))?;
+            templates
+                .data_export(
+                    locale,
+                    "Ursula",
+                    "https://example.com/subscriptions/export",
+                    24,
+                )
+                .with_context(|| format!("Failed to render the `{}` data export email", locale))?;
+            templates
+                .erasure(
+                    locale,
+                    "Ursula",
+                    "https://example.com/subscriptions/erasure",
+                    24,
+                )
+                .with_context(|| format!("Failed to render the `{}` erasure email", locale))?;
+        }
+        Ok(templates)
+    }
+
+    pub fn default_locale(&self) -> &str {
+        &self.default_locale
+    }
+
+    /// The locale we have templates for that best matches a language tag: the tag
+    /// itself (`pt-br`) or, failing that, its primary language (`pt`).
+    pub fn supported_locale(&self, tag: &str) -> Option<&str> {
+        let tag = tag.trim().replace('_', "-").to_lowercase();
+        let primary_language = tag.split('-').next().unwrap_or_default();
+        self.locales
+            .get(&tag)
+            .or_else(|| self.locales.get(primary_language))
+            .map(String::as_str)
+    }
+
+    pub fn welcome(
+        &self,
+        locale: &str,
+        name: &str,
+        confirmation_link: &str,
+    ) -> Result<RenderedEmail, minijinja::Error> {
+        let ctx = context! { name, confirmation_link };
+        Ok(RenderedEmail {
+            subject: self.render(locale, "welcome.subject.txt", &ctx)?,
+            html_body: self.render(locale, "welcome.html", &ctx)?,
+            text_body: self.render(locale, "welcome.txt", &ctx)?,
+        })
+    }
+
+    /// Wraps the content of an issue for one subscriber. The issue HTML comes from an
+    /// authenticated admin and is inserted as is.
+    pub fn newsletter(
+        &self,
+        locale: &str,
+        name: &str,
+        title: &str,
+        html_content: &str,
+        text_content: &str,
+        unsubscribe_link: &str,
+    ) -> Result<RenderedEmail, minijinja::Error> {
+        let html_ctx = context! {
+            name,
+            unsubscribe_link,
+            content => Value::from_safe_string(html_content.to_owned()),
+        };
+        let text_ctx = context! { name, unsubscribe_link, content => text_content };
+        Ok(RenderedEmail {
+            subject: title.to_owned(),
+            html_body: self.render(locale, "newsletter.html", &html_ctx)?,
+            text_body: self.render(locale, "newsletter.txt", &text_ctx)?,
+        })
+    }
+
+    /// Sends a subscriber the link to download their data.
+    pub fn data_export(
+        &self,
+        locale: &str,
+        name: &str,
+        download_link: &str,
+        valid_for_hours: i64,
+    ) -> Result<RenderedEmail, minijinja::Error> {
+        let ctx = context! { name, download_link, valid_for_hours };
+        Ok(RenderedEmail {
+            subject: self.render(locale, "data_export.subject.txt", &ctx)?,
+            html_body: self.render(locale, "data_export.html", &ctx)?,
+            text_body: self.render(locale, "data_export.txt", &ctx)?,
+        })
+    }
+
+    /// Asks a subscriber to confirm that all their data should be deleted.
+    pub fn erasure(
+        &self,
+        locale: &str,
+        name: &str,
+        erasure_link: &str,
+        valid_for_hours: i64,
+    ) -> Result<RenderedEmail, minijinja::Error> {
+        let ctx = context! { name, erasure_link, valid_for_hours };
+        Ok(RenderedEmail {
+            subject: self.render(locale, "erasure.subject.txt", &ctx)?,
+            html_body: self.render(locale, "erasure.html", &ctx)?,
+            text_body: self.render(locale, "erasure.txt", &ctx)?,
+        })
+    }
+
+    fn render(&self, locale: &str, name: &str, ctx: &Value) -> Result<String, minijinja::Error> {
+        self.template(locale, name)?.render(ctx)
+    }
+
+    fn template(&self, locale: &str, name: &str) -> Result<Template<'_, '_>, minijinja::Error> {
+        self.env
+            .get_template(&format!("{}/{}", locale, name))
+            .or_else(|_| {
+                self.env
+                    .get_template(&format!("{}/{}", self.default_locale, name))
+            })
+    }
+}
+
+/// Reads `<directory>/<locale>/<template name>` files, keyed like `EMBEDDED_TEMPLATES`.
+fn read_directory(directory: &Path) -> Result<BTreeMap<String, String>, anyhow::Error> {
+    let mut sources = BTreeMap::new();
+    let entries = std::fs::read_dir(directory)
+        .with_context(|| format!("Failed to read {}", directory.display()))?;
+    for entry in entries {
+        let entry = entry?;
+        if !entry.file_type()?.is_dir() {
+            continue;
+        }
+        let locale = entry.file_name().to_string_lossy().to_lowercase();
+        for name in TEMPLATE_NAMES {
+            let path = entry.path().join(name);
+            if path.exists() {
+                let source = std::fs::read_to_string(&path)
+                    .with_context(|| format!("Failed to read {}", path.display()))?;
+                sources.insert(format!("{}/{}", locale, name), source);
+            }
+        }
+    }
+    Ok(sources)
+}
+
+/// minijinja's HTML escaping also escapes `/`, which leaves links in emails unreadable
+/// to anyone looking at the source (and to link detection). Escaping the characters
+/// that are significant in HTML text and attribute values is enough.
+fn html_formatter(out: &mut Output, state: &State, value: &Value) -> Result<(), minijinja::Error> {
+    let s = match value.as_str() {
+        Some(s) if state.auto_escape() == AutoEscape::Html && !value.is_safe() => s,
+        _ => return escape_formatter(out, state, value),
+    };
+    for c in s.chars() {
+        match c {
+            '&' => out.write_str("&amp;")?,
+            '<' => out.write_str("&lt;")?,
+            '>' => out.write_str("&gt;")?,
+            '"' => out.write_str("&quot;")?,
+            '\'' => out.write_str("&#x27;")?,
+            c => out.write_char(c)?,
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use claims::{assert_err, assert_none, assert_ok, assert_some_eq};
+    use tempfile::TempDir;
+
+    use super::EmailTemplates;
+
+    /// Writes `files`, given as paths relative to the directory (e.g. `en/welcome.html`).
+    /// The directory is deleted when the returned guard is dropped.
+    fn template_directory(files: &[(&str, &str)]) -> TempDir {
+        let directory = TempDir::with_prefix("email-templates-").unwrap();
+        for (path, content) in files {
+            let path = directory.path().join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, content).unwrap();
+        }
+        directory
+    }
+
+    fn embedded() -> EmailTemplates {
+        EmailTemplates::load(None, "en").unwrap()
+    }
+
+    #[test]
+    fn the_embedded_templates_are_valid() {
+        assert_ok!(EmailTemplates::load(None, "en"));
+    }
+
+    #[test]
+    fn variables_are_escaped_in_html_but_not_in_text() {
+        let templates = embedded();
+
+        let email = templates
+            .welcome("en", "<b>Ursula</b>", "https://example.com/confirm?a=1&b=2")
+            .unwrap();
+
+        assert!(email.html_body.contains("&lt;b&gt;Ursula&lt;/b&gt;"));
+        assert!(email
+            .html_body
+            .contains("https://example.com/confirm?a=1&amp;b=2"));
+        assert!(email.text_body.contains("<b>Ursula</b>"));
+        assert!(email
+            .text_body
+            .contains("https://example.com/confirm?a=1&b=2"));
+    }
+
+    #[test]
+    fn newsletter_content_is_not_escaped() {
+        let templates = embedded();
+
+        let email = templates
+            .newsletter(
+                "en",
+                "Ursula",
+                "Issue",
+                "<h1>News</h1>",
+                "News",
+                "https://x.y/u",
+            )
+            .unwrap();
+
+        assert!(email.html_body.contains("<h1>News</h1>"));
+        assert!(email.html_body.contains("https://x.y/u"));
+    }
+
+    #[test]
+    fn each_locale_renders_its_own_templates() {
+        let templates = embedded();
+
+        let english = templates.welcome("en", "Ursula", "https://x.y/c").unwrap();
+        let french = templates.welcome("fr", "Ursula", "https://x.y/c").unwrap();
+
+        assert_ne!(english.subject, french.subject);
+        assert!(french.text_body.contains("https://x.y/c"));
+    }
+
+    #[test]
+    fn unknown_locales_fall_back_to_the_default_one() {
+        let templates = embedded();
+
+        let english = templates.welcome("en", "Ursula", "https://x.y/c").unwrap();
+        let klingon = templates.welcome("tlh", "Ursula", "https://x.y/c").unwrap();
+
+        assert_eq!(english.subject, klingon.subject);
+    }
+
+    #[test]
+    fn supported_locale_matches_the_tag_or_its_primary_language() {
+        let templates = embedded();
+
+        assert_some_eq!(templates.supported_locale("fr"), "fr");
+        assert_some_eq!(templates.supported_locale("FR-ca"), "fr");
+        assert_some_eq!(templates.supported_locale("de_AT"), "de");
+        assert_none!(templates.supported_locale("tlh"));
+        assert_none!(templates.supported_locale(""));
+    }
+
+    #[test]
+    fn templates_in_the_directory_replace_the_embedded_ones() {
+        let directory =
+            template_directory(&[("en/welcome.subject.txt", "Hello {{ name }}, please confirm")]);
+
+        let templates = EmailTemplates::load(Some(directory.path()), "en").unwrap();
+        let email = templates.welcome("en", "Ursula", "https://x.y/c").unwrap();
+
+        assert_eq!(email.subject, "Hello Ursula, please confirm");
+        // Files missing from the directory fall back to the embedded version.
+        assert!(email.text_body.contains("https://x.y/c"));
+    }
+
+    #[test]
+    fn new_locales_can_be_added_through_the_directory() {
+        let directory =
+            template_directory(&[("pt-br/welcome.subject.txt", "Bem-vindo, {{ name }}!")]);
+
+        let templates = EmailTemplates::load(Some(directory.path()), "en").unwrap();
+        let email = templates
+            .welcome("pt-br", "Ursula", "https://x.y/c")
+            .unwrap();
+
+        assert_some_eq!(templates.supported_locale("pt-BR"), "pt-br");
+        assert_eq!(email.subject, "Bem-vindo, Ursula!");
+        // The rest comes from the default locale.
+        assert!(email.text_body.contains("https://x.y/c"));
+    }
+
+    #[test]
+    fn the_default_locale_must_provide_every_template() {
+        let directory = template_directory(&[("pt/welcome.subject.txt", "Bem-vindo!")]);
+
+        assert_err!(EmailTemplates::load(Some(directory.path()), "pt"));
+    }
+
+    #[test]
+    fn templates_with_syntax_errors_are_rejected_at_load_time() {
+        let directory = template_directory(&[("fr/welcome.html", "{% if name %}Hi")]);
+
+        assert_err!(EmailTemplates::load(Some(directory.path()), "en"));
+    }
+
+    #[test]
+    fn templates_using_unknown_variables_are_rejected_at_load_time() {
+        let directory = template_directory(&[("de/welcome.txt", "Hi {{ first_name }}")]);
+
+        assert_err!(EmailTemplates::load(Some(directory.path()), "en"));
+    }
+}
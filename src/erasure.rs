@@ -0,0 +1,271 @@
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use secrecy::{ExposeSecret, Secret};
+use sha2::Sha256;
+use sqlx::{Executor, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::domain::SubscriberEmail;
+
+/// Computes the keyed hash we keep instead of the address of an erased subscriber.
+/// Without the key, the hash cannot be matched against a list of candidate addresses.
+#[derive(Debug)]
+pub struct EmailHasher {
+    key: Secret<String>,
+}
+
+impl EmailHasher {
+    pub fn new(key: Secret<String>) -> Self {
+        Self { key }
+    }
+
+    /// The hex-encoded HMAC-SHA256 of the lowercased address.
+    pub fn hash(&self, email: &str) -> String {
+        let mut mac = Hmac::<Sha256>::new_from_slice(self.key.expose_secret().as_bytes())
+            .expect("HMAC accepts keys of any length.");
+        // Keeps the hash from matching any other value we sign with the same key.
+        mac.update(b"erased-email:");
+        mac.update(email.to_lowercase().as_bytes());
+        mac.finalize()
+            .into_bytes()
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum ErasureRequester {
+    /// Through the link we emailed them.
+    Subscriber,
+    Admin(Uuid),
+}
+
+impl ErasureRequester {
+    fn as_str(&self) -> &'static str {
+        match self {
+            ErasureRequester::Subscriber => "subscriber",
+            ErasureRequester::Admin(_) => "admin",
+        }
+    }
+
+    fn admin_user_id(&self) -> Option<Uuid> {
+        match self {
+            ErasureRequester::Subscriber => None,
+            ErasureRequester::Admin(user_id) => Some(*user_id),
+        }
+    }
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct Erasure {
+    pub subscriber_id: Uuid,
+    pub erased_at: DateTime<Utc>,
+}
+
+/// Erases everything that identifies a subscriber, as part of the caller's
+/// transaction, and records the erasure. Returns `None` if there is no such subscriber
+/// or it was already erased.
+///
+/// Tokens, queued deliveries and queued emails are deleted. The subscriber row, list memberships,
+/// delivery log and consent events are kept, stripped of personal data, so that
+/// aggregate statistics still add up. A suppression of the address is kept as a keyed hash.
+#[tracing::instrument(name = "Erasing a subscriber", skip(transaction, email_hasher))]
+pub async fn erase_subscriber(
+    transaction: &mut Transaction<'_, Postgres>,
+    email_hasher: &EmailHasher,
+    subscriber_id: Uuid,
+    requested_by: ErasureRequester,
+) -> Result<Option<Erasure>, sqlx::Error> {
+    let Some(email) = sqlx::query_scalar!(
+        r#"
+        SELECT email FROM subscriptions
+        WHERE id = $1 AND status <> 'erased'
+        FOR UPDATE
+        "#,
+        subscriber_id
+    )
+    .fetch_optional(&mut **transaction)
+    .await?
+    else {
+        return Ok(None);
+    };
+    let email_hash = email_hasher.hash(&email);
+
+    transaction
+        .execute(sqlx::query!(
+            r#"DELETE FROM subscription_tokens WHERE subscriber_id = $1"#,
+            subscriber_id
+        ))
+        .await?;
+    transaction
+        .execute(sqlx::query!(
+            r#"DELETE FROM data_export_tokens WHERE subscriber_id = $1"#,
+            subscriber_id
+        ))
+        .await?;
+    transaction
+        .execute(sqlx::query!(
+            r#"DELETE FROM erasure_tokens WHERE subscriber_id = $1"#,
+            subscriber_id
+        ))
+        .await?;
+    transaction
+        .execute(sqlx::query!(
+            r#"DELETE FROM issue_delivery_queue WHERE subscriber_email = $1"#,
+            email
+        ))
+        .await?;
+    transaction
+        .execute(sqlx::query!(
+            r#"DELETE FROM transactional_email_queue WHERE subscriber_id = $1"#,
+            subscriber_id
+        ))
+        .await?;
+    // Provider message ids lead to the provider's logs, which hold the address, and
+    // error messages may quote it.
+    transaction
+        .execute(sqlx::query!(
+            r#"
+            UPDATE email_log SET provider_message_id = NULL, error_message = NULL
+            WHERE subscriber_id = $1
+            "#,
+            subscriber_id
+        ))
+        .await?;
+    transaction
+        .execute(sqlx::query!(
+            r#"
+            UPDATE consent_events SET ip_address = NULL, user_agent = NULL, source_page = NULL
+            WHERE subscriber_id = $1
+            "#,
+            subscriber_id
+        ))
+        .await?;
+    transaction
+        .execute(sqlx::query!(
+            r#"
+            WITH removed AS (
+                DELETE FROM suppressions WHERE email = lower($1)
+                RETURNING reason, source, created_at
+            )
+            INSERT INTO erased_suppressions (email_hash, reason, source, created_at)
+            SELECT $2, reason, source, created_at FROM removed
+            ON CONFLICT (email_hash) DO NOTHING
+            "#,
+            email,
+            email_hash
+        ))
+        .await?;
+    // Unsubscribe links in past issues stop working.
+    transaction
+        .execute(sqlx::query!(
+            r#"
+            UPDATE list_memberships
+            SET status = 'unsubscribed',
+                unsubscribed_at = coalesce(unsubscribed_at, now()),
+                unsubscribe_token = replace(gen_random_uuid()::text, '-', '')
+            WHERE subscriber_id = $1
+            "#,
+            subscriber_id
+        ))
+        .await?;
+    // `email` is unique: it gets a value nobody can sign up with.
+    transaction
+        .execute(sqlx::query!(
+            r#"
+            UPDATE subscriptions
+            SET email = id::text || '@erased.invalid',
+                name = '',
+                locale = NULL,
+                status = 'erased',
+                erased_at = now()
+            WHERE id = $1
+            "#,
+            subscriber_id
+        ))
+        .await?;
+    let erased_at = sqlx::query_scalar!(
+        r#"
+        INSERT INTO erasures (id, subscriber_id, email_hash, requested_by, admin_user_id)
+        VALUES ($1, $2, $3, $4, $5)
+        RETURNING erased_at
+        "#,
+        Uuid::new_v4(),
+        subscriber_id,
+        email_hash,
+        requested_by.as_str(),
+        requested_by.admin_user_id()
+    )
+    .fetch_one(&mut **transaction)
+    .await?;
+    Ok(Some(Erasure {
+        subscriber_id,
+        erased_at,
+    }))
+}
+
+/// Turns the suppression of an erased address back into a regular one when the
+/// address signs up again, so that it keeps applying to every email.
+#[tracing::instrument(
+    name = "Restoring the suppression of an erased address",
+    skip(transaction, email_hasher, email)
+)]
+pub async fn restore_suppression(
+    transaction: &mut Transaction<'_, Postgres>,
+    email_hasher: &EmailHasher,
+    email: &SubscriberEmail,
+) -> Result<(), sqlx::Error> {
+    let query = sqlx::query!(
+        r#"
+        WITH restored AS (
+            DELETE FROM erased_suppressions WHERE email_hash = $1
+            RETURNING reason, source, created_at
+        )
+        INSERT INTO suppressions (email, reason, source, created_at)
+        SELECT lower($2), reason, source, created_at FROM restored
+        ON CONFLICT (email) DO NOTHING
+        "#,
+        email_hasher.hash(email.as_ref()),
+        email.as_ref()
+    );
+    transaction.execute(query).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use secrecy::Secret;
+
+    use super::EmailHasher;
+
+    fn hasher(key: &str) -> EmailHasher {
+        EmailHasher::new(Secret::new(key.into()))
+    }
+
+    #[test]
+    fn the_hash_ignores_case() {
+        let hasher = hasher("key");
+
+        assert_eq!(
+            hasher.hash("Ursula@Example.com"),
+            hasher.hash("ursula@example.com")
+        );
+    }
+
+    #[test]
+    fn the_hash_depends_on_the_key() {
+        assert_ne!(
+            hasher("key").hash("ursula@example.com"),
+            hasher("another key").hash("ursula@example.com")
+        );
+    }
+
+    #[test]
+    fn the_hash_is_hex_encoded_sha256() {
+        let hash = hasher("key").hash("ursula@example.com");
+
+        assert_eq!(hash.len(), 64);
+        assert!(hash.chars().all(|c| c.is_ascii_hexdigit()));
+    }
+}
@@ -0,0 +1,34 @@
+use actix_web::HttpRequest;
+use sha2::{Digest, Sha256};
+
+/// A hash of what the request asks for: its method, path and (deserialized) body.
+/// Two requests with the same fingerprint are retries of each other.
+#[derive(Debug, PartialEq, Eq)]
+pub struct RequestFingerprint(String);
+
+impl RequestFingerprint {
+    pub fn new(request: &HttpRequest, body: &impl serde::Serialize) -> Result<Self, anyhow::Error> {
+        let body = serde_json::to_vec(body)?;
+        let mut hasher = Sha256::new();
+        for part in [
+            request.method().as_str().as_bytes(),
+            request.path().as_bytes(),
+        ] {
+            hasher.update(part);
+            hasher.update(b"\n");
+        }
+        hasher.update(&body);
+        let fingerprint = hasher
+            .finalize()
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect();
+        Ok(Self(fingerprint))
+    }
+}
+
+impl AsRef<str> for RequestFingerprint {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
@@ -0,0 +1,89 @@
+use actix_web::http::header::HeaderMap;
+
+/// Header clients use to tag a request so that retries can be recognised.
+pub const IDEMPOTENCY_KEY_HEADER: &str = "Idempotency-Key";
+
+#[derive(Debug)]
+pub struct IdempotencyKey(String);
+
+impl IdempotencyKey {
+    /// Extracts the key from the request headers, if the client provided one.
+    pub fn from_headers(headers: &HeaderMap) -> Result<Option<IdempotencyKey>, String> {
+        let Some(value) = headers.get(IDEMPOTENCY_KEY_HEADER) else {
+            return Ok(None);
+        };
+        let value = value
+            .to_str()
+            .map_err(|_| format!("The {} header is not valid UTF-8", IDEMPOTENCY_KEY_HEADER))?;
+        IdempotencyKey::try_from(value.to_owned()).map(Some)
+    }
+}
+
+impl TryFrom<String> for IdempotencyKey {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        if s.is_empty() {
+            return Err("The idempotency key cannot be empty".into());
+        }
+        let max_length = 50;
+        if s.len() >= max_length {
+            return Err(format!(
+                "The idempotency key must be shorter than {} characters",
+                max_length
+            ));
+        }
+        Ok(Self(s))
+    }
+}
+
+impl From<IdempotencyKey> for String {
+    fn from(k: IdempotencyKey) -> Self {
+        k.0
+    }
+}
+
+impl AsRef<str> for IdempotencyKey {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::http::header::{HeaderMap, HeaderName, HeaderValue};
+    use claims::{assert_err, assert_none, assert_ok, assert_some};
+
+    use super::IdempotencyKey;
+
+    #[test]
+    fn an_empty_key_is_rejected() {
+        assert_err!(IdempotencyKey::try_from("".to_string()));
+    }
+
+    #[test]
+    fn a_50_characters_long_key_is_rejected() {
+        assert_err!(IdempotencyKey::try_from("k".repeat(50)));
+    }
+
+    #[test]
+    fn a_uuid_is_a_valid_key() {
+        assert_ok!(IdempotencyKey::try_from(uuid::Uuid::new_v4().to_string()));
+    }
+
+    #[test]
+    fn a_missing_header_is_not_an_error() {
+        let headers = HeaderMap::new();
+        assert_none!(IdempotencyKey::from_headers(&headers).unwrap());
+    }
+
+    #[test]
+    fn the_key_is_read_from_the_idempotency_key_header() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            HeaderName::from_static("idempotency-key"),
+            HeaderValue::from_static("abc"),
+        );
+        assert_some!(IdempotencyKey::from_headers(&headers).unwrap());
+    }
+}
@@ -0,0 +1,7 @@
+mod fingerprint;
+mod key;
+mod persistence;
+
+pub use fingerprint::RequestFingerprint;
+pub use key::IdempotencyKey;
+pub use persistence::{save_response, try_processing, NextAction, ANONYMOUS_USER_ID};
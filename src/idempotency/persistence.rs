@@ -0,0 +1,196 @@
+use actix_web::{body::to_bytes, http::StatusCode, HttpResponse};
+use chrono::Utc;
+use sqlx::{postgres::PgHasArrayType, Executor, PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use super::{IdempotencyKey, RequestFingerprint};
+
+/// Owner of the idempotency records created by requests that are not tied to a user,
+/// e.g. public subscription forms. Anonymous clients share this namespace: the request
+/// fingerprint keeps one of them from getting the response meant for another.
+pub const ANONYMOUS_USER_ID: Uuid = Uuid::nil();
+
+/// How long a duplicate request waits for the original one to finish before giving up.
+const LOCK_TIMEOUT: &str = "5s";
+
+/// Postgres error code for `lock_not_available`.
+const LOCK_NOT_AVAILABLE: &str = "55P03";
+
+#[derive(Debug, sqlx::Type)]
+#[sqlx(type_name = "header_pair")]
+struct HeaderPairRecord {
+    name: String,
+    value: Vec<u8>,
+}
+
+impl PgHasArrayType for HeaderPairRecord {
+    fn array_type_info() -> sqlx::postgres::PgTypeInfo {
+        sqlx::postgres::PgTypeInfo::with_name("_header_pair")
+    }
+}
+
+#[allow(clippy::large_enum_variant)]
+pub enum NextAction {
+    /// First time we see this key: the caller must process the request and hand the
+    /// transaction back to `save_response`.
+    StartProcessing(Transaction<'static, Postgres>),
+    /// The request was already processed: replay what we returned the first time.
+    ReturnSavedResponse(HttpResponse),
+    /// Another request with the same key is still being processed.
+    Conflict,
+    /// The key was used for a different request.
+    KeyReused,
+}
+
+struct SavedResponse {
+    request_fingerprint: Option<String>,
+    response: HttpResponse,
+}
+
+#[tracing::instrument(name = "Get saved response", skip(pool, idempotency_key))]
+async fn get_saved_response(
+    pool: &PgPool,
+    idempotency_key: &IdempotencyKey,
+    user_id: Uuid,
+) -> Result<Option<SavedResponse>, anyhow::Error> {
+    let saved_response = sqlx::query!(
+        r#"
+        SELECT
+            request_fingerprint,
+            response_status_code as "response_status_code!",
+            response_headers as "response_headers!: Vec<HeaderPairRecord>",
+            response_body as "response_body!"
+        FROM idempotency
+        WHERE
+          user_id = $1 AND
+          idempotency_key = $2
+        "#,
+        user_id,
+        idempotency_key.as_ref()
+    )
+    .fetch_optional(pool)
+    .await?;
+    if let Some(r) = saved_response {
+        let status_code = StatusCode::from_u16(r.response_status_code.try_into()?)?;
+        let mut response = HttpResponse::build(status_code);
+        for HeaderPairRecord { name, value } in r.response_headers {
+            response.append_header((name, value));
+        }
+        Ok(Some(SavedResponse {
+            request_fingerprint: r.request_fingerprint,
+            response: response.body(r.response_body),
+        }))
+    } else {
+        Ok(None)
+    }
+}
+
+/// Stores `http_response` against the idempotency key and commits the transaction
+/// opened by `try_processing`.
+#[tracing::instrument(
+    name = "Save response for idempotency key",
+    skip(transaction, idempotency_key, http_response)
+)]
+pub async fn save_response(
+    mut transaction: Transaction<'static, Postgres>,
+    idempotency_key: &IdempotencyKey,
+    user_id: Uuid,
+    http_response: HttpResponse,
+) -> Result<HttpResponse, anyhow::Error> {
+    let (response_head, body) = http_response.into_parts();
+    let body = to_bytes(body).await.map_err(|e| anyhow::anyhow!("{}", e))?;
+    let status_code = response_head.status().as_u16() as i16;
+    let headers = {
+        let mut h = Vec::with_capacity(response_head.headers().len());
+        for (name, value) in response_head.headers().iter() {
+            let name = name.as_str().to_owned();
+            let value = value.as_bytes().to_owned();
+            h.push(HeaderPairRecord { name, value });
+        }
+        h
+    };
+    let query = sqlx::query_unchecked!(
+        r#"
+        UPDATE idempotency
+        SET
+            response_status_code = $3,
+            response_headers = $4,
+            response_body = $5
+        WHERE
+            user_id = $1 AND
+            idempotency_key = $2
+        "#,
+        user_id,
+        idempotency_key.as_ref(),
+        status_code,
+        headers,
+        body.as_ref()
+    );
+    transaction.execute(query).await?;
+    transaction.commit().await?;
+
+    let http_response = response_head.set_body(body).map_into_boxed_body();
+    Ok(http_response)
+}
+
+/// Claims the idempotency key for the current request.
+///
+/// If a concurrent request already claimed it, Postgres makes us wait on the row lock
+/// until that request commits, so that we can replay its response. We give up with
+/// `NextAction::Conflict` if that takes longer than `LOCK_TIMEOUT`.
+///
+/// The response is only replayed to a request with the same fingerprint as the one
+/// that claimed the key.
+#[tracing::instrument(
+    name = "Try processing idempotent request",
+    skip(pool, idempotency_key, request_fingerprint)
+)]
+pub async fn try_processing(
+    pool: &PgPool,
+    idempotency_key: &IdempotencyKey,
+    user_id: Uuid,
+    request_fingerprint: &RequestFingerprint,
+) -> Result<NextAction, anyhow::Error> {
+    let mut transaction = pool.begin().await?;
+    transaction
+        .execute(format!("SET LOCAL lock_timeout = '{}'", LOCK_TIMEOUT).as_str())
+        .await?;
+    let query = sqlx::query!(
+        r#"
+        INSERT INTO idempotency (
+            user_id,
+            idempotency_key,
+            request_fingerprint,
+            created_at
+        )
+        VALUES ($1, $2, $3, $4)
+        ON CONFLICT DO NOTHING
+        "#,
+        user_id,
+        idempotency_key.as_ref(),
+        request_fingerprint.as_ref(),
+        Utc::now()
+    );
+    let n_inserted_rows = match transaction.execute(query).await {
+        Ok(result) => result.rows_affected(),
+        Err(e) if is_lock_timeout(&e) => return Ok(NextAction::Conflict),
+        Err(e) => return Err(e.into()),
+    };
+    if n_inserted_rows > 0 {
+        Ok(NextAction::StartProcessing(transaction))
+    } else {
+        let saved_response = get_saved_response(pool, idempotency_key, user_id)
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("We expected a saved response, we didn't find it"))?;
+        match saved_response.request_fingerprint {
+            Some(saved) if saved != request_fingerprint.as_ref() => Ok(NextAction::KeyReused),
+            _ => Ok(NextAction::ReturnSavedResponse(saved_response.response)),
+        }
+    }
+}
+
+fn is_lock_timeout(e: &sqlx::Error) -> bool {
+    e.as_database_error()
+        .and_then(|e| e.code())
+        .is_some_and(|code| code == LOCK_NOT_AVAILABLE)
+}
@@ -0,0 +1,313 @@
+use std::time::Duration;
+
+use sqlx::{Executor, PgPool, Postgres, Transaction};
+use tracing::{field::display, Span};
+use uuid::Uuid;
+
+use crate::{
+    configuration::Settings,
+    domain::SubscriberEmail,
+    email_client::{EmailClient, EmailMessage},
+    email_log::EmailType,
+    email_templates::EmailTemplates,
+    outbound_email::{send_to_subscriber, OutboundEmailError},
+    startup::get_connection_pool,
+};
+
+/// How many times a failed delivery is attempted before it is dropped from the queue.
+const MAX_RETRIES: i16 = 5;
+
+pub enum ExecutionOutcome {
+    TaskCompleted,
+    EmptyQueue,
+}
+
+struct Task {
+    newsletter_issue_id: Uuid,
+    subscriber_email: String,
+    n_retries: i16,
+    /// `None` if the subscriber has been removed since the task was enqueued.
+    subscriber_id: Option<Uuid>,
+    subscriber_status: Option<String>,
+    /// The subscriber's status on the issue's list.
+    membership_status: Option<String>,
+    subscriber_name: Option<String>,
+    subscriber_locale: Option<String>,
+    unsubscribe_token: Option<String>,
+}
+
+struct NewsletterIssue {
+    title: String,
+    text_content: String,
+    html_content: String,
+}
+
+#[tracing::instrument(
+    skip_all,
+    fields(
+        newsletter_issue_id=tracing::field::Empty,
+        subscriber_email=tracing::field::Empty
+    ),
+    err
+)]
+pub async fn try_execute_task(
+    pool: &PgPool,
+    email_client: &EmailClient,
+    email_templates: &EmailTemplates,
+    base_url: &str,
+) -> Result<ExecutionOutcome, sqlx::Error> {
+    let Some((mut transaction, task)) = dequeue_task(pool).await? else {
+        return Ok(ExecutionOutcome::EmptyQueue);
+    };
+    Span::current()
+        .record("newsletter_issue_id", display(task.newsletter_issue_id))
+        .record("subscriber_email", display(&task.subscriber_email));
+    let (subscriber_id, unsubscribe_token) = match (
+        task.subscriber_id,
+        task.subscriber_status.as_deref(),
+        task.membership_status.as_deref(),
+        &task.unsubscribe_token,
+    ) {
+        (Some(subscriber_id), Some("active"), Some("confirmed"), Some(unsubscribe_token)) => {
+            (subscriber_id, unsubscribe_token)
+        }
+        _ => {
+            tracing::info!("Skipping delivery. The recipient is no longer a confirmed subscriber.");
+            delete_task(&mut transaction, &task).await?;
+            transaction.commit().await?;
+            return Ok(ExecutionOutcome::TaskCompleted);
+        }
+    };
+    match SubscriberEmail::parse(task.subscriber_email.clone()) {
+        Ok(recipient) => {
+            let issue = get_issue(pool, task.newsletter_issue_id).await?;
+            let unsubscribe_link = format!(
+                "{}/subscriptions/unsubscribe?token={}",
+                base_url, unsubscribe_token
+            );
+            let email = newsletter_email(
+                email_client,
+                email_templates,
+                &task,
+                &issue,
+                recipient,
+                &unsubscribe_link,
+            );
+            let email = match email {
+                Ok(email) => email,
+                Err(e) => {
+                    tracing::error!(
+                        error.cause_chain = ?e,
+                        error.message = %e,
+                        "Skipping a confirmed subscriber. Failed to render the newsletter email."
+                    );
+                    delete_task(&mut transaction, &task).await?;
+                    transaction.commit().await?;
+                    return Ok(ExecutionOutcome::TaskCompleted);
+                }
+            };
+            let outcome = send_to_subscriber(
+                &mut transaction,
+                email_client,
+                subscriber_id,
+                EmailType::NewsletterIssue(task.newsletter_issue_id),
+                &email,
+            )
+            .await;
+            match outcome {
+                // Suppressed recipients will not become deliverable by retrying.
+                Ok(_) => delete_task(&mut transaction, &task).await?,
+                Err(OutboundEmailError::Database(e)) => return Err(e),
+                Err(OutboundEmailError::Email(e)) if !e.is_transient() => {
+                    tracing::error!(
+                        error.cause_chain = ?e,
+                        error.message = %e,
+                        "Failed to deliver issue to a confirmed subscriber. The provider rejected it, giving up."
+                    );
+                    delete_task(&mut transaction, &task).await?;
+                }
+                Err(OutboundEmailError::Email(e)) if task.n_retries + 1 >= MAX_RETRIES => {
+                    tracing::error!(
+                        error.cause_chain = ?e,
+                        error.message = %e,
+                        "Failed to deliver issue to a confirmed subscriber. Giving up after {} attempts.",
+                        MAX_RETRIES
+                    );
+                    delete_task(&mut transaction, &task).await?;
+                }
+                Err(OutboundEmailError::Email(e)) => {
+                    tracing::warn!(
+                        error.cause_chain = ?e,
+                        error.message = %e,
+                        "Failed to deliver issue to a confirmed subscriber. Retrying later."
+                    );
+                    reschedule_task(&mut transaction, &task).await?;
+                }
+            }
+        }
+        Err(e) => {
+            tracing::error!(
+                error.message = %e,
+                "Skipping a confirmed subscriber. Their stored contact details are invalid."
+            );
+            delete_task(&mut transaction, &task).await?;
+        }
+    }
+    transaction.commit().await?;
+    Ok(ExecutionOutcome::TaskCompleted)
+}
+
+fn newsletter_email(
+    email_client: &EmailClient,
+    email_templates: &EmailTemplates,
+    task: &Task,
+    issue: &NewsletterIssue,
+    recipient: SubscriberEmail,
+    unsubscribe_link: &str,
+) -> Result<EmailMessage, anyhow::Error> {
+    let locale = task
+        .subscriber_locale
+        .as_deref()
+        .unwrap_or(email_templates.default_locale());
+    let email = email_templates.newsletter(
+        locale,
+        task.subscriber_name.as_deref().unwrap_or_default(),
+        &issue.title,
+        &issue.html_content,
+        &issue.text_content,
+        unsubscribe_link,
+    )?;
+    let email = email_client
+        .message(recipient, &email.subject)
+        .html_body(email.html_body)
+        .text_body(email.text_body)
+        .list_unsubscribe(unsubscribe_link)
+        .build()?;
+    Ok(email)
+}
+
+#[tracing::instrument(skip_all)]
+async fn dequeue_task(
+    pool: &PgPool,
+) -> Result<Option<(Transaction<'static, Postgres>, Task)>, sqlx::Error> {
+    let mut transaction = pool.begin().await?;
+    let task = sqlx::query_as!(
+        Task,
+        r#"
+        SELECT
+            q.newsletter_issue_id,
+            q.subscriber_email,
+            q.n_retries,
+            s.id as "subscriber_id?",
+            s.status as "subscriber_status?",
+            m.status as "membership_status?",
+            s.name as "subscriber_name?",
+            s.locale as "subscriber_locale?",
+            m.unsubscribe_token as "unsubscribe_token?"
+        FROM issue_delivery_queue q
+        JOIN newsletter_issues i ON i.newsletter_issue_id = q.newsletter_issue_id
+        LEFT JOIN subscriptions s ON s.email = q.subscriber_email
+        LEFT JOIN list_memberships m ON m.list_id = i.list_id AND m.subscriber_id = s.id
+        WHERE q.execute_after <= now()
+        FOR UPDATE OF q
+        SKIP LOCKED
+        LIMIT 1
+        "#
+    )
+    .fetch_optional(&mut *transaction)
+    .await?;
+    Ok(task.map(|task| (transaction, task)))
+}
+
+#[tracing::instrument(skip_all)]
+async fn delete_task(
+    transaction: &mut Transaction<'static, Postgres>,
+    task: &Task,
+) -> Result<(), sqlx::Error> {
+    let query = sqlx::query!(
+        r#"
+        DELETE FROM issue_delivery_queue
+        WHERE
+            newsletter_issue_id = $1 AND
+            subscriber_email = $2
+        "#,
+        task.newsletter_issue_id,
+        task.subscriber_email
+    );
+    transaction.execute(query).await?;
+    Ok(())
+}
+
+/// Puts a failed task back in the queue, backing off exponentially between attempts.
+#[tracing::instrument(skip_all)]
+async fn reschedule_task(
+    transaction: &mut Transaction<'static, Postgres>,
+    task: &Task,
+) -> Result<(), sqlx::Error> {
+    let backoff_seconds = 2_f64.powi(task.n_retries.into()) * 30.0;
+    let query = sqlx::query!(
+        r#"
+        UPDATE issue_delivery_queue
+        SET
+            n_retries = n_retries + 1,
+            execute_after = now() + make_interval(secs => $3)
+        WHERE
+            newsletter_issue_id = $1 AND
+            subscriber_email = $2
+        "#,
+        task.newsletter_issue_id,
+        task.subscriber_email,
+        backoff_seconds
+    );
+    transaction.execute(query).await?;
+    Ok(())
+}
+
+#[tracing::instrument(skip_all)]
+async fn get_issue(pool: &PgPool, issue_id: Uuid) -> Result<NewsletterIssue, sqlx::Error> {
+    let issue = sqlx::query_as!(
+        NewsletterIssue,
+        r#"
+        SELECT title, text_content, html_content
+        FROM newsletter_issues
+        WHERE
+            newsletter_issue_id = $1
+        "#,
+        issue_id
+    )
+    .fetch_one(pool)
+    .await?;
+    Ok(issue)
+}
+
+async fn worker_loop(
+    pool: PgPool,
+    email_client: EmailClient,
+    email_templates: EmailTemplates,
+    base_url: String,
+) -> Result<(), anyhow::Error> {
+    loop {
+        match try_execute_task(&pool, &email_client, &email_templates, &base_url).await {
+            Ok(ExecutionOutcome::EmptyQueue) => {
+                tokio::time::sleep(Duration::from_secs(10)).await;
+            }
+            Err(_) => {
+                tokio::time::sleep(Duration::from_secs(1)).await;
+            }
+            Ok(ExecutionOutcome::TaskCompleted) => {}
+        }
+    }
+}
+
+pub async fn run_worker_until_stopped(configuration: Settings) -> Result<(), anyhow::Error> {
+    let connection_pool = get_connection_pool(&configuration.database_url);
+    let email_client = configuration.email_client.client();
+    let email_templates = configuration.email_templates.load()?;
+    worker_loop(
+        connection_pool,
+        email_client,
+        email_templates,
+        configuration.application.base_url,
+    )
+    .await
+}
@@ -1,6 +1,22 @@
+pub mod authentication;
+pub mod bot_protection;
 pub mod configuration;
+pub mod consent;
 pub mod domain;
 pub mod email_client;
+pub mod email_log;
+pub mod email_templates;
+pub mod erasure;
+pub mod idempotency;
+pub mod issue_delivery_worker;
+pub mod lists;
+pub mod outbound_email;
+pub mod problem_details;
+pub mod rate_limit;
 pub mod routes;
+pub mod session_state;
+pub mod session_store;
 pub mod startup;
 pub mod telemetry;
+pub mod transactional_email_worker;
+pub mod utils;
@@ -0,0 +1,31 @@
+use sqlx::{Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::domain::ListSlug;
+
+/// A list subscribers can join and newsletter issues are sent to.
+#[derive(Debug)]
+pub struct MailingList {
+    pub id: Uuid,
+    pub slug: String,
+    pub name: String,
+}
+
+/// The list with the given slug, or the default list if there is no slug.
+#[tracing::instrument(name = "Looking up a list", skip(transaction))]
+pub async fn find_list(
+    transaction: &mut Transaction<'_, Postgres>,
+    slug: Option<&ListSlug>,
+) -> Result<Option<MailingList>, sqlx::Error> {
+    sqlx::query_as!(
+        MailingList,
+        r#"
+        SELECT id, slug, name
+        FROM lists
+        WHERE ($1::TEXT IS NULL AND is_default) OR slug = $1
+        "#,
+        slug.map(AsRef::as_ref)
+    )
+    .fetch_optional(&mut **transaction)
+    .await
+}
@@ -1,12 +1,93 @@
-use zero2prod::{configuration::get_configuration, startup::Application, telemetry};
+use std::fmt::{Debug, Display};
+
+use anyhow::Context;
+use secrecy::Secret;
+use tokio::task::JoinError;
+use zero2prod::{
+    authentication::create_user,
+    configuration::{get_configuration, Settings},
+    issue_delivery_worker,
+    startup::{get_connection_pool, Application},
+    telemetry, transactional_email_worker,
+};
 
 #[tokio_macros::main]
-async fn main() -> Result<(), std::io::Error> {
+async fn main() -> anyhow::Result<()> {
     let subscriber = telemetry::get_subscriber("zero2prod".into(), "info".into(), std::io::stdout);
     telemetry::init_subscriber(subscriber);
 
     let settings = get_configuration().expect("Failed to read configuration.yaml");
-    let server = Application::build(settings).await?;
-    server.run_until_stopped().await?;
+    let mut args = std::env::args().skip(1);
+    match args.next().as_deref() {
+        None => serve(settings).await,
+        Some("create-admin") => {
+            let username = args
+                .next()
+                .context("Usage: zero2prod create-admin <username>")?;
+            create_admin(settings, username).await
+        }
+        Some(other) => anyhow::bail!("Unknown command '{}'. Try 'create-admin'.", other),
+    }
+}
+
+/// Creates an admin user. The password is read from the `ADMIN_PASSWORD` environment
+/// variable or, if that is not set, from the first line of stdin.
+async fn create_admin(settings: Settings, username: String) -> anyhow::Result<()> {
+    let password = match std::env::var("ADMIN_PASSWORD") {
+        Ok(password) => password,
+        Err(_) => {
+            let mut line = String::new();
+            std::io::stdin()
+                .read_line(&mut line)
+                .context("Failed to read the password from stdin")?;
+            line.trim_end_matches(['\r', '\n']).to_owned()
+        }
+    };
+    anyhow::ensure!(!password.is_empty(), "The admin password cannot be empty");
+    let pool = get_connection_pool(&settings.database_url);
+    let user_id = create_user(&username, Secret::new(password), &pool).await?;
+    tracing::info!(%user_id, %username, "Created admin user");
     Ok(())
 }
+
+async fn serve(settings: Settings) -> anyhow::Result<()> {
+    let application = Application::build(settings.clone()).await?;
+    let application_task = tokio::spawn(application.run_until_stopped());
+    let worker_task = tokio::spawn(issue_delivery_worker::run_worker_until_stopped(
+        settings.clone(),
+    ));
+    let email_worker_task = tokio::spawn(transactional_email_worker::run_worker_until_stopped(
+        settings,
+    ));
+
+    tokio::select! {
+        o = application_task => report_exit("API", o),
+        o = worker_task => report_exit("Background worker", o),
+        o = email_worker_task => report_exit("Transactional email worker", o),
+    };
+    Ok(())
+}
+
+fn report_exit(task_name: &str, outcome: Result<Result<(), impl Debug + Display>, JoinError>) {
+    match outcome {
+        Ok(Ok(())) => {
+            tracing::info!("{} has exited", task_name)
+        }
+        Ok(Err(e)) => {
+            tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "{} failed",
+                task_name
+            )
+        }
+        Err(e) => {
+            tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "{} task failed to complete",
+                task_name
+            )
+        }
+    }
+}
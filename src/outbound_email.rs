@@ -0,0 +1,66 @@
+use sqlx::{Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::{
+    email_client::{EmailClient, EmailError, EmailMessage, SentEmail},
+    email_log::{record_email, record_suppressed_email, EmailType},
+};
+
+/// What happened to an email sent through [`send_to_subscriber`].
+#[derive(Debug)]
+pub enum Delivery {
+    Sent(SentEmail),
+    /// The recipient is on the suppression list: nothing was sent.
+    Suppressed {
+        reason: String,
+    },
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum OutboundEmailError {
+    #[error(transparent)]
+    Email(#[from] EmailError),
+    #[error("Failed to check the suppression list or to record the email.")]
+    Database(#[from] sqlx::Error),
+}
+
+/// Every email to a subscriber goes out through here. Recipients on the suppression
+/// list are skipped, and every attempt (sent, failed or skipped) is recorded in the
+/// email log as part of `transaction`.
+#[tracing::instrument(
+    name = "Sending an email to a subscriber",
+    skip(transaction, email_client, message)
+)]
+pub async fn send_to_subscriber(
+    transaction: &mut Transaction<'_, Postgres>,
+    email_client: &EmailClient,
+    subscriber_id: Uuid,
+    email_type: EmailType,
+    message: &EmailMessage,
+) -> Result<Delivery, OutboundEmailError> {
+    if let Some(reason) = get_suppression_reason(transaction, message.to().as_ref()).await? {
+        tracing::info!(
+            suppression.reason = %reason,
+            "Not sending the email. The recipient is on the suppression list."
+        );
+        record_suppressed_email(transaction, subscriber_id, email_type, &reason).await?;
+        return Ok(Delivery::Suppressed { reason });
+    }
+    let outcome = email_client.send_message(message).await;
+    record_email(transaction, subscriber_id, email_type, outcome.as_ref()).await?;
+    Ok(Delivery::Sent(outcome?))
+}
+
+#[tracing::instrument(name = "Checking the suppression list", skip_all)]
+async fn get_suppression_reason(
+    transaction: &mut Transaction<'_, Postgres>,
+    email: &str,
+) -> Result<Option<String>, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"SELECT source, reason FROM suppressions WHERE email = lower($1)"#,
+        email
+    )
+    .fetch_optional(&mut **transaction)
+    .await?;
+    Ok(row.map(|row| format!("{}: {}", row.source, row.reason)))
+}
@@ -0,0 +1,47 @@
+use actix_web::{http::StatusCode, HttpResponse};
+
+/// An RFC 7807 problem document, returned as `application/problem+json`.
+#[derive(serde::Serialize, Debug)]
+pub struct ProblemDetails {
+    #[serde(rename = "type")]
+    pub problem_type: &'static str,
+    pub title: &'static str,
+    pub status: u16,
+    pub detail: String,
+    /// Extension member listing what is wrong with each offending input field.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<FieldError>,
+}
+
+#[derive(serde::Serialize, Debug)]
+pub struct FieldError {
+    pub field: &'static str,
+    pub code: &'static str,
+    pub detail: String,
+}
+
+impl ProblemDetails {
+    /// A problem with no more specific `type` than its status code, as allowed by
+    /// RFC 7807 with `about:blank`.
+    pub fn new(status: StatusCode, detail: impl Into<String>) -> Self {
+        Self {
+            problem_type: "about:blank",
+            title: status.canonical_reason().unwrap_or("Unknown Error"),
+            status: status.as_u16(),
+            detail: detail.into(),
+            errors: Vec::new(),
+        }
+    }
+
+    pub fn with_errors(mut self, errors: Vec<FieldError>) -> Self {
+        self.errors = errors;
+        self
+    }
+
+    pub fn into_response(self) -> HttpResponse {
+        let status = StatusCode::from_u16(self.status).unwrap_or(StatusCode::BAD_REQUEST);
+        HttpResponse::build(status)
+            .content_type("application/problem+json")
+            .json(self)
+    }
+}
@@ -0,0 +1,295 @@
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use actix_web::{
+    body::MessageBody,
+    dev::{ServiceRequest, ServiceResponse},
+    http::{
+        header::{HeaderName, HeaderValue, RETRY_AFTER},
+        StatusCode,
+    },
+    web, HttpRequest, HttpResponse, ResponseError,
+};
+use actix_web_lab::middleware::Next;
+
+use crate::{problem_details::ProblemDetails, startup::SignupProtection};
+
+/// The most keys a limiter tracks. Once a new key would go past it, buckets that have
+/// filled up again are dropped, since they behave exactly like a bucket we have never
+/// seen, and then the least recently used ones until a tenth of the room is free again.
+const MAX_TRACKED_KEYS: usize = 10_000;
+
+/// An in-memory token bucket per key: each request takes a token, and tokens come
+/// back one every `refill_interval`, up to `burst`.
+#[derive(Debug)]
+pub struct RateLimiter {
+    burst: u32,
+    refill_interval: Duration,
+    capacity: usize,
+    buckets: Mutex<HashMap<String, Bucket>>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    tokens: f64,
+    updated_at: Instant,
+}
+
+impl RateLimiter {
+    pub fn new(burst: u32, refill_interval: Duration) -> Self {
+        Self {
+            burst,
+            refill_interval,
+            capacity: MAX_TRACKED_KEYS,
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Takes a token from `key`'s bucket, or says how long until one is available.
+    pub fn check(&self, key: &str) -> Result<(), RateLimited> {
+        self.check_at(key, Instant::now())
+    }
+
+    fn check_at(&self, key: &str, now: Instant) -> Result<(), RateLimited> {
+        let mut buckets = self.buckets.lock().unwrap();
+        if buckets.len() >= self.capacity && !buckets.contains_key(key) {
+            self.evict(&mut buckets, now);
+        }
+        let bucket = buckets.get(key).copied().unwrap_or(Bucket {
+            tokens: self.burst as f64,
+            updated_at: now,
+        });
+        let mut bucket = self.refill(bucket, now);
+        let outcome = if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(RateLimited {
+                retry_after: self.refill_interval.mul_f64(1.0 - bucket.tokens),
+            })
+        };
+        buckets.insert(key.to_owned(), bucket);
+        outcome
+    }
+
+    /// Makes room for new keys. Eviction frees a tenth of the capacity, so the scan
+    /// only runs once every so many new keys rather than on every request.
+    fn evict(&self, buckets: &mut HashMap<String, Bucket>, now: Instant) {
+        buckets.retain(|_, bucket| self.refill(*bucket, now).tokens < self.burst as f64);
+        let target = self.capacity - (self.capacity / 10).max(1);
+        if buckets.len() <= target {
+            return;
+        }
+        let excess = buckets.len() - target;
+        let mut by_age: Vec<(Instant, String)> = buckets
+            .iter()
+            .map(|(key, bucket)| (bucket.updated_at, key.clone()))
+            .collect();
+        by_age.select_nth_unstable(excess - 1);
+        for (_, key) in &by_age[..excess] {
+            buckets.remove(key);
+        }
+    }
+
+    fn refill(&self, bucket: Bucket, now: Instant) -> Bucket {
+        let elapsed = now.saturating_duration_since(bucket.updated_at);
+        let refilled = if self.refill_interval.is_zero() {
+            f64::INFINITY
+        } else {
+            elapsed.as_secs_f64() / self.refill_interval.as_secs_f64()
+        };
+        Bucket {
+            tokens: (bucket.tokens + refilled).min(self.burst as f64),
+            updated_at: now,
+        }
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+#[error("Too many requests. Try again later.")]
+pub struct RateLimited {
+    pub retry_after: Duration,
+}
+
+impl ResponseError for RateLimited {
+    fn status_code(&self) -> StatusCode {
+        StatusCode::TOO_MANY_REQUESTS
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let mut response =
+            ProblemDetails::new(self.status_code(), self.to_string()).into_response();
+        // `Retry-After` only takes whole seconds: round up, so that a client waiting
+        // exactly as long does get a token.
+        let seconds = self.retry_after.as_secs() + u64::from(self.retry_after.subsec_nanos() > 0);
+        response
+            .headers_mut()
+            .insert(RETRY_AFTER, HeaderValue::from(seconds));
+        response
+    }
+}
+
+/// The limits applied to `POST /subscriptions` and to the data export and erasure
+/// requests, shared by every worker.
+#[derive(Debug)]
+pub struct SubscriptionRateLimits {
+    pub per_ip: RateLimiter,
+    /// Keyed by the normalized address, so that nobody can flood a single inbox with
+    /// confirmation emails from many IPs.
+    pub per_email: RateLimiter,
+    /// The per-IP limit of data export and erasure requests. Separate from `per_ip`,
+    /// so that asking for one's data does not use up one's signups, and vice versa.
+    pub data_requests_per_ip: RateLimiter,
+    /// The header our reverse proxy puts the client's IP address in, if any.
+    pub trusted_proxy_header: Option<HeaderName>,
+}
+
+impl SubscriptionRateLimits {
+    /// The trusted proxy header when it is present and valid, the address of the peer
+    /// otherwise.
+    pub fn client_ip(&self, request: &HttpRequest) -> Option<IpAddr> {
+        self.trusted_proxy_header
+            .as_ref()
+            .and_then(|header| request.headers().get(header))
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.trim().parse().ok())
+            .or_else(|| request.peer_addr().map(|address| address.ip()))
+    }
+}
+
+/// Rejects the request with `429 Too Many Requests` once the client IP has used up its
+/// token bucket.
+pub async fn limit_subscriptions_per_ip(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    limit_per_ip(req, next, |limits| &limits.per_ip).await
+}
+
+/// Same as [`limit_subscriptions_per_ip`], for data export and erasure requests.
+pub async fn limit_data_requests_per_ip(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    limit_per_ip(req, next, |limits| &limits.data_requests_per_ip).await
+}
+
+async fn limit_per_ip(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+    limiter: impl Fn(&SubscriptionRateLimits) -> &RateLimiter,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let limits = &req
+        .app_data::<web::Data<SignupProtection>>()
+        .expect("The signup protection is not registered.")
+        .rate_limits;
+    if let Some(ip) = limits.client_ip(req.request()) {
+        if let Err(e) = limiter(limits).check(&ip.to_string()) {
+            tracing::warn!(client_ip = %ip, path = %req.path(), "Rate limited a request.");
+            return Ok(req.error_response(e).map_into_right_body());
+        }
+    }
+    next.call(req).await.map(|res| res.map_into_left_body())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use claims::{assert_err, assert_ok};
+
+    use super::RateLimiter;
+
+    fn limiter() -> RateLimiter {
+        RateLimiter::new(2, Duration::from_secs(10))
+    }
+
+    #[test]
+    fn the_burst_is_allowed_then_requests_are_rejected() {
+        let limiter = limiter();
+        let now = Instant::now();
+
+        assert_ok!(limiter.check_at("a", now));
+        assert_ok!(limiter.check_at("a", now));
+        let e = assert_err!(limiter.check_at("a", now));
+
+        assert_eq!(e.retry_after, Duration::from_secs(10));
+    }
+
+    #[test]
+    fn tokens_come_back_over_time() {
+        let limiter = limiter();
+        let now = Instant::now();
+        for _ in 0..2 {
+            limiter.check_at("a", now).unwrap();
+        }
+
+        let e = assert_err!(limiter.check_at("a", now + Duration::from_secs(5)));
+        assert_eq!(e.retry_after, Duration::from_secs(5));
+        assert_ok!(limiter.check_at("a", now + Duration::from_secs(10)));
+    }
+
+    #[test]
+    fn keys_have_their_own_bucket() {
+        let limiter = limiter();
+        let now = Instant::now();
+        for _ in 0..2 {
+            limiter.check_at("a", now).unwrap();
+        }
+
+        assert_ok!(limiter.check_at("b", now));
+    }
+
+    #[test]
+    fn a_bucket_never_holds_more_than_the_burst() {
+        let limiter = limiter();
+        let now = Instant::now();
+        limiter.check_at("a", now).unwrap();
+        let later = now + Duration::from_secs(3600);
+
+        assert_ok!(limiter.check_at("a", later));
+        assert_ok!(limiter.check_at("a", later));
+        assert_err!(limiter.check_at("a", later));
+    }
+
+    #[test]
+    fn the_number_of_tracked_keys_is_capped() {
+        let mut limiter = limiter();
+        limiter.capacity = 10;
+        let now = Instant::now();
+
+        // Every bucket is drained, so none of them can be dropped for being full.
+        for i in 0..100 {
+            let at = now + Duration::from_millis(i);
+            limiter.check_at(&i.to_string(), at).unwrap();
+            limiter.check_at(&i.to_string(), at).unwrap();
+            assert!(limiter.buckets.lock().unwrap().len() <= 10);
+        }
+    }
+
+    #[test]
+    fn the_least_recently_used_keys_are_evicted_first() {
+        let mut limiter = limiter();
+        limiter.capacity = 10;
+        let now = Instant::now();
+        for i in 0..10 {
+            let at = now + Duration::from_millis(i);
+            limiter.check_at(&i.to_string(), at).unwrap();
+            limiter.check_at(&i.to_string(), at).unwrap();
+        }
+        // Touching the oldest key keeps it around.
+        let later = now + Duration::from_millis(10);
+        assert_err!(limiter.check_at("0", later));
+
+        limiter.check_at("new", later).unwrap();
+
+        let buckets = limiter.buckets.lock().unwrap();
+        assert!(buckets.contains_key("0"));
+        assert!(buckets.contains_key("new"));
+        assert!(!buckets.contains_key("1"));
+    }
+}
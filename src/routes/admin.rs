@@ -0,0 +1,58 @@
+use actix_web::{http::header::ContentType, web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    authentication::UserId,
+    session_state::TypedSession,
+    utils::{e500, escape_html, see_other},
+};
+
+pub async fn admin_dashboard(
+    user_id: web::ReqData<UserId>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let username = get_username(**user_id, &pool).await.map_err(e500)?;
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Admin dashboard</title>
+</head>
+<body>
+    <p>Welcome {username}!</p>
+    <form name="logoutForm" action="/admin/logout" method="post">
+        <input type="submit" value="Logout">
+    </form>
+</body>
+</html>"#,
+            username = escape_html(&username)
+        )))
+}
+
+pub async fn log_out(session: TypedSession) -> HttpResponse {
+    session.log_out();
+    FlashMessage::info("You have successfully logged out.").send();
+    see_other("/login")
+}
+
+#[tracing::instrument(name = "Get username", skip(pool))]
+pub async fn get_username(user_id: Uuid, pool: &PgPool) -> Result<String, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT username
+        FROM users
+        WHERE user_id = $1
+        "#,
+        user_id,
+    )
+    .fetch_one(pool)
+    .await
+    .context("Failed to perform a query to retrieve a username.")?;
+    Ok(row.username)
+}
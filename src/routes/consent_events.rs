@@ -0,0 +1,57 @@
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::utils::e500;
+
+/// How many events a single query returns at most, most recent first.
+const MAX_CONSENT_EVENTS: i64 = 500;
+
+#[derive(serde::Deserialize)]
+pub struct ConsentEventsQuery {
+    /// Only the events of this subscriber. Matched case-insensitively.
+    email: Option<String>,
+}
+
+#[derive(serde::Serialize)]
+pub struct ConsentEventRecord {
+    pub subscriber_id: Uuid,
+    pub email: String,
+    /// The slug of the list consent was given for.
+    pub list: String,
+    pub event_type: String,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+    pub source_page: Option<String>,
+    pub policy_version: String,
+    pub occurred_at: DateTime<Utc>,
+}
+
+#[tracing::instrument(name = "List consent events", skip(query, pool))]
+pub async fn list_consent_events(
+    query: web::Query<ConsentEventsQuery>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let events = sqlx::query_as!(
+        ConsentEventRecord,
+        r#"
+        SELECT c.subscriber_id, s.email, l.slug as list, c.event_type, c.ip_address,
+            c.user_agent, c.source_page, c.policy_version, c.occurred_at
+        FROM consent_events c
+        JOIN subscriptions s ON s.id = c.subscriber_id
+        JOIN lists l ON l.id = c.list_id
+        WHERE $1::TEXT IS NULL OR lower(s.email) = lower($1)
+        ORDER BY c.occurred_at DESC
+        LIMIT $2
+        "#,
+        query.email,
+        MAX_CONSENT_EVENTS
+    )
+    .fetch_all(pool.get_ref())
+    .await
+    .context("Failed to retrieve consent events.")
+    .map_err(e500)?;
+    Ok(HttpResponse::Ok().json(events))
+}
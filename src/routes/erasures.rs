@@ -0,0 +1,83 @@
+use actix_web::{http::StatusCode, web, HttpResponse, ResponseError};
+use anyhow::Context;
+use sqlx::PgPool;
+
+use crate::{
+    authentication::UserId,
+    domain::SubscriberEmail,
+    erasure::{erase_subscriber, EmailHasher, ErasureRequester},
+    utils::error_chain_fmt,
+};
+
+#[derive(serde::Deserialize)]
+pub struct ErasureRequest {
+    email: String,
+}
+
+/// Erases a subscriber on behalf of an admin, e.g. for a request received by post.
+#[tracing::instrument(
+    name = "Erase a subscriber",
+    skip(body, pool, email_hasher, user_id),
+    fields(user_id = %*user_id)
+)]
+pub async fn create_erasure(
+    body: web::Json<ErasureRequest>,
+    pool: web::Data<PgPool>,
+    email_hasher: web::Data<EmailHasher>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, ErasureError> {
+    let email = SubscriberEmail::parse(body.0.email)
+        .map_err(|e| ErasureError::ValidationError(e.to_string()))?;
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let subscriber_id = sqlx::query_scalar!(
+        r#"SELECT id FROM subscriptions WHERE lower(email) = lower($1)"#,
+        email.as_ref()
+    )
+    .fetch_optional(&mut *transaction)
+    .await
+    .context("Failed to look up the subscriber.")?
+    .ok_or(ErasureError::UnknownSubscriber)?;
+    let erasure = erase_subscriber(
+        &mut transaction,
+        &email_hasher,
+        subscriber_id,
+        ErasureRequester::Admin(**user_id),
+    )
+    .await
+    .context("Failed to erase the subscriber.")?
+    .ok_or(ErasureError::UnknownSubscriber)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to erase a subscriber.")?;
+    Ok(HttpResponse::Ok().json(erasure))
+}
+
+#[derive(thiserror::Error)]
+pub enum ErasureError {
+    #[error("{0}")]
+    ValidationError(String),
+    #[error("There is no subscriber with this email address.")]
+    UnknownSubscriber,
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for ErasureError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for ErasureError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ErasureError::ValidationError(_) => StatusCode::BAD_REQUEST,
+            ErasureError::UnknownSubscriber => StatusCode::NOT_FOUND,
+            ErasureError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
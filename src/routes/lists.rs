@@ -0,0 +1,101 @@
+use actix_web::{http::StatusCode, web, HttpResponse, ResponseError};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{domain::ListSlug, utils::error_chain_fmt};
+
+#[derive(serde::Deserialize)]
+pub struct NewList {
+    slug: String,
+    name: String,
+}
+
+#[derive(serde::Serialize)]
+pub struct ListSummary {
+    pub slug: String,
+    pub name: String,
+    pub is_default: bool,
+    pub confirmed_subscribers: i64,
+    pub created_at: DateTime<Utc>,
+}
+
+#[tracing::instrument(name = "List mailing lists", skip(pool))]
+pub async fn list_mailing_lists(pool: web::Data<PgPool>) -> Result<HttpResponse, ListError> {
+    let lists = sqlx::query_as!(
+        ListSummary,
+        r#"
+        SELECT l.slug, l.name, l.is_default,
+            count(m.subscriber_id) FILTER (WHERE m.status = 'confirmed') as "confirmed_subscribers!",
+            l.created_at
+        FROM lists l
+        LEFT JOIN list_memberships m ON m.list_id = l.id
+        GROUP BY l.id
+        ORDER BY l.created_at
+        "#
+    )
+    .fetch_all(pool.get_ref())
+    .await
+    .context("Failed to retrieve the mailing lists.")?;
+    Ok(HttpResponse::Ok().json(lists))
+}
+
+/// Creates a list subscribers can join with its slug in the signup form.
+#[tracing::instrument(name = "Create a mailing list", skip(body, pool))]
+pub async fn create_mailing_list(
+    body: web::Json<NewList>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, ListError> {
+    let NewList { slug, name } = body.into_inner();
+    let slug = ListSlug::parse(slug).map_err(|e| ListError::ValidationError(e.to_string()))?;
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(ListError::ValidationError("A name is required.".into()));
+    }
+    let inserted = sqlx::query!(
+        r#"
+        INSERT INTO lists (id, slug, name)
+        VALUES ($1, $2, $3)
+        ON CONFLICT (slug) DO NOTHING
+        "#,
+        Uuid::new_v4(),
+        slug.as_ref(),
+        name
+    )
+    .execute(pool.get_ref())
+    .await
+    .context("Failed to store the mailing list.")?
+    .rows_affected()
+        > 0;
+    if !inserted {
+        return Err(ListError::AlreadyExists);
+    }
+    Ok(HttpResponse::Created().finish())
+}
+
+#[derive(thiserror::Error)]
+pub enum ListError {
+    #[error("{0}")]
+    ValidationError(String),
+    #[error("A list with this slug already exists.")]
+    AlreadyExists,
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for ListError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for ListError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ListError::ValidationError(_) => StatusCode::BAD_REQUEST,
+            ListError::AlreadyExists => StatusCode::CONFLICT,
+            ListError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
@@ -0,0 +1,116 @@
+use std::fmt::Write;
+
+use actix_web::{
+    error::InternalError,
+    http::{header::ContentType, StatusCode},
+    web, HttpResponse,
+};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use secrecy::Secret;
+use sqlx::PgPool;
+
+use crate::{
+    authentication::{validate_credentials, AuthError, Credentials},
+    session_state::TypedSession,
+    utils::{error_chain_fmt, see_other},
+};
+
+#[derive(serde::Deserialize)]
+pub struct LoginFormData {
+    username: String,
+    password: Secret<String>,
+}
+
+pub async fn login_form(flash_messages: IncomingFlashMessages) -> HttpResponse {
+    let mut error_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(error_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+    HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Login</title>
+</head>
+<body>
+    {error_html}
+    <form action="/login" method="post">
+        <label>Username
+            <input
+                type="text"
+                placeholder="Enter Username"
+                name="username"
+            >
+        </label>
+        <label>Password
+            <input
+                type="password"
+                placeholder="Enter Password"
+                name="password"
+            >
+        </label>
+        <button type="submit">Login</button>
+    </form>
+</body>
+</html>"#,
+        ))
+}
+
+#[tracing::instrument(
+    name = "Log in",
+    skip(form, pool, session),
+    fields(username=tracing::field::Empty, user_id=tracing::field::Empty)
+)]
+pub async fn login(
+    form: web::Form<LoginFormData>,
+    pool: web::Data<PgPool>,
+    session: TypedSession,
+) -> Result<HttpResponse, InternalError<LoginError>> {
+    let credentials = Credentials {
+        username: form.0.username,
+        password: form.0.password,
+    };
+    tracing::Span::current().record("username", tracing::field::display(&credentials.username));
+    let user_id = validate_credentials(credentials, &pool)
+        .await
+        .map_err(|e| match e {
+            AuthError::InvalidCredentials(_) => login_redirect(LoginError::AuthError(e.into())),
+            AuthError::UnexpectedError(_) => InternalError::new(
+                LoginError::UnexpectedError(e.into()),
+                StatusCode::INTERNAL_SERVER_ERROR,
+            ),
+        })?;
+    tracing::Span::current().record("user_id", tracing::field::display(&user_id));
+    // A fresh session key on login prevents session fixation.
+    session.renew();
+    session.insert_user_id(user_id).map_err(|e| {
+        InternalError::new(
+            LoginError::UnexpectedError(e.into()),
+            StatusCode::INTERNAL_SERVER_ERROR,
+        )
+    })?;
+    Ok(see_other("/admin/dashboard"))
+}
+
+/// Sends the user back to the login form, with the error shown as a flash message.
+fn login_redirect(e: LoginError) -> InternalError<LoginError> {
+    FlashMessage::error(e.to_string()).send();
+    InternalError::from_response(e, see_other("/login"))
+}
+
+#[derive(thiserror::Error)]
+pub enum LoginError {
+    #[error("Authentication failed")]
+    AuthError(#[source] anyhow::Error),
+    #[error("Something went wrong")]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for LoginError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
@@ -1,7 +1,29 @@
+mod admin;
+mod consent_events;
+mod erasures;
 mod health_check;
+mod lists;
+mod login;
+mod newsletters;
 mod subscriptions;
 mod subscriptions_confirm;
+mod subscriptions_erasure;
+mod subscriptions_export;
+mod subscriptions_unsubscribe;
+mod suppressions;
+mod webhooks;
 
+pub use admin::*;
+pub use consent_events::*;
+pub use erasures::*;
 pub use health_check::*;
+pub use lists::*;
+pub use login::*;
+pub use newsletters::*;
 pub use subscriptions::*;
 pub use subscriptions_confirm::*;
+pub use subscriptions_erasure::*;
+pub use subscriptions_export::*;
+pub use subscriptions_unsubscribe::*;
+pub use suppressions::*;
+pub use webhooks::*;
@@ -0,0 +1,240 @@
+use actix_web::{http::StatusCode, web, HttpRequest, HttpResponse, ResponseError};
+use anyhow::Context;
+use chrono::Utc;
+use sqlx::{Executor, PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::{
+    authentication::UserId,
+    domain::{ListSlug, SubscriberEmail},
+    idempotency::{save_response, try_processing, IdempotencyKey, NextAction, RequestFingerprint},
+    lists::find_list,
+    utils::error_chain_fmt,
+};
+
+#[derive(serde::Deserialize, serde::Serialize)]
+pub struct BodyData {
+    title: String,
+    content: Content,
+    /// The slug of the list to send the issue to. Defaults to the default list.
+    #[serde(default)]
+    list: Option<String>,
+}
+
+#[derive(serde::Deserialize, serde::Serialize)]
+pub struct Content {
+    html: String,
+    text: String,
+}
+
+#[derive(serde::Serialize)]
+pub struct PublishReport {
+    pub newsletter_issue_id: Uuid,
+    /// The slug of the list the issue was sent to.
+    pub list: String,
+    pub enqueued: usize,
+    pub failed: Vec<FailedDelivery>,
+}
+
+#[derive(serde::Serialize)]
+pub struct FailedDelivery {
+    pub email: String,
+    pub reason: String,
+}
+
+struct ConfirmedSubscriber {
+    email: SubscriberEmail,
+}
+
+/// Stores the issue and enqueues one delivery task per confirmed subscriber of its list.
+/// The emails themselves are sent by the background worker in `issue_delivery_worker`.
+///
+/// Requests must carry an `Idempotency-Key` header: retries of the same request get
+/// the original response back instead of publishing the issue twice.
+#[tracing::instrument(
+    name = "Publish a newsletter issue",
+    skip(body, request, pool, user_id),
+    fields(title = %body.title, user_id = %*user_id)
+)]
+pub async fn publish_newsletter(
+    body: web::Json<BodyData>,
+    request: HttpRequest,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, PublishError> {
+    let user_id = user_id.into_inner();
+    validate_title(&body.title).map_err(PublishError::ValidationError)?;
+    let list_slug = body
+        .list
+        .clone()
+        .map(ListSlug::parse)
+        .transpose()
+        .map_err(|e| PublishError::ValidationError(e.to_string()))?;
+    let idempotency_key = IdempotencyKey::from_headers(request.headers())
+        .map_err(PublishError::ValidationError)?
+        .ok_or_else(|| {
+            PublishError::ValidationError("The `Idempotency-Key` header is required.".into())
+        })?;
+    let request_fingerprint = RequestFingerprint::new(&request, &*body)
+        .context("Failed to fingerprint the publishing request.")?;
+    let next_action =
+        try_processing(&pool, &idempotency_key, *user_id, &request_fingerprint).await?;
+    let mut transaction = match next_action {
+        NextAction::StartProcessing(txn) => txn,
+        NextAction::ReturnSavedResponse(saved_response) => return Ok(saved_response),
+        NextAction::Conflict => return Ok(HttpResponse::Conflict().finish()),
+        NextAction::KeyReused => return Err(PublishError::IdempotencyKeyReused),
+    };
+    let list = find_list(&mut transaction, list_slug.as_ref())
+        .await
+        .context("Failed to look up the list.")?
+        .ok_or_else(|| PublishError::ValidationError("There is no such list.".into()))?;
+    let subscribers = get_confirmed_subscribers(&pool, list.id)
+        .await
+        .context("Failed to retrieve the list of confirmed subscribers.")?;
+    let mut recipients = Vec::new();
+    let mut failed = Vec::new();
+    for subscriber in subscribers {
+        match subscriber {
+            Ok(subscriber) => recipients.push(subscriber.email.as_ref().to_owned()),
+            Err(failure) => {
+                tracing::warn!(
+                    "Skipping a confirmed subscriber. Their stored contact details are invalid: {}",
+                    failure.reason
+                );
+                failed.push(failure);
+            }
+        }
+    }
+    let newsletter_issue_id = insert_newsletter_issue(&mut transaction, &body, list.id)
+        .await
+        .context("Failed to store newsletter issue details.")?;
+    enqueue_delivery_tasks(&mut transaction, newsletter_issue_id, &recipients)
+        .await
+        .context("Failed to enqueue delivery tasks.")?;
+    let response = HttpResponse::Accepted().json(PublishReport {
+        newsletter_issue_id,
+        list: list.slug,
+        enqueued: recipients.len(),
+        failed,
+    });
+    let response = save_response(transaction, &idempotency_key, *user_id, response).await?;
+    Ok(response)
+}
+
+/// The title becomes the subject of every email of the issue: it must be one that
+/// `EmailMessageBuilder::build` accepts, or each delivery would be dropped.
+fn validate_title(title: &str) -> Result<(), String> {
+    if title.trim().is_empty() {
+        return Err("The title cannot be empty.".into());
+    }
+    if title.contains(['\r', '\n']) {
+        return Err("The title must fit on a single line.".into());
+    }
+    Ok(())
+}
+
+#[derive(thiserror::Error)]
+pub enum PublishError {
+    #[error("{0}")]
+    ValidationError(String),
+    #[error("The idempotency key was already used for a different request.")]
+    IdempotencyKeyReused,
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for PublishError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for PublishError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            PublishError::ValidationError(_) => StatusCode::BAD_REQUEST,
+            PublishError::IdempotencyKeyReused => StatusCode::UNPROCESSABLE_ENTITY,
+            PublishError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+/// Subscribers who confirmed their membership of the list, and whose address has not
+/// bounced or complained since.
+#[tracing::instrument(name = "Get confirmed subscribers", skip(pool))]
+async fn get_confirmed_subscribers(
+    pool: &PgPool,
+    list_id: Uuid,
+) -> Result<Vec<Result<ConfirmedSubscriber, FailedDelivery>>, sqlx::Error> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT s.email
+        FROM list_memberships m
+        JOIN subscriptions s ON s.id = m.subscriber_id
+        WHERE m.list_id = $1 AND m.status = 'confirmed' AND s.status = 'active'
+        "#,
+        list_id
+    )
+    .fetch_all(pool)
+    .await?;
+    let confirmed_subscribers = rows
+        .into_iter()
+        .map(|r| match SubscriberEmail::parse(r.email.clone()) {
+            Ok(email) => Ok(ConfirmedSubscriber { email }),
+            Err(e) => Err(FailedDelivery {
+                email: r.email,
+                reason: e.to_string(),
+            }),
+        })
+        .collect();
+    Ok(confirmed_subscribers)
+}
+
+#[tracing::instrument(name = "Saving newsletter issue in the DB", skip_all)]
+async fn insert_newsletter_issue(
+    transaction: &mut Transaction<'_, Postgres>,
+    body: &BodyData,
+    list_id: Uuid,
+) -> Result<Uuid, sqlx::Error> {
+    let newsletter_issue_id = Uuid::new_v4();
+    let query = sqlx::query!(
+        r#"
+        INSERT INTO newsletter_issues (
+            newsletter_issue_id,
+            title,
+            text_content,
+            html_content,
+            published_at,
+            list_id
+        )
+        VALUES ($1, $2, $3, $4, $5, $6)
+        "#,
+        newsletter_issue_id,
+        body.title,
+        body.content.text,
+        body.content.html,
+        Utc::now(),
+        list_id
+    );
+    transaction.execute(query).await?;
+    Ok(newsletter_issue_id)
+}
+
+#[tracing::instrument(name = "Enqueueing delivery tasks", skip(transaction, recipients))]
+async fn enqueue_delivery_tasks(
+    transaction: &mut Transaction<'_, Postgres>,
+    newsletter_issue_id: Uuid,
+    recipients: &[String],
+) -> Result<(), sqlx::Error> {
+    let query = sqlx::query!(
+        r#"
+        INSERT INTO issue_delivery_queue (newsletter_issue_id, subscriber_email)
+        SELECT $1, email FROM UNNEST($2::text[]) AS recipients(email)
+        "#,
+        newsletter_issue_id,
+        recipients
+    );
+    transaction.execute(query).await?;
+    Ok(())
+}
@@ -1,20 +1,86 @@
-use actix_web::{web, HttpResponse};
+use actix_web::{
+    http::{
+        header::{AcceptLanguage, CacheControl, CacheDirective, ContentType, Header, Preference},
+        StatusCode,
+    },
+    web, HttpRequest, HttpResponse, ResponseError,
+};
+use anyhow::Context;
 use chrono::Utc;
 use rand::{distributions::Alphanumeric, thread_rng, Rng};
 use sqlx::{Executor, PgPool, Postgres, Transaction};
 use uuid::Uuid;
 
-use crate::{domain::NewSubscriber, email_client::EmailClient, startup::ApplicationBaseUrl};
+use crate::{
+    consent::{record_consent, ConsentContext, ConsentEvent},
+    domain::{NewSubscriber, NewSubscriberError},
+    email_log::EmailType,
+    email_templates::{EmailTemplates, RenderedEmail},
+    erasure::{restore_suppression, EmailHasher},
+    idempotency::{
+        save_response, try_processing, IdempotencyKey, NextAction, RequestFingerprint,
+        ANONYMOUS_USER_ID,
+    },
+    lists::{find_list, MailingList},
+    problem_details::{FieldError, ProblemDetails},
+    rate_limit::RateLimited,
+    startup::{ApplicationBaseUrl, SignupProtection},
+    transactional_email_worker::enqueue_email,
+    utils::error_chain_fmt,
+};
 
-#[derive(serde::Deserialize)]
+#[derive(serde::Deserialize, serde::Serialize)]
 pub struct SubscriptionFormData {
     pub name: String,
     pub email: String,
+    /// The slug of the list to subscribe to. Defaults to the default list.
+    #[serde(default)]
+    pub list: Option<String>,
+    /// The language to write to the subscriber in. Takes precedence over the
+    /// `Accept-Language` header.
+    #[serde(default)]
+    pub locale: Option<String>,
+    /// Honeypot: the signup form hides it from people, so only bots fill it in.
+    #[serde(default)]
+    pub website: Option<String>,
+    /// The page the form was on. Defaults to the `Referer` header.
+    #[serde(default)]
+    pub source_page: Option<String>,
+    /// Issued by `GET /subscriptions/form_token` when the form was displayed.
+    #[serde(default)]
+    pub form_token: Option<String>,
+    /// The token the CAPTCHA widget added to the form, under the name it picked.
+    #[serde(default, alias = "h-captcha-response", alias = "cf-turnstile-response")]
+    pub captcha_response: Option<String>,
+}
+
+#[derive(serde::Serialize)]
+struct FormToken {
+    form_token: String,
+}
+
+/// A token to embed in the signup form, proving when the form was displayed.
+pub async fn form_token(signup_protection: web::Data<SignupProtection>) -> HttpResponse {
+    let form_token = signup_protection
+        .bot_protection
+        .form_tokens
+        .issue(Utc::now());
+    HttpResponse::Ok()
+        .insert_header(CacheControl(vec![CacheDirective::NoStore]))
+        .json(FormToken { form_token })
 }
 
 #[tracing::instrument(
     name = "Adding a new subscriber",
-    skip(form, pool, email_client, base_url),
+    skip(
+        form,
+        request,
+        pool,
+        email_templates,
+        base_url,
+        signup_protection,
+        email_hasher
+    ),
     fields(
         subscriber_email = %form.email,
         subscriber_name = %form.name
@@ -22,74 +88,325 @@ pub struct SubscriptionFormData {
 )]
 pub async fn subscribe(
     form: web::Form<SubscriptionFormData>,
+    request: HttpRequest,
     pool: web::Data<PgPool>,
-    email_client: web::Data<EmailClient>,
+    email_templates: web::Data<EmailTemplates>,
     base_url: web::Data<ApplicationBaseUrl>,
-) -> HttpResponse {
-    let new_subscriber = match form.try_into() {
-        Ok(subscriber) => subscriber,
-        Err(_) => return HttpResponse::BadRequest().finish(),
+    signup_protection: web::Data<SignupProtection>,
+    email_hasher: web::Data<EmailHasher>,
+) -> Result<HttpResponse, SubscribeError> {
+    let client_ip = signup_protection.rate_limits.client_ip(&request);
+    let bot_signal = signup_protection
+        .bot_protection
+        .check(&form, client_ip)
+        .await
+        .context("Failed to check the signup form for bots.")?;
+    if let Some(bot_signal) = bot_signal {
+        // Bots get the same answer as everyone else, so that they cannot tell what
+        // gave them away.
+        tracing::warn!(?bot_signal, "Ignored a signup from a suspected bot.");
+        return Ok(HttpResponse::Ok().finish());
+    }
+    let locale = preferred_locale(form.locale.as_deref(), &request, &email_templates);
+    let mut consent = ConsentContext::from_request(&request);
+    if let Some(source_page) = form.source_page.clone() {
+        consent.source_page = Some(source_page);
+    }
+    let request_fingerprint = RequestFingerprint::new(&request, &*form)
+        .context("Failed to fingerprint the subscription request.")?;
+    let new_subscriber: NewSubscriber = form.try_into().map_err(SubscribeError::ValidationError)?;
+    signup_protection
+        .rate_limits
+        .per_email
+        .check(&new_subscriber.email.as_ref().to_lowercase())?;
+    let idempotency_key = IdempotencyKey::from_headers(request.headers())
+        .map_err(SubscribeError::InvalidIdempotencyKey)?;
+    let mut transaction = match &idempotency_key {
+        Some(key) => {
+            match try_processing(&pool, key, ANONYMOUS_USER_ID, &request_fingerprint).await? {
+                NextAction::StartProcessing(txn) => txn,
+                NextAction::ReturnSavedResponse(saved_response) => return Ok(saved_response),
+                NextAction::Conflict => return Ok(HttpResponse::Conflict().finish()),
+                NextAction::KeyReused => return Err(SubscribeError::IdempotencyKeyReused),
+            }
+        }
+        None => pool
+            .begin()
+            .await
+            .context("Failed to acquire a Postgres connection from the pool")?,
+    };
+    restore_suppression(&mut transaction, &email_hasher, &new_subscriber.email)
+        .await
+        .context("Failed to restore the suppression of an erased address.")?;
+    let list = find_list(&mut transaction, new_subscriber.list.as_ref())
+        .await
+        .context("Failed to look up the list in the database.")?
+        .ok_or(SubscribeError::UnknownList)?;
+    let subscriber_id = add_to_list(&mut transaction, &new_subscriber, &list, &locale).await?;
+    if let Some(subscriber_id) = subscriber_id {
+        record_consent(
+            &mut transaction,
+            subscriber_id,
+            list.id,
+            ConsentEvent::Subscribed,
+            &consent,
+        )
+        .await
+        .context("Failed to record the consent of a new subscriber.")?;
+        let subscription_token = generate_subscription_token();
+        store_token(
+            &mut transaction,
+            subscriber_id,
+            list.id,
+            &subscription_token,
+        )
+        .await
+        .context("Failed to store the confirmation token for a new subscriber.")?;
+        let email = confirmation_email(
+            &email_templates,
+            &new_subscriber,
+            &locale,
+            &base_url.0,
+            &subscription_token,
+        )?;
+        // The email is sent by a background worker: the caller gets the same answer, as
+        // fast, whether or not the address was already confirmed.
+        enqueue_email(
+            &mut transaction,
+            subscriber_id,
+            EmailType::Confirmation,
+            &email,
+        )
+        .await
+        .context("Failed to queue a confirmation email.")?;
+    }
+    let response = HttpResponse::Ok().finish();
+    let response = match idempotency_key {
+        Some(key) => save_response(transaction, &key, ANONYMOUS_USER_ID, response).await?,
+        None => {
+            transaction
+                .commit()
+                .await
+                .context("Failed to commit SQL transaction to store a new subscriber.")?;
+            response
+        }
     };
-    let mut transaction = match pool.begin().await {
-        Ok(txn) => txn,
-        Err(_) => return HttpResponse::InternalServerError().finish(),
+    Ok(response)
+}
+
+/// Adds the subscriber to the list, pending confirmation, creating the subscriber if
+/// needed. Returns the subscriber to send a confirmation email to, if any.
+///
+/// Whatever we find, the caller gets the same empty 200: the endpoint must not reveal
+/// whether an address is already on the list.
+async fn add_to_list(
+    transaction: &mut Transaction<'_, Postgres>,
+    new_subscriber: &NewSubscriber,
+    list: &MailingList,
+    locale: &str,
+) -> Result<Option<Uuid>, anyhow::Error> {
+    let existing = get_existing_subscriber(transaction, new_subscriber)
+        .await
+        .context("Failed to look up the subscriber in the database.")?;
+    let existing = match existing {
+        Some(existing) => Some(existing),
+        None => {
+            let inserted = insert_subscriber(transaction, new_subscriber, locale)
+                .await
+                .context("Failed to insert new subscriber in the database.")?;
+            if let Some(subscriber_id) = inserted {
+                insert_membership(transaction, list.id, subscriber_id)
+                    .await
+                    .context("Failed to add the subscriber to the list.")?;
+                return Ok(Some(subscriber_id));
+            }
+            // A concurrent sign-up for the same address got there first.
+            get_existing_subscriber(transaction, new_subscriber)
+                .await
+                .context("Failed to look up the subscriber in the database.")?
+        }
     };
-    let subscriber_id = match insert_subscriber(&mut transaction, &new_subscriber).await {
-        Ok(sub_id) => sub_id,
-        Err(_) => return HttpResponse::InternalServerError().finish(),
+    let subscriber_id = match existing {
+        None => anyhow::bail!("The address is taken by a subscriber we cannot find."),
+        Some(ExistingSubscriber { status, .. }) if status != "active" => {
+            tracing::info!(
+                status,
+                "The email address cannot be mailed. No confirmation email sent."
+            );
+            return Ok(None);
+        }
+        Some(ExistingSubscriber { id, .. }) => id,
     };
-    let subscription_token = generate_subscription_token();
-    if store_token(&mut transaction, subscriber_id, &subscription_token)
+    let membership_status = get_membership_status(transaction, list.id, subscriber_id)
         .await
-        .is_err()
-    {
-        return HttpResponse::InternalServerError().finish();
+        .context("Failed to look up the list membership in the database.")?;
+    match membership_status.as_deref() {
+        None => insert_membership(transaction, list.id, subscriber_id)
+            .await
+            .context("Failed to add the subscriber to the list.")?,
+        Some("pending_confirmation") => {}
+        Some("unsubscribed") => rejoin_list(transaction, list.id, subscriber_id)
+            .await
+            .context("Failed to add the subscriber back to the list.")?,
+        Some(_) => {
+            tracing::info!(
+                "The email address is already subscribed to the list. No confirmation email sent."
+            );
+            return Ok(None);
+        }
     }
-    if transaction.commit().await.is_err() {
-        return HttpResponse::InternalServerError().finish();
+    update_locale(transaction, subscriber_id, locale)
+        .await
+        .context("Failed to update the locale of a subscriber.")?;
+    Ok(Some(subscriber_id))
+}
+
+#[derive(thiserror::Error)]
+pub enum SubscribeError {
+    #[error(transparent)]
+    ValidationError(NewSubscriberError),
+    #[error("There is no such list.")]
+    UnknownList,
+    #[error("{0}")]
+    InvalidIdempotencyKey(String),
+    #[error("The idempotency key was already used for a different request.")]
+    IdempotencyKeyReused,
+    #[error(transparent)]
+    RateLimited(#[from] RateLimited),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for SubscribeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
     }
-    if send_confirmation_email(
-        &email_client,
-        new_subscriber,
-        &base_url.0,
-        &subscription_token,
-    )
-    .await
-    .is_err()
-    {
-        return HttpResponse::InternalServerError().finish();
+}
+
+impl ResponseError for SubscribeError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            SubscribeError::ValidationError(_)
+            | SubscribeError::UnknownList
+            | SubscribeError::InvalidIdempotencyKey(_) => StatusCode::BAD_REQUEST,
+            SubscribeError::IdempotencyKeyReused => StatusCode::UNPROCESSABLE_ENTITY,
+            SubscribeError::RateLimited(e) => e.status_code(),
+            SubscribeError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            SubscribeError::ValidationError(e) => {
+                ProblemDetails::new(self.status_code(), self.to_string())
+                    .with_errors(field_errors(e))
+                    .into_response()
+            }
+            SubscribeError::UnknownList => {
+                ProblemDetails::new(self.status_code(), self.to_string())
+                    .with_errors(vec![FieldError {
+                        field: "list",
+                        code: "unknown",
+                        detail: self.to_string(),
+                    }])
+                    .into_response()
+            }
+            SubscribeError::InvalidIdempotencyKey(_) | SubscribeError::IdempotencyKeyReused => {
+                ProblemDetails::new(self.status_code(), self.to_string()).into_response()
+            }
+            SubscribeError::RateLimited(e) => e.error_response(),
+            SubscribeError::UnexpectedError(_) => HttpResponse::build(self.status_code())
+                .content_type(ContentType::plaintext())
+                .body(self.to_string()),
+        }
     }
-    HttpResponse::Ok().finish()
 }
 
-#[tracing::instrument(
-    name = "Sending confirmation email to a new subscriber",
-    skip(email_client, new_subscriber, base_url, subscription_token)
-)]
-pub async fn send_confirmation_email(
-    email_client: &EmailClient,
-    new_subscriber: NewSubscriber,
+fn field_errors(e: &NewSubscriberError) -> Vec<FieldError> {
+    let name = e.name.as_ref().map(|e| FieldError {
+        field: "name",
+        code: e.code(),
+        detail: e.to_string(),
+    });
+    let email = e.email.as_ref().map(|e| FieldError {
+        field: "email",
+        code: e.code(),
+        detail: e.to_string(),
+    });
+    let list = e.list.as_ref().map(|e| FieldError {
+        field: "list",
+        code: e.code(),
+        detail: e.to_string(),
+    });
+    name.into_iter().chain(email).chain(list).collect()
+}
+
+/// The form's `locale` field wins if we have templates for it, then the first language
+/// of the `Accept-Language` header we have templates for, then the default locale.
+fn preferred_locale(
+    form_locale: Option<&str>,
+    request: &HttpRequest,
+    email_templates: &EmailTemplates,
+) -> String {
+    let accepted_languages = AcceptLanguage::parse(request)
+        .map(|header| header.ranked())
+        .unwrap_or_default();
+    let accepted_languages = accepted_languages
+        .iter()
+        .filter_map(|language| match language {
+            Preference::Specific(tag) => Some(tag.as_str()),
+            Preference::Any => None,
+        });
+    form_locale
+        .into_iter()
+        .chain(accepted_languages)
+        .find_map(|tag| email_templates.supported_locale(tag))
+        .unwrap_or(email_templates.default_locale())
+        .to_owned()
+}
+
+fn confirmation_email(
+    email_templates: &EmailTemplates,
+    new_subscriber: &NewSubscriber,
+    locale: &str,
     base_url: &str,
     subscription_token: &str,
-) -> Result<(), reqwest::Error> {
-    let confirmation_link = &format!(
+) -> Result<RenderedEmail, anyhow::Error> {
+    let confirmation_link = format!(
         "{}/subscriptions/confirm?subscription_token={}",
         base_url, subscription_token
     );
-    let html_body = &format!(
-        "Welcome to our newsletter! <br />\
-                    Click <a href=\"{}\">here</a> to confirm your subscription.",
-        confirmation_link
-    );
-    let text_body = &format!(
-        "Welcome to our newsletter!\nVisit {} to confirm your subscription.",
-        confirmation_link
-    );
-    email_client
-        .send_email(new_subscriber.email, "Welcome!", html_body, text_body)
-        .await
+    email_templates
+        .welcome(locale, new_subscriber.name.as_ref(), &confirmation_link)
+        .context("Failed to render the confirmation email.")
+}
+
+struct ExistingSubscriber {
+    id: Uuid,
+    status: String,
+}
+
+/// Looks up a subscriber by email, ignoring case, locking the row so that concurrent
+/// sign-ups for the same address queue up behind each other.
+#[tracing::instrument(
+    name = "Looking up an existing subscriber",
+    skip(new_subscriber, transaction)
+)]
+async fn get_existing_subscriber(
+    transaction: &mut Transaction<'_, Postgres>,
+    new_subscriber: &NewSubscriber,
+) -> Result<Option<ExistingSubscriber>, sqlx::Error> {
+    sqlx::query_as!(
+        ExistingSubscriber,
+        r#"SELECT id, status FROM subscriptions WHERE lower(email) = lower($1) FOR UPDATE"#,
+        new_subscriber.email.as_ref()
+    )
+    .fetch_optional(&mut **transaction)
+    .await
 }
 
+/// Stores a new subscriber. Returns `None` if the address is taken, whatever its case,
+/// e.g. by a concurrent sign-up that committed while we waited for it.
 #[tracing::instrument(
     name = "Saving new subscriber details in the DB",
     skip(new_subscriber, transaction)
@@ -97,23 +414,95 @@ pub async fn send_confirmation_email(
 pub async fn insert_subscriber(
     transaction: &mut Transaction<'_, Postgres>,
     new_subscriber: &NewSubscriber,
-) -> Result<Uuid, sqlx::Error> {
-    let user_id = Uuid::new_v4();
-    let query = sqlx::query!(
+    locale: &str,
+) -> Result<Option<Uuid>, sqlx::Error> {
+    sqlx::query_scalar!(
         r#"
-        INSERT INTO subscriptions (id, email, name, subscribed_at, status)
-        VALUES ($1, $2, $3, $4, 'pending_confirmation')
+        INSERT INTO subscriptions (id, email, name, subscribed_at, status, locale)
+        VALUES ($1, $2, $3, $4, 'active', $5)
+        ON CONFLICT ((lower(email))) DO NOTHING
+        RETURNING id
         "#,
-        &user_id,
+        Uuid::new_v4(),
         new_subscriber.email.as_ref(),
         new_subscriber.name.as_ref(),
-        Utc::now()
+        Utc::now(),
+        locale
+    )
+    .fetch_optional(&mut **transaction)
+    .await
+}
+
+#[tracing::instrument(name = "Looking up a list membership", skip(transaction))]
+async fn get_membership_status(
+    transaction: &mut Transaction<'_, Postgres>,
+    list_id: Uuid,
+    subscriber_id: Uuid,
+) -> Result<Option<String>, sqlx::Error> {
+    sqlx::query_scalar!(
+        r#"
+        SELECT status FROM list_memberships
+        WHERE list_id = $1 AND subscriber_id = $2
+        "#,
+        list_id,
+        subscriber_id
+    )
+    .fetch_optional(&mut **transaction)
+    .await
+}
+
+#[tracing::instrument(name = "Adding a subscriber to a list", skip(transaction))]
+async fn insert_membership(
+    transaction: &mut Transaction<'_, Postgres>,
+    list_id: Uuid,
+    subscriber_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    let query = sqlx::query!(
+        r#"
+        INSERT INTO list_memberships (list_id, subscriber_id, status, subscribed_at)
+        VALUES ($1, $2, 'pending_confirmation', now())
+        "#,
+        list_id,
+        subscriber_id
     );
-    transaction.execute(query).await.map_err(|e| {
-        tracing::error!("Failed to execute query: {:?}", e);
-        e
-    })?;
-    Ok(user_id)
+    transaction.execute(query).await?;
+    Ok(())
+}
+
+/// Puts a membership the subscriber ended back to pending confirmation: they opt in
+/// again, as if they had never been on the list.
+#[tracing::instrument(name = "Adding a subscriber back to a list", skip(transaction))]
+async fn rejoin_list(
+    transaction: &mut Transaction<'_, Postgres>,
+    list_id: Uuid,
+    subscriber_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    let query = sqlx::query!(
+        r#"
+        UPDATE list_memberships
+        SET status = 'pending_confirmation', subscribed_at = now(), unsubscribed_at = NULL
+        WHERE list_id = $1 AND subscriber_id = $2
+        "#,
+        list_id,
+        subscriber_id
+    );
+    transaction.execute(query).await?;
+    Ok(())
+}
+
+#[tracing::instrument(name = "Updating the locale of a subscriber", skip(transaction))]
+async fn update_locale(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    locale: &str,
+) -> Result<(), sqlx::Error> {
+    let query = sqlx::query!(
+        r#"UPDATE subscriptions SET locale = $1 WHERE id = $2"#,
+        locale,
+        subscriber_id
+    );
+    transaction.execute(query).await?;
+    Ok(())
 }
 
 #[tracing::instrument(
@@ -123,22 +512,22 @@ pub async fn insert_subscriber(
 pub async fn store_token(
     transaction: &mut Transaction<'_, Postgres>,
     subscriber_id: Uuid,
+    list_id: Uuid,
     subscription_token: &str,
 ) -> Result<(), sqlx::Error> {
     let query = sqlx::query!(
-        r#"INSERT INTO subscription_tokens (subscription_token, subscriber_id)
-        VALUES ($1, $2)"#,
+        r#"INSERT INTO subscription_tokens (subscription_token, subscriber_id, list_id)
+        VALUES ($1, $2, $3)"#,
         subscription_token,
-        subscriber_id
+        subscriber_id,
+        list_id
     );
-    transaction.execute(query).await.map_err(|e| {
-        tracing::error!("Failed to execute query: {:?}", e);
-        e
-    })?;
+    transaction.execute(query).await?;
     Ok(())
 }
 
-fn generate_subscription_token() -> String {
+/// A random token, hard enough to guess to stand in for the subscriber in links.
+pub fn generate_subscription_token() -> String {
     let mut rng = thread_rng();
     std::iter::repeat_with(|| rng.sample(Alphanumeric))
         .map(char::from)
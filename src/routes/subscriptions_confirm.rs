@@ -1,61 +1,163 @@
-use actix_web::{web, HttpResponse};
-use sqlx::PgPool;
+use actix_web::{http::StatusCode, web, HttpRequest, HttpResponse, ResponseError};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::{Executor, PgPool, Postgres, Transaction};
 use uuid::Uuid;
 
+use crate::{
+    consent::{record_consent, ConsentContext, ConsentEvent},
+    startup::ConfirmationTokenTtl,
+    utils::error_chain_fmt,
+};
+
 #[derive(serde::Deserialize)]
 pub struct Parameters {
     subscription_token: String,
 }
 
-#[tracing::instrument(name = "Confirm a pending subscriber", skip(parameters, pool))]
-pub async fn confirm(parameters: web::Query<Parameters>, pool: web::Data<PgPool>) -> HttpResponse {
-    let id = match get_subscriber_id_from_token(&pool, &parameters.subscription_token).await {
-        Ok(id) => id,
-        Err(_) => return HttpResponse::InternalServerError().finish(),
-    };
-    match id {
-        None => HttpResponse::Unauthorized().finish(),
-        Some(subscriber_id) => {
-            if confirm_subscriber(&pool, subscriber_id).await.is_err() {
-                return HttpResponse::InternalServerError().finish();
-            }
-            HttpResponse::Ok().finish()
+struct StoredToken {
+    subscriber_id: Uuid,
+    list_id: Uuid,
+    created_at: DateTime<Utc>,
+    consumed_at: Option<DateTime<Utc>>,
+}
+
+#[tracing::instrument(
+    name = "Confirm a pending subscriber",
+    skip(parameters, request, pool, token_ttl)
+)]
+pub async fn confirm(
+    parameters: web::Query<Parameters>,
+    request: HttpRequest,
+    pool: web::Data<PgPool>,
+    token_ttl: web::Data<ConfirmationTokenTtl>,
+) -> Result<HttpResponse, ConfirmError> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let token = get_token(&mut transaction, &parameters.subscription_token)
+        .await
+        .context("Failed to retrieve the subscription token.")?
+        .ok_or(ConfirmError::UnknownToken)?;
+    if token.consumed_at.is_some() {
+        return Err(ConfirmError::ConsumedToken);
+    }
+    if token.created_at + token_ttl.0 < Utc::now() {
+        return Err(ConfirmError::ExpiredToken);
+    }
+    consume_token(&mut transaction, &parameters.subscription_token)
+        .await
+        .context("Failed to mark the subscription token as used.")?;
+    let confirmed = confirm_membership(&mut transaction, token.list_id, token.subscriber_id)
+        .await
+        .context("Failed to update the membership status to `confirmed`.")?;
+    if confirmed {
+        record_consent(
+            &mut transaction,
+            token.subscriber_id,
+            token.list_id,
+            ConsentEvent::Confirmed,
+            &ConsentContext::from_request(&request),
+        )
+        .await
+        .context("Failed to record the consent of a confirmed subscriber.")?;
+    }
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to confirm a subscriber.")?;
+    Ok(HttpResponse::Ok().finish())
+}
+
+#[derive(thiserror::Error)]
+pub enum ConfirmError {
+    #[error("There is no subscriber associated with the provided token.")]
+    UnknownToken,
+    #[error("The subscription token has already been used.")]
+    ConsumedToken,
+    #[error(
+        "This confirmation link has expired. \
+        Subscribe again with the same email address to receive a new one."
+    )]
+    ExpiredToken,
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for ConfirmError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for ConfirmError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ConfirmError::UnknownToken | ConfirmError::ConsumedToken => StatusCode::UNAUTHORIZED,
+            ConfirmError::ExpiredToken => StatusCode::GONE,
+            ConfirmError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
 }
 
+/// Returns whether the subscriber was waiting for confirmation on this list: nothing
+/// changes for subscribers who have since unsubscribed from it.
 #[tracing::instrument(
-    name = "Set status `confirmed` for the given subscriber id",
-    skip(pool)
+    name = "Set status `confirmed` for the given list membership",
+    skip(transaction)
 )]
-pub async fn confirm_subscriber(pool: &PgPool, subscriber_id: Uuid) -> Result<(), sqlx::Error> {
-    sqlx::query!(
-        r#"UPDATE subscriptions SET status = 'confirmed' WHERE id = $1"#,
+pub async fn confirm_membership(
+    transaction: &mut Transaction<'_, Postgres>,
+    list_id: Uuid,
+    subscriber_id: Uuid,
+) -> Result<bool, sqlx::Error> {
+    let query = sqlx::query!(
+        r#"
+        UPDATE list_memberships
+        SET status = 'confirmed'
+        WHERE list_id = $1 AND subscriber_id = $2 AND status = 'pending_confirmation'
+        "#,
+        list_id,
         subscriber_id
-    )
-    .execute(pool)
-    .await
-    .map_err(|e| {
-        tracing::error!("Failed to execute query: {:?}", e);
-        e
-    })?;
-    Ok(())
+    );
+    let result = transaction.execute(query).await?;
+    Ok(result.rows_affected() == 1)
 }
 
-#[tracing::instrument(name = "Get subscriber_id from token", skip(subscription_token, pool))]
-pub async fn get_subscriber_id_from_token(
-    pool: &PgPool,
+/// Fetches the token, locking its row until the transaction ends so that two
+/// concurrent clicks on the same link cannot both consume it.
+#[tracing::instrument(name = "Get subscription token", skip(subscription_token, transaction))]
+async fn get_token(
+    transaction: &mut Transaction<'_, Postgres>,
     subscription_token: &str,
-) -> Result<Option<Uuid>, sqlx::Error> {
-    let result = sqlx::query!(
-        r#"SELECT subscriber_id FROM subscription_tokens WHERE subscription_token=$1"#,
+) -> Result<Option<StoredToken>, sqlx::Error> {
+    sqlx::query_as!(
+        StoredToken,
+        r#"
+        SELECT subscriber_id, list_id, created_at, consumed_at
+        FROM subscription_tokens
+        WHERE subscription_token = $1
+        FOR UPDATE
+        "#,
         subscription_token
     )
-    .fetch_optional(pool)
+    .fetch_optional(&mut **transaction)
     .await
-    .map_err(|e| {
-        tracing::error!("Failed to execute query: {:?}", e);
-        e
-    })?;
-    Ok(result.map(|r| r.subscriber_id))
+}
+
+#[tracing::instrument(
+    name = "Mark subscription token as used",
+    skip(subscription_token, transaction)
+)]
+async fn consume_token(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscription_token: &str,
+) -> Result<(), sqlx::Error> {
+    let query = sqlx::query!(
+        r#"UPDATE subscription_tokens SET consumed_at = now() WHERE subscription_token = $1"#,
+        subscription_token
+    );
+    transaction.execute(query).await?;
+    Ok(())
 }
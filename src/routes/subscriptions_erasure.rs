@@ -0,0 +1,263 @@
+use actix_web::{
+    http::{header::ContentType, StatusCode},
+    web, HttpResponse, ResponseError,
+};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::{Executor, PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use super::{
+    generate_subscription_token,
+    subscriptions_export::{get_subscriber, Subscriber},
+};
+use crate::{
+    domain::SubscriberEmail,
+    email_log::EmailType,
+    email_templates::{EmailTemplates, RenderedEmail},
+    erasure::{erase_subscriber, EmailHasher, ErasureRequester},
+    problem_details::ProblemDetails,
+    rate_limit::RateLimited,
+    startup::{ApplicationBaseUrl, ErasureLinkTtl, SignupProtection},
+    transactional_email_worker::enqueue_email,
+    utils::error_chain_fmt,
+};
+
+#[derive(serde::Deserialize)]
+pub struct ErasureRequestForm {
+    email: String,
+}
+
+#[derive(serde::Deserialize)]
+pub struct ErasureParameters {
+    token: String,
+}
+
+struct StoredErasureToken {
+    subscriber_id: Uuid,
+    created_at: DateTime<Utc>,
+}
+
+/// Emails the subscriber with `email` a link to confirm the erasure of their data.
+/// Like data exports, the link goes to the address on file, and the caller gets the
+/// same answer whether or not we know the address.
+#[tracing::instrument(
+    name = "Request an erasure",
+    skip(form, pool, email_templates, base_url, signup_protection, link_ttl),
+    fields(subscriber_email = %form.email)
+)]
+pub async fn request_erasure(
+    form: web::Form<ErasureRequestForm>,
+    pool: web::Data<PgPool>,
+    email_templates: web::Data<EmailTemplates>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    signup_protection: web::Data<SignupProtection>,
+    link_ttl: web::Data<ErasureLinkTtl>,
+) -> Result<HttpResponse, SubscriberErasureError> {
+    let email = SubscriberEmail::parse(form.0.email)
+        .map_err(|e| SubscriberErasureError::ValidationError(e.to_string()))?;
+    signup_protection
+        .rate_limits
+        .per_email
+        .check(&email.as_ref().to_lowercase())?;
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let Some(subscriber) = get_subscriber(&mut transaction, &email)
+        .await
+        .context("Failed to look up the subscriber.")?
+    else {
+        tracing::info!("No subscriber with this email address. No erasure link sent.");
+        return Ok(HttpResponse::Ok().finish());
+    };
+    let token = generate_subscription_token();
+    store_erasure_token(&mut transaction, subscriber.id, &token)
+        .await
+        .context("Failed to store the erasure token.")?;
+    let erasure_link = format!("{}/subscriptions/erasure?token={}", base_url.0, token);
+    let email = erasure_email(
+        &email_templates,
+        &subscriber,
+        &erasure_link,
+        link_ttl.0.num_hours(),
+    )?;
+    enqueue_email(
+        &mut transaction,
+        subscriber.id,
+        EmailType::ErasureRequest,
+        &email,
+    )
+    .await
+    .context("Failed to queue the erasure email.")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to store an erasure token.")?;
+    Ok(HttpResponse::Ok().finish())
+}
+
+/// Landing page for the link in the erasure email. Like the unsubscribe page, it only
+/// asks for confirmation: link scanners must not be able to erase anyone.
+#[tracing::instrument(name = "Show the erasure page", skip(parameters, pool, link_ttl))]
+pub async fn erasure_form(
+    parameters: web::Query<ErasureParameters>,
+    pool: web::Data<PgPool>,
+    link_ttl: web::Data<ErasureLinkTtl>,
+) -> Result<HttpResponse, SubscriberErasureError> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    get_valid_token(&mut transaction, &parameters.token, &link_ttl).await?;
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Delete your data</title>
+</head>
+<body>
+    <p>Do you want us to delete all the data we hold about you? You will stop receiving our newsletter. This cannot be undone.</p>
+    <form action="/subscriptions/erasure/confirm?token={}" method="post">
+        <button type="submit">Delete my data</button>
+    </form>
+</body>
+</html>"#,
+            // Tokens are alphanumeric, no escaping needed once we know the token exists.
+            parameters.token
+        )))
+}
+
+#[tracing::instrument(
+    name = "Confirm an erasure",
+    skip(parameters, pool, email_hasher, link_ttl)
+)]
+pub async fn confirm_erasure(
+    parameters: web::Query<ErasureParameters>,
+    pool: web::Data<PgPool>,
+    email_hasher: web::Data<EmailHasher>,
+    link_ttl: web::Data<ErasureLinkTtl>,
+) -> Result<HttpResponse, SubscriberErasureError> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let token = get_valid_token(&mut transaction, &parameters.token, &link_ttl).await?;
+    erase_subscriber(
+        &mut transaction,
+        &email_hasher,
+        token.subscriber_id,
+        ErasureRequester::Subscriber,
+    )
+    .await
+    .context("Failed to erase the subscriber.")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to erase a subscriber.")?;
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body("<p>Your data has been deleted. You will not hear from us again.</p>"))
+}
+
+#[derive(thiserror::Error)]
+pub enum SubscriberErasureError {
+    #[error("{0}")]
+    ValidationError(String),
+    #[error(transparent)]
+    RateLimited(#[from] RateLimited),
+    #[error("This link is not valid.")]
+    UnknownToken,
+    #[error("This link has expired. Ask for a new one.")]
+    ExpiredToken,
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for SubscriberErasureError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for SubscriberErasureError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            SubscriberErasureError::ValidationError(_) => StatusCode::BAD_REQUEST,
+            SubscriberErasureError::RateLimited(e) => e.status_code(),
+            SubscriberErasureError::UnknownToken => StatusCode::UNAUTHORIZED,
+            SubscriberErasureError::ExpiredToken => StatusCode::GONE,
+            SubscriberErasureError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            SubscriberErasureError::RateLimited(e) => e.error_response(),
+            SubscriberErasureError::UnexpectedError(_) => HttpResponse::new(self.status_code()),
+            _ => ProblemDetails::new(self.status_code(), self.to_string()).into_response(),
+        }
+    }
+}
+
+/// Erasure deletes the subscriber's tokens, so a token that still exists belongs to a
+/// subscriber who has not been erased yet.
+async fn get_valid_token(
+    transaction: &mut Transaction<'_, Postgres>,
+    token: &str,
+    link_ttl: &ErasureLinkTtl,
+) -> Result<StoredErasureToken, SubscriberErasureError> {
+    let token = sqlx::query_as!(
+        StoredErasureToken,
+        r#"
+        SELECT subscriber_id, created_at
+        FROM erasure_tokens
+        WHERE erasure_token = $1
+        "#,
+        token
+    )
+    .fetch_optional(&mut **transaction)
+    .await
+    .context("Failed to retrieve the erasure token.")?
+    .ok_or(SubscriberErasureError::UnknownToken)?;
+    if token.created_at + link_ttl.0 < Utc::now() {
+        return Err(SubscriberErasureError::ExpiredToken);
+    }
+    Ok(token)
+}
+
+#[tracing::instrument(name = "Store an erasure token", skip(transaction, token))]
+async fn store_erasure_token(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    token: &str,
+) -> Result<(), sqlx::Error> {
+    let query = sqlx::query!(
+        r#"
+        INSERT INTO erasure_tokens (erasure_token, subscriber_id)
+        VALUES ($1, $2)
+        "#,
+        token,
+        subscriber_id
+    );
+    transaction.execute(query).await?;
+    Ok(())
+}
+
+fn erasure_email(
+    email_templates: &EmailTemplates,
+    subscriber: &Subscriber,
+    erasure_link: &str,
+    valid_for_hours: i64,
+) -> Result<RenderedEmail, anyhow::Error> {
+    let locale = subscriber
+        .locale
+        .as_deref()
+        .unwrap_or(email_templates.default_locale());
+    email_templates
+        .erasure(locale, &subscriber.name, erasure_link, valid_for_hours)
+        .context("Failed to render the erasure email.")
+}
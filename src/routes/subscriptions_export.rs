@@ -0,0 +1,379 @@
+use actix_web::{
+    http::{
+        header::{CacheControl, CacheDirective, ContentDisposition},
+        StatusCode,
+    },
+    web, HttpResponse, ResponseError,
+};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::{Executor, PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use super::generate_subscription_token;
+use crate::{
+    domain::SubscriberEmail,
+    email_log::EmailType,
+    email_templates::{EmailTemplates, RenderedEmail},
+    problem_details::ProblemDetails,
+    rate_limit::RateLimited,
+    startup::{ApplicationBaseUrl, DataExportLinkTtl, SignupProtection},
+    transactional_email_worker::enqueue_email,
+    utils::error_chain_fmt,
+};
+
+#[derive(serde::Deserialize)]
+pub struct DataExportRequest {
+    email: String,
+}
+
+#[derive(serde::Deserialize)]
+pub struct DownloadParameters {
+    token: String,
+}
+
+/// Emails the subscriber with `email` a link to download everything we hold about
+/// them. The link goes to the address on file, whoever asks for it, and the caller
+/// gets the same answer whether or not we know the address.
+#[tracing::instrument(
+    name = "Request a data export",
+    skip(form, pool, email_templates, base_url, signup_protection, link_ttl),
+    fields(subscriber_email = %form.email)
+)]
+pub async fn request_data_export(
+    form: web::Form<DataExportRequest>,
+    pool: web::Data<PgPool>,
+    email_templates: web::Data<EmailTemplates>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    signup_protection: web::Data<SignupProtection>,
+    link_ttl: web::Data<DataExportLinkTtl>,
+) -> Result<HttpResponse, DataExportError> {
+    let email = SubscriberEmail::parse(form.0.email)
+        .map_err(|e| DataExportError::ValidationError(e.to_string()))?;
+    signup_protection
+        .rate_limits
+        .per_email
+        .check(&email.as_ref().to_lowercase())?;
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let Some(subscriber) = get_subscriber(&mut transaction, &email)
+        .await
+        .context("Failed to look up the subscriber.")?
+    else {
+        tracing::info!("No subscriber with this email address. No data export sent.");
+        return Ok(HttpResponse::Ok().finish());
+    };
+    let token = generate_subscription_token();
+    store_data_export_token(&mut transaction, subscriber.id, &token)
+        .await
+        .context("Failed to store the data export token.")?;
+    let download_link = format!("{}/subscriptions/export?token={}", base_url.0, token);
+    let email = data_export_email(
+        &email_templates,
+        &subscriber,
+        &download_link,
+        link_ttl.0.num_hours(),
+    )?;
+    enqueue_email(
+        &mut transaction,
+        subscriber.id,
+        EmailType::DataExport,
+        &email,
+    )
+    .await
+    .context("Failed to queue the data export email.")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to store a data export token.")?;
+    Ok(HttpResponse::Ok().finish())
+}
+
+/// Downloads the subscriber's data as a JSON document.
+#[tracing::instrument(name = "Download a data export", skip(parameters, pool, link_ttl))]
+pub async fn download_data_export(
+    parameters: web::Query<DownloadParameters>,
+    pool: web::Data<PgPool>,
+    link_ttl: web::Data<DataExportLinkTtl>,
+) -> Result<HttpResponse, DataExportError> {
+    let token = sqlx::query!(
+        r#"
+        SELECT subscriber_id, created_at
+        FROM data_export_tokens
+        WHERE data_export_token = $1
+        "#,
+        parameters.token
+    )
+    .fetch_optional(pool.get_ref())
+    .await
+    .context("Failed to retrieve the data export token.")?
+    .ok_or(DataExportError::UnknownToken)?;
+    if token.created_at + link_ttl.0 < Utc::now() {
+        return Err(DataExportError::ExpiredToken);
+    }
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let export = get_data_export(&mut transaction, token.subscriber_id)
+        .await
+        .context("Failed to gather the subscriber's data.")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to read a data export.")?;
+    Ok(HttpResponse::Ok()
+        .insert_header(CacheControl(vec![CacheDirective::NoStore]))
+        .insert_header(ContentDisposition::attachment("subscriber-data.json"))
+        .json(export))
+}
+
+#[derive(thiserror::Error)]
+pub enum DataExportError {
+    #[error("{0}")]
+    ValidationError(String),
+    #[error(transparent)]
+    RateLimited(#[from] RateLimited),
+    #[error("This download link is not valid.")]
+    UnknownToken,
+    #[error("This download link has expired. Ask for a new one.")]
+    ExpiredToken,
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for DataExportError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for DataExportError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            DataExportError::ValidationError(_) => StatusCode::BAD_REQUEST,
+            DataExportError::RateLimited(e) => e.status_code(),
+            DataExportError::UnknownToken => StatusCode::UNAUTHORIZED,
+            DataExportError::ExpiredToken => StatusCode::GONE,
+            DataExportError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            DataExportError::RateLimited(e) => e.error_response(),
+            DataExportError::UnexpectedError(_) => HttpResponse::new(self.status_code()),
+            _ => ProblemDetails::new(self.status_code(), self.to_string()).into_response(),
+        }
+    }
+}
+
+pub(super) struct Subscriber {
+    pub id: Uuid,
+    pub name: String,
+    pub locale: Option<String>,
+}
+
+#[tracing::instrument(name = "Look up a subscriber by email", skip(transaction, email))]
+pub(super) async fn get_subscriber(
+    transaction: &mut Transaction<'_, Postgres>,
+    email: &SubscriberEmail,
+) -> Result<Option<Subscriber>, sqlx::Error> {
+    sqlx::query_as!(
+        Subscriber,
+        r#"
+        SELECT id, name, locale
+        FROM subscriptions
+        WHERE lower(email) = lower($1)
+        "#,
+        email.as_ref()
+    )
+    .fetch_optional(&mut **transaction)
+    .await
+}
+
+#[tracing::instrument(name = "Store a data export token", skip(transaction, token))]
+async fn store_data_export_token(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    token: &str,
+) -> Result<(), sqlx::Error> {
+    let query = sqlx::query!(
+        r#"
+        INSERT INTO data_export_tokens (data_export_token, subscriber_id)
+        VALUES ($1, $2)
+        "#,
+        token,
+        subscriber_id
+    );
+    transaction.execute(query).await?;
+    Ok(())
+}
+
+fn data_export_email(
+    email_templates: &EmailTemplates,
+    subscriber: &Subscriber,
+    download_link: &str,
+    valid_for_hours: i64,
+) -> Result<RenderedEmail, anyhow::Error> {
+    let locale = subscriber
+        .locale
+        .as_deref()
+        .unwrap_or(email_templates.default_locale());
+    email_templates
+        .data_export(locale, &subscriber.name, download_link, valid_for_hours)
+        .context("Failed to render the data export email.")
+}
+
+/// Everything we hold about a subscriber.
+#[derive(serde::Serialize)]
+pub struct DataExport {
+    pub exported_at: DateTime<Utc>,
+    pub subscription: SubscriptionRecord,
+    pub lists: Vec<ListMembershipRecord>,
+    pub subscription_tokens: Vec<SubscriptionTokenRecord>,
+    pub consent_events: Vec<ConsentEventExport>,
+    pub delivery_history: Vec<DeliveryRecord>,
+    /// When the address was put on our do-not-email list, if it is on it.
+    pub suppressed_at: Option<DateTime<Utc>>,
+}
+
+#[derive(serde::Serialize)]
+pub struct SubscriptionRecord {
+    pub id: Uuid,
+    pub email: String,
+    pub name: String,
+    pub status: String,
+    pub locale: Option<String>,
+    pub subscribed_at: DateTime<Utc>,
+}
+
+#[derive(serde::Serialize)]
+pub struct ListMembershipRecord {
+    /// The slug of the list.
+    pub list: String,
+    pub status: String,
+    pub subscribed_at: DateTime<Utc>,
+    pub unsubscribed_at: Option<DateTime<Utc>>,
+    pub unsubscribe_token: String,
+}
+
+#[derive(serde::Serialize)]
+pub struct SubscriptionTokenRecord {
+    pub list: String,
+    pub subscription_token: String,
+    pub created_at: DateTime<Utc>,
+    pub consumed_at: Option<DateTime<Utc>>,
+}
+
+#[derive(serde::Serialize)]
+pub struct ConsentEventExport {
+    pub list: String,
+    pub event_type: String,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+    pub source_page: Option<String>,
+    pub policy_version: String,
+    pub occurred_at: DateTime<Utc>,
+}
+
+#[derive(serde::Serialize)]
+pub struct DeliveryRecord {
+    pub email_type: String,
+    pub newsletter_issue_title: Option<String>,
+    pub outcome: String,
+    pub provider_message_id: Option<String>,
+    pub submitted_at: Option<DateTime<Utc>>,
+    pub delivered_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[tracing::instrument(name = "Gather a subscriber's data", skip(transaction))]
+async fn get_data_export(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+) -> Result<DataExport, sqlx::Error> {
+    let subscription = sqlx::query_as!(
+        SubscriptionRecord,
+        r#"
+        SELECT id, email, name, status, locale, subscribed_at
+        FROM subscriptions
+        WHERE id = $1
+        "#,
+        subscriber_id
+    )
+    .fetch_one(&mut **transaction)
+    .await?;
+    let lists = sqlx::query_as!(
+        ListMembershipRecord,
+        r#"
+        SELECT l.slug as list, m.status, m.subscribed_at, m.unsubscribed_at,
+            m.unsubscribe_token
+        FROM list_memberships m
+        JOIN lists l ON l.id = m.list_id
+        WHERE m.subscriber_id = $1
+        ORDER BY m.subscribed_at
+        "#,
+        subscriber_id
+    )
+    .fetch_all(&mut **transaction)
+    .await?;
+    let subscription_tokens = sqlx::query_as!(
+        SubscriptionTokenRecord,
+        r#"
+        SELECT l.slug as list, t.subscription_token, t.created_at, t.consumed_at
+        FROM subscription_tokens t
+        JOIN lists l ON l.id = t.list_id
+        WHERE t.subscriber_id = $1
+        ORDER BY t.created_at
+        "#,
+        subscriber_id
+    )
+    .fetch_all(&mut **transaction)
+    .await?;
+    let consent_events = sqlx::query_as!(
+        ConsentEventExport,
+        r#"
+        SELECT l.slug as list, c.event_type, c.ip_address, c.user_agent, c.source_page,
+            c.policy_version, c.occurred_at
+        FROM consent_events c
+        JOIN lists l ON l.id = c.list_id
+        WHERE c.subscriber_id = $1
+        ORDER BY c.occurred_at
+        "#,
+        subscriber_id
+    )
+    .fetch_all(&mut **transaction)
+    .await?;
+    let delivery_history = sqlx::query_as!(
+        DeliveryRecord,
+        r#"
+        SELECT l.email_type, i.title as "newsletter_issue_title?", l.outcome,
+            l.provider_message_id, l.submitted_at, l.delivered_at, l.created_at
+        FROM email_log l
+        LEFT JOIN newsletter_issues i ON i.newsletter_issue_id = l.newsletter_issue_id
+        WHERE l.subscriber_id = $1
+        ORDER BY l.created_at
+        "#,
+        subscriber_id
+    )
+    .fetch_all(&mut **transaction)
+    .await?;
+    let suppressed_at = sqlx::query_scalar!(
+        r#"SELECT created_at FROM suppressions WHERE email = lower($1)"#,
+        subscription.email
+    )
+    .fetch_optional(&mut **transaction)
+    .await?;
+    Ok(DataExport {
+        exported_at: Utc::now(),
+        subscription,
+        lists,
+        subscription_tokens,
+        consent_events,
+        delivery_history,
+        suppressed_at,
+    })
+}
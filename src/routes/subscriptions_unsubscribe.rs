@@ -0,0 +1,145 @@
+use actix_web::{
+    http::{header::ContentType, StatusCode},
+    web, HttpResponse, ResponseError,
+};
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::utils::{error_chain_fmt, escape_html};
+
+#[derive(serde::Deserialize)]
+pub struct UnsubscribeParameters {
+    token: String,
+}
+
+/// Unsubscribe tokens are per list: the link in an issue only unsubscribes from the
+/// list the issue was sent to.
+struct Membership {
+    list_id: Uuid,
+    subscriber_id: Uuid,
+    list_name: String,
+}
+
+/// Landing page for the unsubscribe link in newsletters. It only asks for confirmation:
+/// link scanners and prefetchers issue `GET`s, so the actual change happens on `POST`.
+#[tracing::instrument(name = "Show the unsubscribe page", skip(parameters, pool))]
+pub async fn unsubscribe_form(
+    parameters: web::Query<UnsubscribeParameters>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, UnsubscribeError> {
+    let membership = get_membership_from_unsubscribe_token(&pool, &parameters.token)
+        .await
+        .context("Failed to look up the unsubscribe token.")?
+        .ok_or(UnsubscribeError::UnknownToken)?;
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Unsubscribe</title>
+</head>
+<body>
+    <p>Do you want to stop receiving {}?</p>
+    <form action="/subscriptions/unsubscribe?token={}" method="post">
+        <input type="hidden" name="List-Unsubscribe" value="One-Click">
+        <button type="submit">Unsubscribe</button>
+    </form>
+</body>
+</html>"#,
+            escape_html(&membership.list_name),
+            // Tokens are alphanumeric, no escaping needed once we know the token exists.
+            parameters.token
+        )))
+}
+
+/// Target of both the confirmation form and RFC 8058 one-click requests from mail
+/// clients (`List-Unsubscribe=One-Click` in the body). The body is not inspected:
+/// the token alone identifies the subscriber.
+#[tracing::instrument(name = "Unsubscribe a subscriber", skip(parameters, pool))]
+pub async fn unsubscribe(
+    parameters: web::Query<UnsubscribeParameters>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, UnsubscribeError> {
+    let membership = get_membership_from_unsubscribe_token(&pool, &parameters.token)
+        .await
+        .context("Failed to look up the unsubscribe token.")?
+        .ok_or(UnsubscribeError::UnknownToken)?;
+    unsubscribe_member(&pool, membership.list_id, membership.subscriber_id)
+        .await
+        .context("Failed to update the membership status to `unsubscribed`.")?;
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            "<p>You have been unsubscribed from {}. You will not receive further issues.</p>",
+            escape_html(&membership.list_name)
+        )))
+}
+
+#[derive(thiserror::Error)]
+pub enum UnsubscribeError {
+    #[error("There is no subscriber associated with the provided token.")]
+    UnknownToken,
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for UnsubscribeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for UnsubscribeError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            UnsubscribeError::UnknownToken => StatusCode::UNAUTHORIZED,
+            UnsubscribeError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+#[tracing::instrument(name = "Get list membership from unsubscribe token", skip(token, pool))]
+async fn get_membership_from_unsubscribe_token(
+    pool: &PgPool,
+    token: &str,
+) -> Result<Option<Membership>, sqlx::Error> {
+    sqlx::query_as!(
+        Membership,
+        r#"
+        SELECT m.list_id, m.subscriber_id, l.name as list_name
+        FROM list_memberships m
+        JOIN lists l ON l.id = m.list_id
+        WHERE m.unsubscribe_token = $1
+        "#,
+        token
+    )
+    .fetch_optional(pool)
+    .await
+}
+
+#[tracing::instrument(
+    name = "Set status `unsubscribed` for the given list membership",
+    skip(pool)
+)]
+async fn unsubscribe_member(
+    pool: &PgPool,
+    list_id: Uuid,
+    subscriber_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE list_memberships
+        SET status = 'unsubscribed', unsubscribed_at = now()
+        WHERE list_id = $1 AND subscriber_id = $2
+            AND status IN ('pending_confirmation', 'confirmed')
+        "#,
+        list_id,
+        subscriber_id
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
@@ -0,0 +1,137 @@
+use actix_web::{http::StatusCode, web, HttpResponse, ResponseError};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+
+use crate::{authentication::UserId, domain::SubscriberEmail, utils::error_chain_fmt};
+
+#[derive(serde::Deserialize)]
+pub struct NewSuppression {
+    email: String,
+    reason: String,
+    /// Who asked for the address to be blocked, e.g. `legal` or `support`.
+    source: String,
+}
+
+#[derive(serde::Serialize)]
+pub struct Suppression {
+    pub email: String,
+    pub reason: String,
+    pub source: String,
+    /// The admin who added the entry, if they still exist.
+    pub added_by: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[tracing::instrument(name = "List suppressed addresses", skip(pool))]
+pub async fn list_suppressions(pool: web::Data<PgPool>) -> Result<HttpResponse, SuppressionError> {
+    let suppressions = sqlx::query_as!(
+        Suppression,
+        r#"
+        SELECT s.email, s.reason, s.source, u.username as "added_by?", s.created_at
+        FROM suppressions s
+        LEFT JOIN users u ON u.user_id = s.added_by
+        ORDER BY s.created_at DESC
+        "#
+    )
+    .fetch_all(pool.get_ref())
+    .await
+    .context("Failed to retrieve the suppression list.")?;
+    Ok(HttpResponse::Ok().json(suppressions))
+}
+
+/// Blocks an address: no email of any kind will be sent to it, whatever the status of
+/// the matching subscriber.
+#[tracing::instrument(
+    name = "Suppress an address",
+    skip(body, pool, user_id),
+    fields(user_id = %*user_id)
+)]
+pub async fn add_suppression(
+    body: web::Json<NewSuppression>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, SuppressionError> {
+    let NewSuppression {
+        email,
+        reason,
+        source,
+    } = body.into_inner();
+    let email = SubscriberEmail::parse(email)
+        .map_err(|e| SuppressionError::ValidationError(e.to_string()))?;
+    let (reason, source) = (reason.trim(), source.trim());
+    if reason.is_empty() || source.is_empty() {
+        return Err(SuppressionError::ValidationError(
+            "Both a reason and a source are required.".into(),
+        ));
+    }
+    let inserted = sqlx::query!(
+        r#"
+        INSERT INTO suppressions (email, reason, source, added_by)
+        VALUES (lower($1), $2, $3, $4)
+        ON CONFLICT (email) DO NOTHING
+        "#,
+        email.as_ref(),
+        reason,
+        source,
+        **user_id
+    )
+    .execute(pool.get_ref())
+    .await
+    .context("Failed to store the suppression.")?
+    .rows_affected()
+        > 0;
+    if !inserted {
+        return Err(SuppressionError::AlreadySuppressed);
+    }
+    Ok(HttpResponse::Created().finish())
+}
+
+#[tracing::instrument(name = "Lift the suppression of an address", skip(pool))]
+pub async fn remove_suppression(
+    email: web::Path<String>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, SuppressionError> {
+    let deleted = sqlx::query!(
+        r#"DELETE FROM suppressions WHERE email = lower($1)"#,
+        email.as_str()
+    )
+    .execute(pool.get_ref())
+    .await
+    .context("Failed to delete the suppression.")?
+    .rows_affected()
+        > 0;
+    if !deleted {
+        return Err(SuppressionError::NotFound);
+    }
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[derive(thiserror::Error)]
+pub enum SuppressionError {
+    #[error("{0}")]
+    ValidationError(String),
+    #[error("The address is already suppressed.")]
+    AlreadySuppressed,
+    #[error("The address is not suppressed.")]
+    NotFound,
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for SuppressionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for SuppressionError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            SuppressionError::ValidationError(_) => StatusCode::BAD_REQUEST,
+            SuppressionError::AlreadySuppressed => StatusCode::CONFLICT,
+            SuppressionError::NotFound => StatusCode::NOT_FOUND,
+            SuppressionError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
@@ -0,0 +1,214 @@
+use actix_web::{
+    http::{
+        header::{self, HeaderValue},
+        StatusCode,
+    },
+    web, HttpRequest, HttpResponse, ResponseError,
+};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use secrecy::ExposeSecret;
+use sqlx::PgPool;
+use subtle::ConstantTimeEq;
+
+use crate::{
+    authentication::basic_authentication, configuration::PostmarkWebhookSettings,
+    utils::error_chain_fmt,
+};
+
+/// The webhook payloads we act upon, told apart by their `RecordType`.
+/// See <https://postmarkapp.com/developer/webhooks/webhooks-overview>.
+#[derive(serde::Deserialize, Debug)]
+#[serde(tag = "RecordType")]
+pub enum PostmarkEvent {
+    #[serde(rename_all = "PascalCase")]
+    Bounce {
+        /// `HardBounce`, `SoftBounce`, `Transient`, ...
+        #[serde(rename = "Type")]
+        bounce_type: String,
+        email: String,
+        /// Whether Postmark deactivated the address: it will refuse to send to it again.
+        #[serde(default)]
+        inactive: bool,
+    },
+    #[serde(rename_all = "PascalCase")]
+    SpamComplaint { email: String },
+    #[serde(rename_all = "PascalCase")]
+    Delivery {
+        #[serde(rename = "MessageID")]
+        message_id: String,
+        delivered_at: DateTime<Utc>,
+    },
+    /// Opens, clicks, subscription changes, ... Acknowledged and ignored.
+    #[serde(other)]
+    Other,
+}
+
+/// Receives bounce, spam complaint and delivery notifications from Postmark. Addresses
+/// that hard-bounce or complain are taken off the list for good: mailing them again
+/// hurts our sender reputation.
+///
+/// Postmark retries calls that do not get a `2xx`, so events about addresses we do not
+/// know are acknowledged too.
+#[tracing::instrument(
+    name = "Handle a Postmark webhook",
+    skip(request, event, pool, settings)
+)]
+pub async fn postmark_webhook(
+    request: HttpRequest,
+    event: web::Json<PostmarkEvent>,
+    pool: web::Data<PgPool>,
+    settings: web::Data<PostmarkWebhookSettings>,
+) -> Result<HttpResponse, WebhookError> {
+    authenticate(&request, &settings)?;
+    match event.into_inner() {
+        PostmarkEvent::Bounce {
+            bounce_type,
+            email,
+            inactive,
+        } if bounce_type == "HardBounce" || inactive => {
+            let updated = mark_bounced(&pool, &email)
+                .await
+                .context("Failed to mark a bounced subscriber.")?;
+            tracing::info!(
+                updated,
+                "The address hard-bounced. It will not be mailed again."
+            );
+        }
+        PostmarkEvent::Bounce { bounce_type, .. } => {
+            tracing::info!(bounce_type = %bounce_type, "Ignoring a temporary bounce.");
+        }
+        PostmarkEvent::SpamComplaint { email } => {
+            let updated = mark_complained(&pool, &email)
+                .await
+                .context("Failed to mark a subscriber who complained.")?;
+            tracing::info!(
+                updated,
+                "The recipient marked us as spam. They will not be mailed again."
+            );
+        }
+        PostmarkEvent::Delivery {
+            message_id,
+            delivered_at,
+        } => {
+            record_delivery(&pool, &message_id, delivered_at)
+                .await
+                .context("Failed to record a delivery.")?;
+        }
+        PostmarkEvent::Other => {}
+    }
+    Ok(HttpResponse::Ok().finish())
+}
+
+#[derive(thiserror::Error)]
+pub enum WebhookError {
+    #[error("Authentication failed.")]
+    AuthError(#[source] anyhow::Error),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for WebhookError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for WebhookError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            WebhookError::AuthError(_) => StatusCode::UNAUTHORIZED,
+            WebhookError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let mut response = HttpResponse::build(self.status_code());
+        if let WebhookError::AuthError(_) = self {
+            response.insert_header((
+                header::WWW_AUTHENTICATE,
+                HeaderValue::from_static(r#"Basic realm="webhooks""#),
+            ));
+        }
+        response.finish()
+    }
+}
+
+/// Postmark sends the credentials embedded in the webhook URL as HTTP Basic auth.
+fn authenticate(
+    request: &HttpRequest,
+    settings: &PostmarkWebhookSettings,
+) -> Result<(), WebhookError> {
+    let credentials = basic_authentication(request.headers()).map_err(WebhookError::AuthError)?;
+    // Compare in constant time, so that response times do not leak how much of the
+    // password was right.
+    let username_matches = credentials
+        .username
+        .as_bytes()
+        .ct_eq(settings.username.as_bytes());
+    let password_matches = credentials
+        .password
+        .expose_secret()
+        .as_bytes()
+        .ct_eq(settings.password.expose_secret().as_bytes());
+    if bool::from(username_matches & password_matches) {
+        Ok(())
+    } else {
+        Err(WebhookError::AuthError(anyhow::anyhow!(
+            "Invalid webhook credentials."
+        )))
+    }
+}
+
+/// Returns whether a subscriber was updated. Addresses that complained keep their
+/// status.
+#[tracing::instrument(name = "Set status `bounced` for the given email", skip(pool, email))]
+async fn mark_bounced(pool: &PgPool, email: &str) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+        UPDATE subscriptions
+        SET status = 'bounced'
+        WHERE lower(email) = lower($1) AND status = 'active'
+        "#,
+        email
+    )
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+/// Returns whether a subscriber was updated. A complaint trumps every other status:
+/// even if they unsubscribed, it is the best record of why they must not be mailed.
+#[tracing::instrument(
+    name = "Set status `complained` for the given email",
+    skip(pool, email)
+)]
+async fn mark_complained(pool: &PgPool, email: &str) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+        UPDATE subscriptions
+        SET status = 'complained'
+        WHERE lower(email) = lower($1) AND status != 'complained'
+        "#,
+        email
+    )
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+#[tracing::instrument(name = "Record the delivery of an email", skip(pool))]
+async fn record_delivery(
+    pool: &PgPool,
+    message_id: &str,
+    delivered_at: DateTime<Utc>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"UPDATE email_log SET delivered_at = $1 WHERE provider_message_id = $2"#,
+        delivered_at,
+        message_id
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
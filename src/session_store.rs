@@ -0,0 +1,137 @@
+use std::collections::HashMap;
+
+use actix_session::storage::{
+    generate_session_key, LoadError, SaveError, SessionKey, SessionStore, UpdateError,
+};
+use actix_web::cookie::time::Duration;
+use anyhow::Context;
+use sqlx::PgPool;
+
+/// Session state for `actix_session::SessionMiddleware`, persisted in the `sessions` table.
+///
+/// The cookie only carries the (signed) session key; everything else stays server-side.
+#[derive(Clone)]
+pub struct PgSessionStore {
+    pool: PgPool,
+}
+
+impl PgSessionStore {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+impl SessionStore for PgSessionStore {
+    async fn load(
+        &self,
+        session_key: &SessionKey,
+    ) -> Result<Option<HashMap<String, String>>, LoadError> {
+        let row = sqlx::query!(
+            r#"
+            SELECT state
+            FROM sessions
+            WHERE session_key = $1 AND expires_at > now()
+            "#,
+            session_key.as_ref()
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .context("Failed to load session state.")
+        .map_err(LoadError::Other)?;
+        row.map(|row| serde_json::from_value(row.state))
+            .transpose()
+            .context("Failed to deserialize session state.")
+            .map_err(LoadError::Deserialization)
+    }
+
+    async fn save(
+        &self,
+        session_state: HashMap<String, String>,
+        ttl: &Duration,
+    ) -> Result<SessionKey, SaveError> {
+        let state = serde_json::to_value(session_state)
+            .context("Failed to serialize session state.")
+            .map_err(SaveError::Serialization)?;
+        let session_key = generate_session_key();
+        sqlx::query!(
+            r#"
+            INSERT INTO sessions (session_key, state, expires_at)
+            VALUES ($1, $2, now() + make_interval(secs => $3))
+            "#,
+            session_key.as_ref(),
+            state,
+            ttl.as_seconds_f64()
+        )
+        .execute(&self.pool)
+        .await
+        .context("Failed to save session state.")
+        .map_err(SaveError::Other)?;
+        // Piggyback on new logins to get rid of sessions nobody will come back for.
+        sqlx::query!(r#"DELETE FROM sessions WHERE expires_at <= now()"#)
+            .execute(&self.pool)
+            .await
+            .context("Failed to delete expired sessions.")
+            .map_err(SaveError::Other)?;
+        Ok(session_key)
+    }
+
+    async fn update(
+        &self,
+        session_key: SessionKey,
+        session_state: HashMap<String, String>,
+        ttl: &Duration,
+    ) -> Result<SessionKey, UpdateError> {
+        let state = serde_json::to_value(&session_state)
+            .context("Failed to serialize session state.")
+            .map_err(UpdateError::Serialization)?;
+        let result = sqlx::query!(
+            r#"
+            UPDATE sessions
+            SET state = $2, expires_at = now() + make_interval(secs => $3)
+            WHERE session_key = $1
+            "#,
+            session_key.as_ref(),
+            state,
+            ttl.as_seconds_f64()
+        )
+        .execute(&self.pool)
+        .await
+        .context("Failed to update session state.")
+        .map_err(UpdateError::Other)?;
+        if result.rows_affected() == 0 {
+            // The session was deleted or expired in the meantime: start a new one.
+            return self.save(session_state, ttl).await.map_err(|e| match e {
+                SaveError::Serialization(e) => UpdateError::Serialization(e),
+                SaveError::Other(e) => UpdateError::Other(e),
+            });
+        }
+        Ok(session_key)
+    }
+
+    async fn update_ttl(&self, session_key: &SessionKey, ttl: &Duration) -> anyhow::Result<()> {
+        sqlx::query!(
+            r#"
+            UPDATE sessions
+            SET expires_at = now() + make_interval(secs => $2)
+            WHERE session_key = $1
+            "#,
+            session_key.as_ref(),
+            ttl.as_seconds_f64()
+        )
+        .execute(&self.pool)
+        .await
+        .context("Failed to update session TTL.")?;
+        Ok(())
+    }
+
+    async fn delete(&self, session_key: &SessionKey) -> anyhow::Result<()> {
+        sqlx::query!(
+            r#"DELETE FROM sessions WHERE session_key = $1"#,
+            session_key.as_ref()
+        )
+        .execute(&self.pool)
+        .await
+        .context("Failed to delete session.")?;
+        Ok(())
+    }
+}
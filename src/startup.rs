@@ -1,13 +1,33 @@
 use std::net::TcpListener;
 
-use actix_web::{dev::Server, web, App, HttpServer};
+use actix_session::{config::CookieContentSecurity, SessionMiddleware};
+use actix_web::{
+    cookie::{Key, SameSite},
+    dev::Server,
+    web, App, HttpServer,
+};
+use actix_web_flash_messages::{storage::CookieMessageStore, FlashMessagesFramework};
+use actix_web_lab::middleware::from_fn;
+use secrecy::ExposeSecret;
 use sqlx::{postgres::PgPoolOptions, PgPool};
 use tracing_actix_web::TracingLogger;
 
 use crate::{
-    configuration::{DbOptions, Settings},
+    authentication::reject_anonymous_users,
+    bot_protection::BotProtection,
+    configuration::{ApplicationSettings, DbOptions, PostmarkWebhookSettings, Settings},
     email_client::EmailClient,
-    routes::{confirm, health_check, subscribe},
+    email_templates::EmailTemplates,
+    erasure::EmailHasher,
+    rate_limit::{limit_data_requests_per_ip, limit_subscriptions_per_ip, SubscriptionRateLimits},
+    routes::{
+        add_suppression, admin_dashboard, confirm, confirm_erasure, create_erasure,
+        create_mailing_list, download_data_export, erasure_form, form_token, health_check,
+        list_consent_events, list_mailing_lists, list_suppressions, log_out, login, login_form,
+        postmark_webhook, publish_newsletter, remove_suppression, request_data_export,
+        request_erasure, subscribe, unsubscribe, unsubscribe_form,
+    },
+    session_store::PgSessionStore,
 };
 
 pub struct Application {
@@ -16,20 +36,17 @@ pub struct Application {
 }
 
 impl Application {
-    pub async fn build(settings: Settings) -> Result<Application, std::io::Error> {
+    pub async fn build(settings: Settings) -> Result<Application, anyhow::Error> {
         let pool = get_connection_pool(&settings.database_url);
-        let sender_email = settings
-            .email_client
-            .sender()
-            .expect("Invalid sender email address.");
-        let timeout = settings.email_client.timeout();
-        let email_client = EmailClient::new(
-            settings.email_client.base_url,
-            sender_email,
-            timeout,
-            settings.email_client.auth_token,
-        );
-        let listener = TcpListener::bind(&format!(
+        let email_client = settings.email_client.client();
+        let email_templates = settings.email_templates.load()?;
+        let signup_protection = SignupProtection {
+            rate_limits: settings.rate_limit.subscription_limits()?,
+            bot_protection: settings
+                .bot_protection
+                .bot_protection(settings.application.hmac_secret.clone()),
+        };
+        let listener = TcpListener::bind(format!(
             "{}:{}",
             settings.application.host, settings.application.port
         ))
@@ -40,7 +57,15 @@ impl Application {
             )
         });
         let port = listener.local_addr().unwrap().port();
-        let server = run(listener, pool, email_client, settings.application.base_url)?;
+        let server = run(
+            listener,
+            pool,
+            email_client,
+            email_templates,
+            settings.application,
+            settings.postmark_webhook,
+            signup_protection,
+        )?;
         Ok(Self { port, server })
     }
 
@@ -59,24 +84,129 @@ pub fn get_connection_pool(configutaion: &DbOptions) -> PgPool {
 
 pub struct ApplicationBaseUrl(pub String);
 
+/// The version of the privacy policy consent is currently given under.
+pub struct PrivacyPolicyVersion(pub String);
+
+/// How long a subscription confirmation link stays valid.
+pub struct ConfirmationTokenTtl(pub chrono::Duration);
+
+/// Everything standing between the public signup form and our subscriber list.
+#[derive(Debug)]
+pub struct SignupProtection {
+    pub rate_limits: SubscriptionRateLimits,
+    pub bot_protection: BotProtection,
+}
+
+/// How long the link to download a subscriber's data stays valid.
+pub struct DataExportLinkTtl(pub chrono::Duration);
+
+/// How long the link to confirm the erasure of a subscriber's data stays valid.
+pub struct ErasureLinkTtl(pub chrono::Duration);
+
 pub fn run(
     listener: TcpListener,
     connection: PgPool,
     email_client: EmailClient,
-    base_url: String,
+    email_templates: EmailTemplates,
+    application: ApplicationSettings,
+    postmark_webhook_settings: PostmarkWebhookSettings,
+    signup_protection: SignupProtection,
 ) -> Result<Server, std::io::Error> {
-    let base_url = web::Data::new(ApplicationBaseUrl(base_url));
+    let secret_key = Key::from(application.hmac_secret.expose_secret().as_bytes());
+    let message_store = CookieMessageStore::builder(secret_key.clone()).build();
+    let message_framework = FlashMessagesFramework::builder(message_store).build();
+    let session_store = PgSessionStore::new(connection.clone());
+    let confirmation_token_ttl =
+        web::Data::new(ConfirmationTokenTtl(application.confirmation_token_ttl()));
+    let data_export_link_ttl =
+        web::Data::new(DataExportLinkTtl(application.data_export_link_ttl()));
+    let erasure_link_ttl = web::Data::new(ErasureLinkTtl(application.erasure_link_ttl()));
+    let email_hasher = web::Data::new(EmailHasher::new(application.hmac_secret.clone()));
+    let privacy_policy_version =
+        web::Data::new(PrivacyPolicyVersion(application.privacy_policy_version));
+    let base_url = web::Data::new(ApplicationBaseUrl(application.base_url));
+    let postmark_webhook_settings = web::Data::new(postmark_webhook_settings);
+    let signup_protection = web::Data::new(signup_protection);
     let connection = web::Data::new(connection);
     let email_client = web::Data::new(email_client);
+    let email_templates = web::Data::new(email_templates);
     let server = HttpServer::new(move || {
         App::new()
+            .wrap(message_framework.clone())
+            .wrap(
+                SessionMiddleware::builder(session_store.clone(), secret_key.clone())
+                    .cookie_content_security(CookieContentSecurity::Signed)
+                    .cookie_http_only(true)
+                    .cookie_same_site(SameSite::Lax)
+                    .build(),
+            )
             .wrap(TracingLogger::default())
             .route("/health_check", web::get().to(health_check))
-            .route("/subscriptions", web::post().to(subscribe))
+            .service(
+                web::resource("/subscriptions")
+                    .wrap(from_fn(limit_subscriptions_per_ip))
+                    .route(web::post().to(subscribe)),
+            )
+            .route("/subscriptions/form_token", web::get().to(form_token))
             .route("/subscriptions/confirm", web::get().to(confirm))
+            .service(
+                web::resource("/subscriptions/export")
+                    .route(web::get().to(download_data_export))
+                    .route(
+                        web::post()
+                            .to(request_data_export)
+                            .wrap(from_fn(limit_data_requests_per_ip)),
+                    ),
+            )
+            .service(
+                web::resource("/subscriptions/erasure")
+                    .route(web::get().to(erasure_form))
+                    .route(
+                        web::post()
+                            .to(request_erasure)
+                            .wrap(from_fn(limit_data_requests_per_ip)),
+                    ),
+            )
+            .route(
+                "/subscriptions/erasure/confirm",
+                web::post().to(confirm_erasure),
+            )
+            .route(
+                "/subscriptions/unsubscribe",
+                web::get().to(unsubscribe_form),
+            )
+            .route("/subscriptions/unsubscribe", web::post().to(unsubscribe))
+            .route("/login", web::get().to(login_form))
+            .route("/login", web::post().to(login))
+            .route("/webhooks/postmark", web::post().to(postmark_webhook))
+            .service(
+                web::scope("/admin")
+                    .wrap(from_fn(reject_anonymous_users))
+                    .route("/dashboard", web::get().to(admin_dashboard))
+                    .route("/newsletters", web::post().to(publish_newsletter))
+                    .route("/consent_events", web::get().to(list_consent_events))
+                    .route("/erasures", web::post().to(create_erasure))
+                    .route("/lists", web::get().to(list_mailing_lists))
+                    .route("/lists", web::post().to(create_mailing_list))
+                    .route("/suppressions", web::get().to(list_suppressions))
+                    .route("/suppressions", web::post().to(add_suppression))
+                    .route(
+                        "/suppressions/{email}",
+                        web::delete().to(remove_suppression),
+                    )
+                    .route("/logout", web::post().to(log_out)),
+            )
             .app_data(connection.clone())
             .app_data(email_client.clone())
+            .app_data(email_templates.clone())
             .app_data(base_url.clone())
+            .app_data(confirmation_token_ttl.clone())
+            .app_data(privacy_policy_version.clone())
+            .app_data(data_export_link_ttl.clone())
+            .app_data(erasure_link_ttl.clone())
+            .app_data(email_hasher.clone())
+            .app_data(postmark_webhook_settings.clone())
+            .app_data(signup_protection.clone())
     })
     .listen(listener)?
     .run();
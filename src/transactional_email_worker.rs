@@ -0,0 +1,241 @@
+use std::time::Duration;
+
+use anyhow::Context;
+use sqlx::{Executor, PgPool, Postgres, Transaction};
+use tracing::{field::display, Span};
+use uuid::Uuid;
+
+use crate::{
+    configuration::Settings,
+    domain::SubscriberEmail,
+    email_client::{EmailClient, EmailMessage},
+    email_log::EmailType,
+    email_templates::RenderedEmail,
+    issue_delivery_worker::ExecutionOutcome,
+    outbound_email::{send_to_subscriber, OutboundEmailError},
+    startup::get_connection_pool,
+};
+
+/// How many times a failed email is attempted before it is dropped from the queue.
+const MAX_RETRIES: i16 = 5;
+
+struct Task {
+    id: Uuid,
+    subscriber_id: Uuid,
+    email_type: String,
+    subject: String,
+    html_body: String,
+    text_body: String,
+    n_retries: i16,
+    subscriber_email: String,
+    subscriber_status: String,
+}
+
+/// Queues `email` for the subscriber, as part of the caller's transaction. The worker
+/// sends it once the transaction is committed.
+#[tracing::instrument(name = "Queueing an email to a subscriber", skip(transaction, email))]
+pub async fn enqueue_email(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    email_type: EmailType,
+    email: &RenderedEmail,
+) -> Result<(), sqlx::Error> {
+    let query = sqlx::query!(
+        r#"
+        INSERT INTO transactional_email_queue (
+            id, subscriber_id, email_type, subject, html_body, text_body
+        )
+        VALUES ($1, $2, $3, $4, $5, $6)
+        "#,
+        Uuid::new_v4(),
+        subscriber_id,
+        email_type.as_str(),
+        email.subject,
+        email.html_body,
+        email.text_body
+    );
+    transaction.execute(query).await?;
+    Ok(())
+}
+
+/// Sends the next queued email, if any. Every attempt is recorded in the email log,
+/// including the ones that will be retried.
+#[tracing::instrument(
+    skip_all,
+    fields(email_id=tracing::field::Empty, email_type=tracing::field::Empty),
+    err
+)]
+pub async fn try_execute_task(
+    pool: &PgPool,
+    email_client: &EmailClient,
+) -> Result<ExecutionOutcome, sqlx::Error> {
+    let Some((mut transaction, task)) = dequeue_task(pool).await? else {
+        return Ok(ExecutionOutcome::EmptyQueue);
+    };
+    Span::current()
+        .record("email_id", display(task.id))
+        .record("email_type", display(&task.email_type));
+    if task.subscriber_status == "erased" {
+        tracing::info!("Skipping the email. The subscriber has been erased.");
+        delete_task(&mut transaction, &task).await?;
+        transaction.commit().await?;
+        return Ok(ExecutionOutcome::TaskCompleted);
+    }
+    let (email_type, message) = match queued_email(email_client, &task) {
+        Ok(email) => email,
+        Err(e) => {
+            tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "Skipping a queued email. It cannot be turned into a valid message."
+            );
+            delete_task(&mut transaction, &task).await?;
+            transaction.commit().await?;
+            return Ok(ExecutionOutcome::TaskCompleted);
+        }
+    };
+    let outcome = send_to_subscriber(
+        &mut transaction,
+        email_client,
+        task.subscriber_id,
+        email_type,
+        &message,
+    )
+    .await;
+    match outcome {
+        Ok(_) => delete_task(&mut transaction, &task).await?,
+        Err(OutboundEmailError::Database(e)) => return Err(e),
+        Err(OutboundEmailError::Email(e)) if !e.is_transient() => {
+            tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "Failed to send a queued email. The provider rejected it, giving up."
+            );
+            delete_task(&mut transaction, &task).await?;
+        }
+        Err(OutboundEmailError::Email(e)) if task.n_retries + 1 >= MAX_RETRIES => {
+            tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "Failed to send a queued email. Giving up after {} attempts.",
+                MAX_RETRIES
+            );
+            delete_task(&mut transaction, &task).await?;
+        }
+        Err(OutboundEmailError::Email(e)) => {
+            tracing::warn!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "Failed to send a queued email. Retrying later."
+            );
+            reschedule_task(&mut transaction, &task).await?;
+        }
+    }
+    transaction.commit().await?;
+    Ok(ExecutionOutcome::TaskCompleted)
+}
+
+fn queued_email(
+    email_client: &EmailClient,
+    task: &Task,
+) -> Result<(EmailType, EmailMessage), anyhow::Error> {
+    let email_type = EmailType::parse_transactional(&task.email_type)
+        .with_context(|| format!("Unknown email type '{}'.", task.email_type))?;
+    let recipient = SubscriberEmail::parse(task.subscriber_email.clone())
+        .map_err(|e| anyhow::anyhow!(e))
+        .context("The subscriber's stored email address is invalid.")?;
+    let message = email_client
+        .message(recipient, &task.subject)
+        .html_body(&task.html_body)
+        .text_body(&task.text_body)
+        .build()?;
+    Ok((email_type, message))
+}
+
+#[tracing::instrument(skip_all)]
+async fn dequeue_task(
+    pool: &PgPool,
+) -> Result<Option<(Transaction<'static, Postgres>, Task)>, sqlx::Error> {
+    let mut transaction = pool.begin().await?;
+    let task = sqlx::query_as!(
+        Task,
+        r#"
+        SELECT
+            q.id,
+            q.subscriber_id,
+            q.email_type,
+            q.subject,
+            q.html_body,
+            q.text_body,
+            q.n_retries,
+            s.email as subscriber_email,
+            s.status as subscriber_status
+        FROM transactional_email_queue q
+        JOIN subscriptions s ON s.id = q.subscriber_id
+        WHERE q.execute_after <= now()
+        ORDER BY q.execute_after
+        FOR UPDATE OF q
+        SKIP LOCKED
+        LIMIT 1
+        "#
+    )
+    .fetch_optional(&mut *transaction)
+    .await?;
+    Ok(task.map(|task| (transaction, task)))
+}
+
+#[tracing::instrument(skip_all)]
+async fn delete_task(
+    transaction: &mut Transaction<'static, Postgres>,
+    task: &Task,
+) -> Result<(), sqlx::Error> {
+    let query = sqlx::query!(
+        r#"DELETE FROM transactional_email_queue WHERE id = $1"#,
+        task.id
+    );
+    transaction.execute(query).await?;
+    Ok(())
+}
+
+/// Puts a failed email back in the queue, backing off exponentially between attempts.
+#[tracing::instrument(skip_all)]
+async fn reschedule_task(
+    transaction: &mut Transaction<'static, Postgres>,
+    task: &Task,
+) -> Result<(), sqlx::Error> {
+    let backoff_seconds = 2_f64.powi(task.n_retries.into()) * 30.0;
+    let query = sqlx::query!(
+        r#"
+        UPDATE transactional_email_queue
+        SET
+            n_retries = n_retries + 1,
+            execute_after = now() + make_interval(secs => $2)
+        WHERE id = $1
+        "#,
+        task.id,
+        backoff_seconds
+    );
+    transaction.execute(query).await?;
+    Ok(())
+}
+
+async fn worker_loop(pool: PgPool, email_client: EmailClient) -> Result<(), anyhow::Error> {
+    loop {
+        match try_execute_task(&pool, &email_client).await {
+            // People are waiting for these emails: poll more often than for issues.
+            Ok(ExecutionOutcome::EmptyQueue) => {
+                tokio::time::sleep(Duration::from_secs(1)).await;
+            }
+            Err(_) => {
+                tokio::time::sleep(Duration::from_secs(1)).await;
+            }
+            Ok(ExecutionOutcome::TaskCompleted) => {}
+        }
+    }
+}
+
+pub async fn run_worker_until_stopped(configuration: Settings) -> Result<(), anyhow::Error> {
+    let connection_pool = get_connection_pool(&configuration.database_url);
+    let email_client = configuration.email_client.client();
+    worker_loop(connection_pool, email_client).await
+}
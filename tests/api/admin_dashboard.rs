@@ -0,0 +1,51 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app};
+
+#[tokio_macros::test]
+async fn you_must_be_logged_in_to_access_the_admin_dashboard() {
+    let app = spawn_app().await;
+
+    let response = app.get_admin_dashboard().await;
+
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio_macros::test]
+async fn logout_clears_session_state() {
+    let app = spawn_app().await;
+
+    app.test_user.login(&app).await;
+    let html_page = app.get_admin_dashboard_html().await;
+    assert!(html_page.contains(&format!("Welcome {}", app.test_user.username)));
+
+    let response = app.post_logout().await;
+    assert_is_redirect_to(&response, "/login");
+
+    let html_page = app.get_login_html().await;
+    assert!(html_page.contains(r#"<p><i>You have successfully logged out.</i></p>"#));
+
+    let response = app.get_admin_dashboard().await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio_macros::test]
+async fn sessions_are_stored_server_side() {
+    let app = spawn_app().await;
+
+    app.test_user.login(&app).await;
+
+    let n_sessions = sqlx::query!(r#"SELECT count(*) as "count!" FROM sessions"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_sessions, 1);
+
+    app.post_logout().await;
+
+    let n_sessions = sqlx::query!(r#"SELECT count(*) as "count!" FROM sessions"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_sessions, 0);
+}
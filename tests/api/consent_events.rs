@@ -0,0 +1,133 @@
+use wiremock::{matchers::any, Mock, ResponseTemplate};
+
+use crate::helpers::{
+    assert_is_redirect_to, create_confirmed_subscriber, create_unconfirmed_subscriber, spawn_app,
+    TestApp,
+};
+
+async fn subscribe_from_browser(app: &TestApp, body: &str) -> reqwest::Response {
+    reqwest::Client::new()
+        .post(format!("http://{}/subscriptions", &app.address))
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .header(
+            "User-Agent",
+            "Mozilla/5.0 (X11; Linux x86_64) Firefox/131.0",
+        )
+        .header("Referer", "https://example.com/blog/welcome")
+        .body(body.to_owned())
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+async fn consent_events(app: &TestApp, email: Option<&str>) -> Vec<serde_json::Value> {
+    app.test_user.login(app).await;
+    app.get_consent_events(email).await.json().await.unwrap()
+}
+
+#[tokio_macros::test]
+async fn subscribing_records_how_consent_was_given() {
+    let app = spawn_app().await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    subscribe_from_browser(&app, "name=ursula&email=ursula_le_guin%40gmail.com")
+        .await
+        .error_for_status()
+        .unwrap();
+
+    let events = consent_events(&app, None).await;
+    assert_eq!(events.len(), 1);
+    let event = &events[0];
+    assert_eq!(event["email"], "ursula_le_guin@gmail.com");
+    assert_eq!(event["event_type"], "subscribed");
+    assert_eq!(event["ip_address"], "127.0.0.1");
+    assert_eq!(
+        event["user_agent"],
+        "Mozilla/5.0 (X11; Linux x86_64) Firefox/131.0"
+    );
+    assert_eq!(event["source_page"], "https://example.com/blog/welcome");
+    assert_eq!(event["policy_version"], "2026-10-18");
+}
+
+#[tokio_macros::test]
+async fn the_form_can_tell_which_page_it_was_on() {
+    let app = spawn_app().await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    subscribe_from_browser(
+        &app,
+        "name=ursula&email=ursula_le_guin%40gmail.com&source_page=https%3A%2F%2Fexample.com%2Fabout",
+    )
+    .await
+    .error_for_status()
+    .unwrap();
+
+    let events = consent_events(&app, None).await;
+    assert_eq!(events[0]["source_page"], "https://example.com/about");
+}
+
+#[tokio_macros::test]
+async fn confirming_records_a_second_consent_event() {
+    let app = spawn_app().await;
+
+    create_confirmed_subscriber(&app).await;
+
+    let events = consent_events(&app, Some("2hcompany@gmail.com")).await;
+    let event_types: Vec<_> = events.iter().map(|e| e["event_type"].clone()).collect();
+    assert_eq!(event_types, vec!["confirmed", "subscribed"]);
+    assert_eq!(events[0]["ip_address"], "127.0.0.1");
+}
+
+#[tokio_macros::test]
+async fn following_a_used_confirmation_link_records_nothing() {
+    let app = spawn_app().await;
+    let confirmation_links = create_unconfirmed_subscriber(&app).await;
+    reqwest::get(confirmation_links.html.clone()).await.unwrap();
+
+    reqwest::get(confirmation_links.html).await.unwrap();
+
+    assert_eq!(consent_events(&app, None).await.len(), 2);
+}
+
+#[tokio_macros::test]
+async fn consent_events_can_be_filtered_by_email() {
+    let app = spawn_app().await;
+    create_unconfirmed_subscriber(&app).await;
+
+    let events = consent_events(&app, Some("someone.else@gmail.com")).await;
+    let same_address = consent_events(&app, Some("2HCOMPANY@gmail.com")).await;
+
+    assert!(events.is_empty());
+    assert_eq!(same_address.len(), 1);
+}
+
+#[tokio_macros::test]
+async fn consent_events_cannot_be_changed_or_removed() {
+    let app = spawn_app().await;
+    create_unconfirmed_subscriber(&app).await;
+
+    let update = sqlx::query!("UPDATE consent_events SET policy_version = 'forged'")
+        .execute(&app.db_pool)
+        .await;
+    let delete = sqlx::query!("DELETE FROM consent_events")
+        .execute(&app.db_pool)
+        .await;
+
+    assert!(update.is_err());
+    assert!(delete.is_err());
+}
+
+#[tokio_macros::test]
+async fn you_must_be_logged_in_to_see_consent_events() {
+    let app = spawn_app().await;
+
+    let response = app.get_consent_events(None).await;
+
+    assert_is_redirect_to(&response, "/login");
+}
@@ -0,0 +1,279 @@
+use reqwest::Url;
+use wiremock::{
+    matchers::{any, method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::helpers::{create_confirmed_subscriber, newsletter_request_body, spawn_app, TestApp};
+
+/// Asks for the erasure of the test subscriber's data and returns the link we emailed.
+async fn request_erasure_link(app: &TestApp) -> Url {
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    app.post_erasure_request("2hcompany@gmail.com")
+        .await
+        .error_for_status()
+        .unwrap();
+    app.dispatch_all_pending_emails().await;
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    app.get_confirmation_links(&email_request).html
+}
+
+fn token(link: &Url) -> String {
+    link.query_pairs()
+        .find(|(name, _)| name == "token")
+        .unwrap()
+        .1
+        .into_owned()
+}
+
+async fn subscriber_status(app: &TestApp) -> String {
+    sqlx::query_scalar!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+}
+
+#[tokio_macros::test]
+async fn you_must_be_logged_in_to_erase_a_subscriber() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    let response = app.post_erasure("2hcompany@gmail.com").await;
+
+    assert_eq!(response.status().as_u16(), 303);
+    assert_eq!(subscriber_status(&app).await, "active");
+}
+
+#[tokio_macros::test]
+async fn erasing_an_unknown_or_invalid_address_is_rejected() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let unknown = app.post_erasure("nobody@gmail.com").await;
+    let invalid = app.post_erasure("not-an-email").await;
+
+    assert_eq!(unknown.status().as_u16(), 404);
+    assert_eq!(invalid.status().as_u16(), 400);
+}
+
+#[tokio_macros::test]
+async fn an_erasure_scrubs_personal_data_and_keeps_an_audit_record() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    let response = app.post_erasure("2HCompany@gmail.com").await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let subscriber = sqlx::query!("SELECT id, email, name, locale, status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(
+        subscriber.email,
+        format!("{}@erased.invalid", subscriber.id)
+    );
+    assert_eq!(subscriber.name, "");
+    assert_eq!(subscriber.locale, None);
+    assert_eq!(subscriber.status, "erased");
+    let tokens = sqlx::query_scalar!("SELECT count(*) FROM subscription_tokens")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(tokens, Some(0));
+    // Consent events stay, for the record of what was agreed to, without who agreed.
+    let consent_events = sqlx::query!("SELECT ip_address, user_agent FROM consent_events")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(consent_events.len(), 2);
+    assert!(consent_events
+        .iter()
+        .all(|e| e.ip_address.is_none() && e.user_agent.is_none()));
+    // So does the delivery log, for our statistics.
+    let deliveries = sqlx::query_scalar!("SELECT count(*) FROM email_log")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(deliveries, Some(1));
+    let erasure = sqlx::query!("SELECT email_hash, requested_by, admin_user_id FROM erasures")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(erasure.requested_by, "admin");
+    assert_eq!(erasure.admin_user_id, Some(app.test_user.user_id));
+    assert!(!erasure.email_hash.contains("2hcompany"));
+}
+
+#[tokio_macros::test]
+async fn an_erased_subscriber_can_subscribe_again() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    app.post_erasure("2hcompany@gmail.com").await;
+
+    create_confirmed_subscriber(&app).await;
+
+    let statuses = sqlx::query_scalar!("SELECT status FROM subscriptions ORDER BY status")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(statuses, vec!["active", "erased"]);
+}
+
+#[tokio_macros::test]
+async fn a_suppression_outlives_the_erasure_of_the_address() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    sqlx::query!(
+        "INSERT INTO suppressions (email, reason, source) VALUES ('2hcompany@gmail.com', 'Test', 'test')"
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    app.test_user.login(&app).await;
+
+    app.post_erasure("2hcompany@gmail.com").await;
+    let suppressions = sqlx::query_scalar!("SELECT count(*) FROM suppressions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    let response = app
+        .post_subscriptions("name=kotleta&email=2hcompany%40gmail.com".into())
+        .await;
+    app.dispatch_all_pending_emails().await;
+
+    // The address itself is gone...
+    assert_eq!(suppressions, Some(0));
+    // ...but it is still suppressed when it comes back.
+    assert_eq!(response.status().as_u16(), 200);
+    let restored = sqlx::query_scalar!("SELECT email FROM suppressions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(restored, "2hcompany@gmail.com");
+}
+
+#[tokio_macros::test]
+async fn a_subscriber_can_erase_their_data_through_the_emailed_link() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let link = request_erasure_link(&app).await;
+
+    // Opening the link only asks for confirmation.
+    let page = reqwest::get(link.clone()).await.unwrap();
+    assert_eq!(page.status().as_u16(), 200);
+    assert_eq!(subscriber_status(&app).await, "active");
+
+    let response = app.post_erasure_confirmation(&token(&link)).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(subscriber_status(&app).await, "erased");
+    let requested_by = sqlx::query_scalar!("SELECT requested_by FROM erasures")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(requested_by, "subscriber");
+    // The token went with the rest of the data.
+    let again = app.post_erasure_confirmation(&token(&link)).await;
+    assert_eq!(again.status().as_u16(), 401);
+}
+
+#[tokio_macros::test]
+async fn an_expired_erasure_link_is_rejected() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let link = request_erasure_link(&app).await;
+    sqlx::query!("UPDATE erasure_tokens SET created_at = now() - interval '25 hours'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    let page = reqwest::get(link.clone()).await.unwrap();
+    let response = app.post_erasure_confirmation(&token(&link)).await;
+
+    assert_eq!(page.status().as_u16(), 410);
+    assert_eq!(response.status().as_u16(), 410);
+    assert_eq!(subscriber_status(&app).await, "active");
+}
+
+#[tokio_macros::test]
+async fn an_erasure_request_for_an_unknown_address_sends_nothing() {
+    let app = spawn_app().await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.post_erasure_request("nobody@gmail.com").await;
+    app.dispatch_all_pending_emails().await;
+
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio_macros::test]
+async fn erasure_emails_the_provider_rejects_are_recorded_as_failed() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(422).set_body_json(serde_json::json!({
+            "ErrorCode": 406,
+            "Message": "You tried to send to a recipient that has been marked as inactive."
+        })))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.post_erasure_request("2hcompany@gmail.com").await;
+    app.dispatch_all_pending_emails().await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let logged = sqlx::query!(
+        "SELECT outcome, error_code FROM email_log WHERE email_type = 'erasure_request'"
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(logged.outcome, "failed");
+    assert_eq!(logged.error_code, Some(406));
+    // Retrying would fail the same way.
+    let n_queued =
+        sqlx::query_scalar!(r#"SELECT count(*) as "count!" FROM transactional_email_queue"#)
+            .fetch_one(&app.db_pool)
+            .await
+            .unwrap();
+    assert_eq!(n_queued, 0);
+}
+
+#[tokio_macros::test]
+async fn an_erasure_drops_the_subscriber_from_pending_deliveries() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    app.post_newsletters(newsletter_request_body(None)).await;
+
+    app.post_erasure("2hcompany@gmail.com").await;
+
+    let queued = sqlx::query_scalar!("SELECT count(*) FROM issue_delivery_queue")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(queued, Some(0));
+}
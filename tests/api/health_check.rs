@@ -7,7 +7,7 @@ async fn health_check_works() {
     let client = reqwest::Client::new();
 
     let response = client
-        .get(&format!("http://{}/health_check", &test_app.address))
+        .get(format!("http://{}/health_check", &test_app.address))
         .send()
         .await
         .expect("Failed to execute request to health_check.");
@@ -1,15 +1,25 @@
 use once_cell::sync::Lazy;
 use rand::{distributions::Alphanumeric, Rng};
 use reqwest::Url;
+use secrecy::{ExposeSecret, Secret};
 use sqlx::{
     postgres::{PgConnectOptions, PgPoolOptions},
     Connection, Executor, PgConnection, PgPool,
 };
-use wiremock::MockServer;
+use uuid::Uuid;
+use wiremock::{
+    matchers::{method, path},
+    Mock, MockServer, ResponseTemplate,
+};
 use zero2prod::{
-    configuration::get_configuration,
+    authentication::create_user,
+    configuration::{get_configuration, PostmarkWebhookSettings, Settings},
+    email_client::EmailClient,
+    email_templates::EmailTemplates,
+    issue_delivery_worker::{try_execute_task, ExecutionOutcome},
     startup::{get_connection_pool, Application},
     telemetry::{get_subscriber, init_subscriber},
+    transactional_email_worker,
 };
 
 static TRACING: Lazy<()> = Lazy::new(|| {
@@ -30,6 +40,43 @@ pub struct TestApp {
     pub port: u16,
     pub db_pool: PgPool,
     pub email_server: MockServer,
+    pub email_client: EmailClient,
+    pub email_templates: EmailTemplates,
+    pub test_user: TestUser,
+    pub api_client: reqwest::Client,
+    pub base_url: String,
+    pub postmark_webhook: PostmarkWebhookSettings,
+}
+
+#[derive(Debug)]
+pub struct TestUser {
+    pub user_id: Uuid,
+    pub username: String,
+    pub password: String,
+}
+
+impl TestUser {
+    pub fn generate() -> Self {
+        Self {
+            user_id: Uuid::new_v4(),
+            username: Uuid::new_v4().to_string(),
+            password: Uuid::new_v4().to_string(),
+        }
+    }
+
+    pub async fn login(&self, app: &TestApp) {
+        app.post_login(&serde_json::json!({
+            "username": &self.username,
+            "password": &self.password
+        }))
+        .await;
+    }
+
+    async fn store(&mut self, pool: &PgPool) {
+        self.user_id = create_user(&self.username, Secret::new(self.password.clone()), pool)
+            .await
+            .expect("Failed to store test user.");
+    }
 }
 
 pub struct ConfirmationLinks {
@@ -40,14 +87,272 @@ pub struct ConfirmationLinks {
 impl TestApp {
     pub async fn post_subscriptions(&self, body: String) -> reqwest::Response {
         reqwest::Client::new()
-            .post(&format!("http://{}/subscriptions", &self.address))
+            .post(format!("http://{}/subscriptions", &self.address))
+            .header("Content-Type", "application/x-www-form-urlencoded")
+            .body(body)
+            .send()
+            .await
+            .expect("Failed to execute remote request")
+    }
+
+    pub async fn get_form_token(&self) -> String {
+        let body: serde_json::Value = reqwest::Client::new()
+            .get(format!("http://{}/subscriptions/form_token", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .json()
+            .await
+            .unwrap();
+        body["form_token"].as_str().unwrap().to_owned()
+    }
+
+    pub async fn post_subscriptions_with_header(
+        &self,
+        body: String,
+        name: &str,
+        value: &str,
+    ) -> reqwest::Response {
+        reqwest::Client::new()
+            .post(format!("http://{}/subscriptions", &self.address))
             .header("Content-Type", "application/x-www-form-urlencoded")
+            .header(name, value)
             .body(body)
             .send()
             .await
             .expect("Failed to execute remote request")
     }
 
+    pub async fn post_subscriptions_with_language(
+        &self,
+        body: String,
+        accept_language: &str,
+    ) -> reqwest::Response {
+        self.post_subscriptions_with_header(body, "Accept-Language", accept_language)
+            .await
+    }
+
+    pub async fn post_newsletters(&self, body: serde_json::Value) -> reqwest::Response {
+        self.post_newsletters_with_key(body, &Uuid::new_v4().to_string())
+            .await
+    }
+
+    pub async fn post_newsletters_with_key(
+        &self,
+        body: serde_json::Value,
+        idempotency_key: &str,
+    ) -> reqwest::Response {
+        self.api_client
+            .post(format!("http://{}/admin/newsletters", &self.address))
+            .header("Idempotency-Key", idempotency_key)
+            .json(&body)
+            .send()
+            .await
+            .expect("Failed to execute remote request")
+    }
+
+    pub async fn get_lists(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("http://{}/admin/lists", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_list(&self, body: &serde_json::Value) -> reqwest::Response {
+        self.api_client
+            .post(format!("http://{}/admin/lists", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_suppressions(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("http://{}/admin/suppressions", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_suppression(&self, body: &serde_json::Value) -> reqwest::Response {
+        self.api_client
+            .post(format!("http://{}/admin/suppressions", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn delete_suppression(&self, email: &str) -> reqwest::Response {
+        self.api_client
+            .delete(format!(
+                "http://{}/admin/suppressions/{}",
+                &self.address, email
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_data_export_request(&self, email: &str) -> reqwest::Response {
+        reqwest::Client::new()
+            .post(format!("http://{}/subscriptions/export", &self.address))
+            .form(&[("email", email)])
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_erasure(&self, email: &str) -> reqwest::Response {
+        self.api_client
+            .post(format!("http://{}/admin/erasures", &self.address))
+            .json(&serde_json::json!({ "email": email }))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_erasure_request(&self, email: &str) -> reqwest::Response {
+        reqwest::Client::new()
+            .post(format!("http://{}/subscriptions/erasure", &self.address))
+            .form(&[("email", email)])
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    /// Submits the confirmation form of the erasure page.
+    pub async fn post_erasure_confirmation(&self, token: &str) -> reqwest::Response {
+        reqwest::Client::new()
+            .post(format!(
+                "http://{}/subscriptions/erasure/confirm",
+                &self.address
+            ))
+            .query(&[("token", token)])
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_consent_events(&self, email: Option<&str>) -> reqwest::Response {
+        let mut request = self
+            .api_client
+            .get(format!("http://{}/admin/consent_events", &self.address));
+        if let Some(email) = email {
+            request = request.query(&[("email", email)]);
+        }
+        request.send().await.expect("Failed to execute request.")
+    }
+
+    pub async fn get_unsubscribe(&self, token: &str) -> reqwest::Response {
+        reqwest::Client::new()
+            .get(format!(
+                "http://{}/subscriptions/unsubscribe",
+                &self.address
+            ))
+            .query(&[("token", token)])
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    /// Mimics the RFC 8058 one-click request mail clients send.
+    pub async fn post_unsubscribe(&self, token: &str) -> reqwest::Response {
+        reqwest::Client::new()
+            .post(format!(
+                "http://{}/subscriptions/unsubscribe",
+                &self.address
+            ))
+            .query(&[("token", token)])
+            .header("Content-Type", "application/x-www-form-urlencoded")
+            .body("List-Unsubscribe=One-Click")
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    /// Calls the webhook with the credentials Postmark is configured with.
+    pub async fn post_postmark_webhook(&self, event: &serde_json::Value) -> reqwest::Response {
+        reqwest::Client::new()
+            .post(format!("http://{}/webhooks/postmark", &self.address))
+            .basic_auth(
+                &self.postmark_webhook.username,
+                Some(self.postmark_webhook.password.expose_secret()),
+            )
+            .json(event)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_login<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("http://{}/login", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_login_html(&self) -> String {
+        self.api_client
+            .get(format!("http://{}/login", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn get_admin_dashboard(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("http://{}/admin/dashboard", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_admin_dashboard_html(&self) -> String {
+        self.get_admin_dashboard().await.text().await.unwrap()
+    }
+
+    pub async fn post_logout(&self) -> reqwest::Response {
+        self.api_client
+            .post(format!("http://{}/admin/logout", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    /// Sends every queued email: newsletter issues, then confirmation, data export and
+    /// erasure emails.
+    pub async fn dispatch_all_pending_emails(&self) {
+        loop {
+            if let ExecutionOutcome::EmptyQueue = try_execute_task(
+                &self.db_pool,
+                &self.email_client,
+                &self.email_templates,
+                &self.base_url,
+            )
+            .await
+            .unwrap()
+            {
+                break;
+            }
+        }
+        while let ExecutionOutcome::TaskCompleted =
+            transactional_email_worker::try_execute_task(&self.db_pool, &self.email_client)
+                .await
+                .unwrap()
+        {}
+    }
+
     pub fn get_confirmation_links(&self, email_request: &wiremock::Request) -> ConfirmationLinks {
         let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
         let get_link = |s: &str| {
@@ -68,7 +373,27 @@ impl TestApp {
     }
 }
 
+/// An issue for the list with the given slug, or the default list.
+pub fn newsletter_request_body(list: Option<&str>) -> serde_json::Value {
+    let mut body = serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>",
+        }
+    });
+    if let Some(list) = list {
+        body["list"] = list.into();
+    }
+    body
+}
+
 pub async fn spawn_app() -> TestApp {
+    spawn_app_with(|_| {}).await
+}
+
+/// Spawns the application with `configure` applied on top of the test configuration.
+pub async fn spawn_app_with(configure: impl FnOnce(&mut Settings)) -> TestApp {
     Lazy::force(&TRACING);
 
     let email_server = MockServer::start().await;
@@ -86,6 +411,13 @@ pub async fn spawn_app() -> TestApp {
         c.application.port = 0;
         c.database_url.0 = pg_options;
         c.email_client.base_url = email_server.uri();
+        // Tests assert on the exact number of requests the email server receives.
+        c.email_client.max_retries = 0;
+        // Every request of a test comes from the same IP, often for the same address.
+        c.rate_limit.per_ip.burst = 1_000;
+        c.rate_limit.per_email.burst = 1_000;
+        c.rate_limit.data_requests_per_ip.burst = 1_000;
+        configure(&mut c);
         c
     };
     configure_database(configuration.database_url.clone().0).await;
@@ -98,15 +430,69 @@ pub async fn spawn_app() -> TestApp {
     // Launch as a background task
     // tokio::spawn returns a handle to the spawned future,
     // but we have no use for it
-    let _ = tokio::spawn(server.run_until_stopped());
+    tokio::spawn(server.run_until_stopped());
+    let api_client = reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .cookie_store(true)
+        .build()
+        .unwrap();
+    let db_pool = get_connection_pool(&configuration.database_url);
+    let mut test_user = TestUser::generate();
+    test_user.store(&db_pool).await;
     TestApp {
         address,
         port,
-        db_pool: get_connection_pool(&configuration.database_url),
+        db_pool,
         email_server,
+        email_client: configuration.email_client.client(),
+        email_templates: configuration.email_templates.load().unwrap(),
+        test_user,
+        api_client,
+        base_url: configuration.application.base_url,
+        postmark_webhook: configuration.postmark_webhook,
     }
 }
 
+pub async fn create_unconfirmed_subscriber(app: &TestApp) -> ConfirmationLinks {
+    let body = "name=kotleta&email=2hcompany%40gmail.com";
+
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .named("Create unconfirmed subscriber")
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    app.post_subscriptions(body.into())
+        .await
+        .error_for_status()
+        .unwrap();
+    app.dispatch_all_pending_emails().await;
+
+    let email_request = &app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    app.get_confirmation_links(email_request)
+}
+
+pub async fn create_confirmed_subscriber(app: &TestApp) {
+    let confirmation_links = create_unconfirmed_subscriber(app).await;
+    reqwest::get(confirmation_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+}
+
+pub fn assert_is_redirect_to(response: &reqwest::Response, location: &str) {
+    assert_eq!(response.status().as_u16(), 303);
+    assert_eq!(response.headers().get("Location").unwrap(), location);
+}
+
 async fn configure_database(pg_options: PgConnectOptions) -> PgPool {
     let options_without_db = pg_options.clone().database("");
     let mut connection = PgConnection::connect_with(&options_without_db)
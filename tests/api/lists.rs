@@ -0,0 +1,255 @@
+use wiremock::{
+    matchers::{any, method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::helpers::{
+    create_confirmed_subscriber, newsletter_request_body, spawn_app, ConfirmationLinks, TestApp,
+};
+
+async fn create_list(app: &TestApp, slug: &str) {
+    app.test_user.login(app).await;
+    let response = app
+        .post_list(&serde_json::json!({"slug": slug, "name": "Rust Weekly"}))
+        .await;
+    assert_eq!(response.status().as_u16(), 201);
+}
+
+/// Subscribes the test address to `list` and returns the confirmation links.
+async fn subscribe_to(app: &TestApp, list: &str) -> ConfirmationLinks {
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    app.post_subscriptions(format!(
+        "name=kotleta&email=2hcompany%40gmail.com&list={}",
+        list
+    ))
+    .await
+    .error_for_status()
+    .unwrap();
+    app.dispatch_all_pending_emails().await;
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    app.get_confirmation_links(&email_request)
+}
+
+async fn membership_statuses(app: &TestApp) -> Vec<(String, String)> {
+    sqlx::query!(
+        r#"
+        SELECT l.slug, m.status
+        FROM list_memberships m
+        JOIN lists l ON l.id = m.list_id
+        ORDER BY l.slug
+        "#
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .unwrap()
+    .into_iter()
+    .map(|row| (row.slug, row.status))
+    .collect()
+}
+
+#[tokio_macros::test]
+async fn you_must_be_logged_in_to_manage_lists() {
+    let app = spawn_app().await;
+
+    let list = app.get_lists().await;
+    let create = app
+        .post_list(&serde_json::json!({"slug": "rust", "name": "Rust"}))
+        .await;
+
+    assert_eq!(list.status().as_u16(), 303);
+    assert_eq!(create.status().as_u16(), 303);
+}
+
+#[tokio_macros::test]
+async fn the_existing_list_is_the_default_one() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    create_list(&app, "rust-weekly").await;
+
+    let lists: serde_json::Value = app.get_lists().await.json().await.unwrap();
+
+    assert_eq!(lists.as_array().unwrap().len(), 2);
+    assert_eq!(lists[0]["slug"], "newsletter");
+    assert_eq!(lists[0]["is_default"], true);
+    assert_eq!(lists[0]["confirmed_subscribers"], 1);
+    assert_eq!(lists[1]["slug"], "rust-weekly");
+    assert_eq!(lists[1]["is_default"], false);
+    assert_eq!(lists[1]["confirmed_subscribers"], 0);
+}
+
+#[tokio_macros::test]
+async fn invalid_or_duplicate_lists_are_rejected() {
+    let app = spawn_app().await;
+    create_list(&app, "rust-weekly").await;
+
+    let duplicate = app
+        .post_list(&serde_json::json!({"slug": "rust-weekly", "name": "Again"}))
+        .await;
+    let invalid_slug = app
+        .post_list(&serde_json::json!({"slug": "Rust Weekly", "name": "Rust"}))
+        .await;
+    let empty_name = app
+        .post_list(&serde_json::json!({"slug": "rust", "name": " "}))
+        .await;
+
+    assert_eq!(duplicate.status().as_u16(), 409);
+    assert_eq!(invalid_slug.status().as_u16(), 400);
+    assert_eq!(empty_name.status().as_u16(), 400);
+}
+
+#[tokio_macros::test]
+async fn one_address_can_subscribe_to_several_lists() {
+    let app = spawn_app().await;
+    create_list(&app, "rust-weekly").await;
+
+    let newsletter = subscribe_to(&app, "newsletter").await;
+    subscribe_to(&app, "rust-weekly").await;
+    reqwest::get(newsletter.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    let subscribers = sqlx::query!(r#"SELECT count(*) as "count!" FROM subscriptions"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(subscribers, 1);
+    // Each list is confirmed on its own.
+    assert_eq!(
+        membership_statuses(&app).await,
+        vec![
+            ("newsletter".into(), "confirmed".into()),
+            ("rust-weekly".into(), "pending_confirmation".into())
+        ]
+    );
+}
+
+#[tokio_macros::test]
+async fn subscribing_to_an_unknown_list_is_rejected() {
+    let app = spawn_app().await;
+
+    let response = app
+        .post_subscriptions("name=kotleta&email=2hcompany%40gmail.com&list=nope".into())
+        .await;
+
+    assert_eq!(response.status().as_u16(), 400);
+    let problem: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(problem["errors"][0]["field"], "list");
+    assert_eq!(problem["errors"][0]["code"], "unknown");
+}
+
+#[tokio_macros::test]
+async fn issues_only_go_to_the_confirmed_subscribers_of_their_list() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    create_list(&app, "rust-weekly").await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    let response = app
+        .post_newsletters(newsletter_request_body(Some("rust-weekly")))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 202);
+    let report: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(report["list"], "rust-weekly");
+    assert_eq!(report["enqueued"], 0);
+    app.dispatch_all_pending_emails().await;
+}
+
+#[tokio_macros::test]
+async fn publishing_to_an_unknown_list_is_rejected() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let response = app
+        .post_newsletters(newsletter_request_body(Some("nope")))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio_macros::test]
+async fn unsubscribing_from_one_list_keeps_the_others() {
+    let app = spawn_app().await;
+    create_list(&app, "rust-weekly").await;
+    for list in ["newsletter", "rust-weekly"] {
+        let links = subscribe_to(&app, list).await;
+        reqwest::get(links.html)
+            .await
+            .unwrap()
+            .error_for_status()
+            .unwrap();
+    }
+    let token = sqlx::query_scalar!(
+        r#"
+        SELECT m.unsubscribe_token
+        FROM list_memberships m
+        JOIN lists l ON l.id = m.list_id
+        WHERE l.slug = 'rust-weekly'
+        "#
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+
+    let page = app.get_unsubscribe(&token).await.text().await.unwrap();
+    app.post_unsubscribe(&token).await;
+
+    assert!(page.contains("Rust Weekly"));
+    assert_eq!(
+        membership_statuses(&app).await,
+        vec![
+            ("newsletter".into(), "confirmed".into()),
+            ("rust-weekly".into(), "unsubscribed".into())
+        ]
+    );
+}
+
+#[tokio_macros::test]
+async fn subscribers_can_rejoin_a_list_they_unsubscribed_from() {
+    let app = spawn_app().await;
+    let links = subscribe_to(&app, "newsletter").await;
+    reqwest::get(links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    let token = sqlx::query_scalar!("SELECT unsubscribe_token FROM list_memberships")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    app.post_unsubscribe(&token).await;
+
+    let links = subscribe_to(&app, "newsletter").await;
+
+    assert_eq!(
+        membership_statuses(&app).await,
+        vec![("newsletter".into(), "pending_confirmation".into())]
+    );
+    reqwest::get(links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    assert_eq!(
+        membership_statuses(&app).await,
+        vec![("newsletter".into(), "confirmed".into())]
+    );
+}
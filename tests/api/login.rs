@@ -0,0 +1,68 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app};
+
+#[tokio_macros::test]
+async fn an_error_flash_message_is_set_on_failure() {
+    let app = spawn_app().await;
+
+    let login_body = serde_json::json!({
+        "username": "random-username",
+        "password": "random-password"
+    });
+    let response = app.post_login(&login_body).await;
+    assert_is_redirect_to(&response, "/login");
+
+    let html_page = app.get_login_html().await;
+    assert!(html_page.contains("<p><i>Authentication failed</i></p>"));
+
+    // The flash message is gone after a reload.
+    let html_page = app.get_login_html().await;
+    assert!(!html_page.contains("Authentication failed"));
+}
+
+#[tokio_macros::test]
+async fn a_wrong_password_is_rejected() {
+    let app = spawn_app().await;
+
+    let login_body = serde_json::json!({
+        "username": &app.test_user.username,
+        "password": "not-the-password"
+    });
+    let response = app.post_login(&login_body).await;
+
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio_macros::test]
+async fn redirect_to_admin_dashboard_after_login_success() {
+    let app = spawn_app().await;
+
+    let login_body = serde_json::json!({
+        "username": &app.test_user.username,
+        "password": &app.test_user.password
+    });
+    let response = app.post_login(&login_body).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+
+    let html_page = app.get_admin_dashboard_html().await;
+    assert!(html_page.contains(&format!("Welcome {}", app.test_user.username)));
+}
+
+#[tokio_macros::test]
+async fn the_session_cookie_is_signed_http_only_and_same_site() {
+    let app = spawn_app().await;
+
+    let login_body = serde_json::json!({
+        "username": &app.test_user.username,
+        "password": &app.test_user.password
+    });
+    let response = app.post_login(&login_body).await;
+
+    let session_cookie = response
+        .cookies()
+        .find(|c| c.name() == "id")
+        .expect("No session cookie was set");
+    assert!(session_cookie.http_only());
+    assert!(session_cookie.same_site_lax());
+    // Signed cookies carry the value after a base64-encoded MAC.
+    assert!(session_cookie.value().len() > 44);
+}
@@ -1,4 +1,16 @@
+mod admin_dashboard;
+mod consent_events;
+mod erasure;
 mod health_check;
 mod helpers;
+mod lists;
+mod login;
+mod newsletters;
 mod subscriptions;
+mod subscriptions_bot_protection;
 mod subscriptions_confirm;
+mod subscriptions_export;
+mod subscriptions_rate_limit;
+mod subscriptions_unsubscribe;
+mod suppressions;
+mod webhooks;
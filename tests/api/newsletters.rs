@@ -0,0 +1,393 @@
+use uuid::Uuid;
+use wiremock::{
+    matchers::{any, method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::helpers::{
+    assert_is_redirect_to, create_confirmed_subscriber, create_unconfirmed_subscriber,
+    newsletter_request_body, spawn_app,
+};
+
+#[tokio_macros::test]
+async fn newsletters_are_not_delivered_to_unconfirmed_subscribers() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    create_unconfirmed_subscriber(&app).await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.post_newsletters(newsletter_request_body(None)).await;
+
+    assert_eq!(response.status().as_u16(), 202);
+    app.dispatch_all_pending_emails().await;
+}
+
+#[tokio_macros::test]
+async fn newsletters_are_delivered_to_confirmed_subscribers() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.post_newsletters(newsletter_request_body(None)).await;
+
+    assert_eq!(response.status().as_u16(), 202);
+    let report: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(report["enqueued"], 1);
+    assert_eq!(report["failed"], serde_json::json!([]));
+    app.dispatch_all_pending_emails().await;
+}
+
+#[tokio_macros::test]
+async fn newsletters_returns_400_for_invalid_data() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let test_cases = vec![
+        (
+            serde_json::json!({
+                "content": {
+                    "text": "Newsletter body as plain text",
+                    "html": "<p>Newsletter body as HTML</p>",
+                }
+            }),
+            "missing title",
+        ),
+        (
+            serde_json::json!({"title": "Newsletter!"}),
+            "missing content",
+        ),
+    ];
+
+    for (invalid_body, error_message) in test_cases {
+        let response = app.post_newsletters(invalid_body).await;
+
+        assert_eq!(
+            400,
+            response.status().as_u16(),
+            "The API did not fail with 400 Bad Request when the payload was {}.",
+            error_message
+        );
+    }
+}
+
+#[tokio_macros::test]
+async fn titles_that_cannot_be_an_email_subject_are_rejected() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    let test_cases = [
+        (
+            "Issue #1\r\nBcc: everyone@example.com",
+            "a multi-line title",
+        ),
+        ("  ", "a blank title"),
+    ];
+
+    for (title, description) in test_cases {
+        let mut body = newsletter_request_body(None);
+        body["title"] = title.into();
+        let response = app.post_newsletters(body).await;
+
+        assert_eq!(
+            400,
+            response.status().as_u16(),
+            "The API did not reject {}.",
+            description
+        );
+    }
+    let n_issues = sqlx::query!(r#"SELECT count(*) as "count!" FROM newsletter_issues"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_issues, 0);
+}
+
+#[tokio_macros::test]
+async fn newsletters_report_confirmed_subscribers_with_an_invalid_stored_email() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let subscriber_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, email, name, subscribed_at, status)
+        VALUES ($1, 'not-an-email', 'kotleta', now(), 'active')
+        "#,
+        subscriber_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    sqlx::query!(
+        r#"
+        INSERT INTO list_memberships (list_id, subscriber_id, status, subscribed_at)
+        SELECT id, $1, 'confirmed', now() FROM lists WHERE is_default
+        "#,
+        subscriber_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.post_newsletters(newsletter_request_body(None)).await;
+
+    assert_eq!(response.status().as_u16(), 202);
+    let report: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(report["enqueued"], 0);
+    assert_eq!(report["failed"][0]["email"], "not-an-email");
+    app.dispatch_all_pending_emails().await;
+}
+
+#[tokio_macros::test]
+async fn failed_deliveries_stay_in_the_queue_to_be_retried() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.post_newsletters(newsletter_request_body(None)).await;
+    assert_eq!(response.status().as_u16(), 202);
+    app.dispatch_all_pending_emails().await;
+
+    let task = sqlx::query!("SELECT subscriber_email, n_retries FROM issue_delivery_queue")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch the delivery task.");
+    assert_eq!(task.subscriber_email, "2hcompany@gmail.com");
+    assert_eq!(task.n_retries, 1);
+}
+
+#[tokio_macros::test]
+async fn deliveries_rejected_by_the_provider_are_not_retried() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(422))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.post_newsletters(newsletter_request_body(None)).await;
+    assert_eq!(response.status().as_u16(), 202);
+    app.dispatch_all_pending_emails().await;
+
+    let n_tasks = sqlx::query!(r#"SELECT count(*) as "count!" FROM issue_delivery_queue"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_tasks, 0);
+}
+
+#[tokio_macros::test]
+async fn every_delivery_attempt_is_recorded_in_the_email_log() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .up_to_n_times(1)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(422).set_body_json(serde_json::json!({
+            "ErrorCode": 406,
+            "Message": "You tried to send to a recipient that has been marked as inactive."
+        })))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    app.post_newsletters(newsletter_request_body(None)).await;
+    app.dispatch_all_pending_emails().await;
+    // The first attempt failed transiently: run the rescheduled task right away.
+    sqlx::query!("UPDATE issue_delivery_queue SET execute_after = now()")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    app.dispatch_all_pending_emails().await;
+
+    let logged = sqlx::query!(
+        r#"
+        SELECT outcome, error_code, newsletter_issue_id
+        FROM email_log
+        WHERE email_type = 'newsletter_issue'
+        ORDER BY created_at
+        "#
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .unwrap();
+    let outcomes: Vec<_> = logged
+        .iter()
+        .map(|row| (row.outcome.as_str(), row.error_code))
+        .collect();
+    assert_eq!(outcomes, vec![("deferred", None), ("failed", Some(406))]);
+    assert!(logged.iter().all(|row| row.newsletter_issue_id.is_some()));
+}
+
+#[tokio_macros::test]
+async fn delivered_tasks_are_removed_from_the_queue() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    app.post_newsletters(newsletter_request_body(None)).await;
+    app.dispatch_all_pending_emails().await;
+
+    let n_tasks = sqlx::query!(r#"SELECT count(*) as "count!" FROM issue_delivery_queue"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_tasks, 0);
+}
+
+#[tokio_macros::test]
+async fn newsletters_require_an_idempotency_key() {
+    let app = spawn_app().await;
+
+    app.test_user.login(&app).await;
+
+    let response = app
+        .api_client
+        .post(format!("http://{}/admin/newsletters", &app.address))
+        .json(&newsletter_request_body(None))
+        .send()
+        .await
+        .expect("Failed to execute remote request");
+
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio_macros::test]
+async fn newsletter_creation_is_idempotent() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let idempotency_key = Uuid::new_v4().to_string();
+    let first = app
+        .post_newsletters_with_key(newsletter_request_body(None), &idempotency_key)
+        .await;
+    assert_eq!(first.status().as_u16(), 202);
+    let first_report: serde_json::Value = first.json().await.unwrap();
+
+    let second = app
+        .post_newsletters_with_key(newsletter_request_body(None), &idempotency_key)
+        .await;
+    assert_eq!(second.status().as_u16(), 202);
+    assert_eq!(
+        second.headers().get("Content-Type").unwrap(),
+        "application/json"
+    );
+    let second_report: serde_json::Value = second.json().await.unwrap();
+    assert_eq!(first_report, second_report);
+
+    app.dispatch_all_pending_emails().await;
+}
+
+#[tokio_macros::test]
+async fn reusing_an_idempotency_key_for_another_issue_is_rejected() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let idempotency_key = Uuid::new_v4().to_string();
+    let first = app
+        .post_newsletters_with_key(newsletter_request_body(None), &idempotency_key)
+        .await;
+    let mut other_issue = newsletter_request_body(None);
+    other_issue["title"] = "Another title".into();
+    let second = app
+        .post_newsletters_with_key(other_issue, &idempotency_key)
+        .await;
+
+    assert_eq!(first.status().as_u16(), 202);
+    assert_eq!(second.status().as_u16(), 422);
+    let n_issues = sqlx::query!(r#"SELECT count(*) as "count!" FROM newsletter_issues"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_issues, 1);
+}
+
+#[tokio_macros::test]
+async fn concurrent_form_submission_is_handled_gracefully() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let idempotency_key = Uuid::new_v4().to_string();
+    let response1 = app.post_newsletters_with_key(newsletter_request_body(None), &idempotency_key);
+    let response2 = app.post_newsletters_with_key(newsletter_request_body(None), &idempotency_key);
+    let (response1, response2) = tokio::join!(response1, response2);
+
+    assert_eq!(response1.status(), response2.status());
+    assert_eq!(
+        response1.text().await.unwrap(),
+        response2.text().await.unwrap()
+    );
+
+    app.dispatch_all_pending_emails().await;
+}
+
+#[tokio_macros::test]
+async fn you_must_be_logged_in_to_publish_a_newsletter() {
+    let app = spawn_app().await;
+
+    let response = app.post_newsletters(newsletter_request_body(None)).await;
+
+    assert_is_redirect_to(&response, "/login");
+}
@@ -1,9 +1,9 @@
 use wiremock::{
-    matchers::{method, path},
+    matchers::{any, method, path},
     Mock, ResponseTemplate,
 };
 
-use crate::helpers::spawn_app;
+use crate::helpers::{create_confirmed_subscriber, spawn_app, TestApp};
 
 #[tokio_macros::test]
 async fn subscribe_returns_200_for_valid_form_data() {
@@ -34,10 +34,16 @@ async fn subscribe_persists_new_subscriber() {
 
     test_app.post_subscriptions(body.to_string()).await;
 
-    let saved = sqlx::query!("SELECT email, name, status FROM subscriptions")
-        .fetch_one(&test_app.db_pool)
-        .await
-        .expect("Failed to fetch saved subscription");
+    let saved = sqlx::query!(
+        r#"
+        SELECT s.email, s.name, m.status
+        FROM subscriptions s
+        JOIN list_memberships m ON m.subscriber_id = s.id
+        "#
+    )
+    .fetch_one(&test_app.db_pool)
+    .await
+    .expect("Failed to fetch saved subscription");
 
     assert_eq!(saved.email, "2hcompany@gmail.com");
     assert_eq!(saved.name, "kotleta");
@@ -66,6 +72,27 @@ async fn subscribe_returns_400_for_invalid_form_data() {
     }
 }
 
+#[tokio_macros::test]
+async fn invalid_form_data_is_reported_as_problem_details_per_field() {
+    let app = spawn_app().await;
+    let body = format!("name={}&email=not-an-email", "n".repeat(257));
+
+    let response = app.post_subscriptions(body).await;
+
+    assert_eq!(response.status().as_u16(), 400);
+    assert_eq!(
+        response.headers()["Content-Type"],
+        "application/problem+json"
+    );
+    let problem: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(problem["status"], 400);
+    assert_eq!(problem["title"], "Bad Request");
+    assert_eq!(problem["errors"][0]["field"], "name");
+    assert_eq!(problem["errors"][0]["code"], "too_long");
+    assert_eq!(problem["errors"][1]["field"], "email");
+    assert_eq!(problem["errors"][1]["code"], "invalid_syntax");
+}
+
 #[tokio_macros::test]
 async fn subscribe_returns_400_when_data_is_missing() {
     let test_app = spawn_app().await;
@@ -100,6 +127,7 @@ async fn subscribe_sends_a_confirmation_email_for_valid_data() {
         .await;
 
     app.post_subscriptions(body.into()).await;
+    app.dispatch_all_pending_emails().await;
 }
 
 #[tokio_macros::test]
@@ -114,10 +142,399 @@ async fn subscribe_sends_a_confirmation_email_with_a_link() {
         .await;
 
     app.post_subscriptions(body.into()).await;
+    app.dispatch_all_pending_emails().await;
     let email_request = &app.email_server.received_requests().await.unwrap()[0];
     let confirmation_links = app.get_confirmation_links(email_request);
     assert_eq!(confirmation_links.html, confirmation_links.plain_text);
 }
 
+#[tokio_macros::test]
+async fn retried_subscriptions_with_the_same_idempotency_key_send_one_email() {
+    let app = spawn_app().await;
+    let body = "name=lol&email=kekovich@gmail.com";
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    for _ in 0..2 {
+        let response = reqwest::Client::new()
+            .post(format!("http://{}/subscriptions", &app.address))
+            .header("Content-Type", "application/x-www-form-urlencoded")
+            .header("Idempotency-Key", "subscribe-kekovich")
+            .body(body)
+            .send()
+            .await
+            .expect("Failed to execute remote request");
+        assert_eq!(response.status().as_u16(), 200);
+    }
+    app.dispatch_all_pending_emails().await;
+}
+
+#[tokio_macros::test]
+async fn reusing_an_idempotency_key_for_another_signup_is_rejected() {
+    let app = spawn_app().await;
+    let post = |body: &'static str| {
+        reqwest::Client::new()
+            .post(format!("http://{}/subscriptions", &app.address))
+            .header("Content-Type", "application/x-www-form-urlencoded")
+            .header("Idempotency-Key", "shared-key")
+            .body(body)
+            .send()
+    };
+
+    let first = post("name=lol&email=kekovich%40gmail.com").await.unwrap();
+    let second = post("name=ursula&email=ursula_le_guin%40gmail.com")
+        .await
+        .unwrap();
+
+    assert_eq!(first.status().as_u16(), 200);
+    // Replaying the first response would silently drop the second signup.
+    assert_eq!(second.status().as_u16(), 422);
+    let saved = sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.len(), 1);
+    assert_eq!(saved[0].email, "kekovich@gmail.com");
+}
+
+#[tokio_macros::test]
+async fn subscribe_does_not_wait_for_the_confirmation_email() {
+    let app = spawn_app().await;
+    let body = "name=lol&email=kekovich@gmail.com";
+
+    // Had we called the provider, a failure would show in the response.
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(500))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.post_subscriptions(body.into()).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let saved = sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.email, "kekovich@gmail.com");
+}
+
+#[tokio_macros::test]
+async fn subscribing_twice_while_pending_sends_a_fresh_confirmation_link() {
+    let app = spawn_app().await;
+    let body = "name=lol&email=kekovich@gmail.com";
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    for _ in 0..2 {
+        let response = app.post_subscriptions(body.into()).await;
+        assert_eq!(response.status().as_u16(), 200);
+    }
+    app.dispatch_all_pending_emails().await;
+
+    let email_requests = app.email_server.received_requests().await.unwrap();
+    let first_link = app.get_confirmation_links(&email_requests[0]).html;
+    let second_link = app.get_confirmation_links(&email_requests[1]).html;
+    assert_ne!(first_link, second_link);
+
+    let response = reqwest::get(second_link).await.unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    let saved = sqlx::query!("SELECT status FROM list_memberships")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, "confirmed");
+}
+
+#[tokio_macros::test]
+async fn subscribing_again_once_confirmed_returns_200_without_sending_an_email() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let body = "name=kotleta&email=2hcompany%40gmail.com";
+    let response = app.post_subscriptions(body.into()).await;
+    app.dispatch_all_pending_emails().await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(response.text().await.unwrap(), "");
+}
+
+#[tokio_macros::test]
+async fn addresses_differing_only_in_case_are_the_same_subscriber() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let body = "name=kotleta&email=2HCompany%40gmail.com";
+    let response = app.post_subscriptions(body.into()).await;
+    app.dispatch_all_pending_emails().await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let subscribers = sqlx::query!(r#"SELECT count(*) as "count!" FROM subscriptions"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(subscribers, 1);
+}
+
+#[tokio_macros::test]
+async fn concurrent_first_sign_ups_for_the_same_address_both_succeed() {
+    let app = spawn_app().await;
+    let body = "name=lol&email=kekovich@gmail.com";
+
+    let (first, second) = tokio::join!(
+        app.post_subscriptions(body.into()),
+        app.post_subscriptions(body.into())
+    );
+
+    assert_eq!(first.status().as_u16(), 200);
+    assert_eq!(second.status().as_u16(), 200);
+    let subscribers = sqlx::query!(r#"SELECT count(*) as "count!" FROM subscriptions"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(subscribers, 1);
+}
+
+#[tokio_macros::test]
+async fn concurrent_first_sign_ups_differing_only_in_case_create_one_subscriber() {
+    let app = spawn_app().await;
+
+    let (first, second) = tokio::join!(
+        app.post_subscriptions("name=lol&email=Kekovich%40gmail.com".into()),
+        app.post_subscriptions("name=lol&email=kekovich%40gmail.com".into())
+    );
+
+    assert_eq!(first.status().as_u16(), 200);
+    assert_eq!(second.status().as_u16(), 200);
+    let subscribers = sqlx::query!(r#"SELECT count(*) as "count!" FROM subscriptions"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(subscribers, 1);
+}
+
+#[tokio_macros::test]
+async fn subscribe_fails_if_there_is_a_fatal_database_error() {
+    let app = spawn_app().await;
+    let body = "name=lol&email=kekovich@gmail.com";
+    sqlx::query!("ALTER TABLE subscription_tokens DROP COLUMN subscription_token;")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    let response = app.post_subscriptions(body.into()).await;
+
+    assert_eq!(response.status().as_u16(), 500);
+}
+
+/// The subject of the only email sent so far.
+async fn sent_subject(app: &TestApp) -> String {
+    let requests = app.email_server.received_requests().await.unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&requests[0].body).unwrap();
+    body["Subject"].as_str().unwrap().to_owned()
+}
+
+#[tokio_macros::test]
+async fn confirmation_email_is_sent_in_the_language_of_accept_language() {
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_subscriptions_with_language(
+            "name=ursula&email=ursula_le_guin%40gmail.com".into(),
+            "tlh, fr-CA;q=0.9, en;q=0.8",
+        )
+        .await;
+    app.dispatch_all_pending_emails().await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(sent_subject(&app).await, "Bienvenue !");
+    let saved = sqlx::query!("SELECT locale FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.locale.as_deref(), Some("fr"));
+}
+
+#[tokio_macros::test]
+async fn the_locale_form_field_takes_precedence_over_accept_language() {
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriptions_with_language(
+        "name=ursula&email=ursula_le_guin%40gmail.com&locale=de".into(),
+        "fr",
+    )
+    .await;
+    app.dispatch_all_pending_emails().await;
+
+    assert_eq!(sent_subject(&app).await, "Willkommen!");
+}
+
+#[tokio_macros::test]
+async fn unsupported_languages_fall_back_to_the_default_locale() {
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriptions_with_language(
+        "name=ursula&email=ursula_le_guin%40gmail.com&locale=tlh".into(),
+        "tlh",
+    )
+    .await;
+    app.dispatch_all_pending_emails().await;
+
+    let english = app
+        .email_templates
+        .welcome("en", "ursula", "https://example.com")
+        .unwrap();
+    assert_eq!(sent_subject(&app).await, english.subject);
+    let saved = sqlx::query!("SELECT locale FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.locale.as_deref(), Some("en"));
+}
+
+#[tokio_macros::test]
+async fn confirmation_emails_are_recorded_in_the_email_log() {
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "To": "ursula_le_guin@gmail.com",
+            "SubmittedAt": "2026-10-18T10:00:00Z",
+            "MessageID": "b7bc2f4a-e38e-4336-af7d-e6c392c2f817",
+            "ErrorCode": 0,
+            "Message": "OK"
+        })))
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriptions("name=ursula&email=ursula_le_guin%40gmail.com".into())
+        .await;
+    app.dispatch_all_pending_emails().await;
+
+    let logged = sqlx::query!(
+        r#"
+        SELECT l.email_type, l.outcome, l.provider_message_id
+        FROM email_log l
+        JOIN subscriptions s ON s.id = l.subscriber_id
+        WHERE s.email = 'ursula_le_guin@gmail.com'
+        "#
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(logged.email_type, "confirmation");
+    assert_eq!(logged.outcome, "sent");
+    assert_eq!(
+        logged.provider_message_id.as_deref(),
+        Some("b7bc2f4a-e38e-4336-af7d-e6c392c2f817")
+    );
+}
+
+/// The outcome and error code of the only confirmation email attempt.
+async fn confirmation_attempt(app: &TestApp) -> (String, Option<i64>) {
+    let logged =
+        sqlx::query!("SELECT outcome, error_code FROM email_log WHERE email_type = 'confirmation'")
+            .fetch_one(&app.db_pool)
+            .await
+            .unwrap();
+    (logged.outcome, logged.error_code)
+}
+
+async fn n_queued_emails(app: &TestApp) -> i64 {
+    sqlx::query_scalar!(r#"SELECT count(*) as "count!" FROM transactional_email_queue"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+}
+
+#[tokio_macros::test]
+async fn confirmation_emails_the_provider_rejects_are_recorded_as_failed() {
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(422).set_body_json(serde_json::json!({
+            "ErrorCode": 300,
+            "Message": "Invalid email request"
+        })))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_subscriptions("name=ursula&email=ursula_le_guin%40gmail.com".into())
+        .await;
+    app.dispatch_all_pending_emails().await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        confirmation_attempt(&app).await,
+        ("failed".into(), Some(300))
+    );
+    assert_eq!(n_queued_emails(&app).await, 0);
+}
+
+#[tokio_macros::test]
+async fn confirmation_emails_the_provider_cannot_take_are_recorded_and_retried() {
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_subscriptions("name=ursula&email=ursula_le_guin%40gmail.com".into())
+        .await;
+    app.dispatch_all_pending_emails().await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(confirmation_attempt(&app).await, ("deferred".into(), None));
+    // Rescheduled with a backoff: the next attempt is not due yet.
+    assert_eq!(n_queued_emails(&app).await, 1);
+}
+
 // #[tokio_macros::test]
 // async fn subscribe_returns_
@@ -0,0 +1,207 @@
+use secrecy::Secret;
+use wiremock::{
+    matchers::{any, body_string_contains, method, path},
+    Mock, MockServer, ResponseTemplate,
+};
+use zero2prod::configuration::CaptchaSettings;
+
+use crate::helpers::{spawn_app, spawn_app_with, TestApp};
+
+const BODY: &str = "name=ursula&email=ursula_le_guin%40gmail.com";
+
+async fn expect_emails(app: &TestApp, n: u64) {
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(n)
+        .mount(&app.email_server)
+        .await;
+}
+
+async fn n_subscribers(app: &TestApp) -> i64 {
+    sqlx::query!(r#"SELECT count(*) as "count!" FROM subscriptions"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count
+}
+
+async fn spawn_app_with_captcha(captcha_server: &MockServer) -> TestApp {
+    let verify_url = format!("{}/siteverify", captcha_server.uri());
+    spawn_app_with(|c| {
+        c.bot_protection.captcha = Some(CaptchaSettings {
+            verify_url,
+            secret: Secret::new("captcha-secret".into()),
+            timeout_milliseconds: 1000,
+        })
+    })
+    .await
+}
+
+#[tokio_macros::test]
+async fn a_filled_in_honeypot_is_silently_ignored() {
+    let app = spawn_app().await;
+    expect_emails(&app, 0).await;
+
+    let response = app
+        .post_subscriptions(format!("{}&website=https%3A%2F%2Fspam.example", BODY))
+        .await;
+    app.dispatch_all_pending_emails().await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(n_subscribers(&app).await, 0);
+}
+
+#[tokio_macros::test]
+async fn an_empty_honeypot_lets_the_signup_through() {
+    let app = spawn_app().await;
+    expect_emails(&app, 1).await;
+
+    let response = app.post_subscriptions(format!("{}&website=", BODY)).await;
+    app.dispatch_all_pending_emails().await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(n_subscribers(&app).await, 1);
+}
+
+#[tokio_macros::test]
+async fn a_form_filled_in_too_fast_is_silently_ignored() {
+    let app = spawn_app_with(|c| c.bot_protection.min_fill_seconds = 60).await;
+    expect_emails(&app, 0).await;
+    let form_token = app.get_form_token().await;
+
+    let response = app
+        .post_subscriptions(format!("{}&form_token={}", BODY, form_token))
+        .await;
+    app.dispatch_all_pending_emails().await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(n_subscribers(&app).await, 0);
+}
+
+#[tokio_macros::test]
+async fn a_form_with_a_valid_token_goes_through() {
+    let app = spawn_app_with(|c| {
+        c.bot_protection.require_form_token = true;
+        c.bot_protection.min_fill_seconds = 0;
+    })
+    .await;
+    expect_emails(&app, 1).await;
+    let form_token = app.get_form_token().await;
+
+    let response = app
+        .post_subscriptions(format!("{}&form_token={}", BODY, form_token))
+        .await;
+    app.dispatch_all_pending_emails().await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(n_subscribers(&app).await, 1);
+}
+
+#[tokio_macros::test]
+async fn missing_or_forged_form_tokens_are_silently_ignored_when_required() {
+    let app = spawn_app_with(|c| {
+        c.bot_protection.require_form_token = true;
+        c.bot_protection.min_fill_seconds = 0;
+    })
+    .await;
+    expect_emails(&app, 0).await;
+    let form_token = app.get_form_token().await;
+    let (_, signature) = form_token.split_once('.').unwrap();
+    let test_cases = [
+        BODY.to_string(),
+        format!("{}&form_token=", BODY),
+        format!("{}&form_token=1.{}", BODY, signature),
+    ];
+
+    for body in test_cases {
+        let response = app.post_subscriptions(body).await;
+
+        assert_eq!(response.status().as_u16(), 200);
+    }
+    app.dispatch_all_pending_emails().await;
+    assert_eq!(n_subscribers(&app).await, 0);
+}
+
+#[tokio_macros::test]
+async fn a_verified_captcha_lets_the_signup_through() {
+    let captcha_server = MockServer::start().await;
+    let app = spawn_app_with_captcha(&captcha_server).await;
+    Mock::given(path("/siteverify"))
+        .and(method("POST"))
+        .and(body_string_contains("secret=captcha-secret"))
+        .and(body_string_contains("response=widget-token"))
+        .and(body_string_contains("remoteip=127.0.0.1"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "success": true
+        })))
+        .expect(1)
+        .mount(&captcha_server)
+        .await;
+    expect_emails(&app, 1).await;
+
+    let response = app
+        .post_subscriptions(format!("{}&h-captcha-response=widget-token", BODY))
+        .await;
+    app.dispatch_all_pending_emails().await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(n_subscribers(&app).await, 1);
+}
+
+#[tokio_macros::test]
+async fn a_rejected_captcha_is_silently_ignored() {
+    let captcha_server = MockServer::start().await;
+    let app = spawn_app_with_captcha(&captcha_server).await;
+    Mock::given(path("/siteverify"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "success": false,
+            "error-codes": ["invalid-input-response"]
+        })))
+        .expect(1)
+        .mount(&captcha_server)
+        .await;
+    expect_emails(&app, 0).await;
+
+    let response = app
+        .post_subscriptions(format!("{}&cf-turnstile-response=forged", BODY))
+        .await;
+    app.dispatch_all_pending_emails().await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(n_subscribers(&app).await, 0);
+}
+
+#[tokio_macros::test]
+async fn a_missing_captcha_is_silently_ignored_without_asking_the_provider() {
+    let captcha_server = MockServer::start().await;
+    let app = spawn_app_with_captcha(&captcha_server).await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&captcha_server)
+        .await;
+    expect_emails(&app, 0).await;
+
+    let response = app.post_subscriptions(BODY.into()).await;
+    app.dispatch_all_pending_emails().await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(n_subscribers(&app).await, 0);
+}
+
+#[tokio_macros::test]
+async fn subscribe_fails_if_the_captcha_provider_is_down() {
+    let captcha_server = MockServer::start().await;
+    let app = spawn_app_with_captcha(&captcha_server).await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(503))
+        .mount(&captcha_server)
+        .await;
+    expect_emails(&app, 0).await;
+
+    let response = app
+        .post_subscriptions(format!("{}&captcha_response=widget-token", BODY))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 500);
+}
@@ -1,12 +1,12 @@
 use wiremock::matchers::{method, path};
 use wiremock::{Mock, ResponseTemplate};
 
-use crate::helpers::spawn_app;
+use crate::helpers::{create_unconfirmed_subscriber, spawn_app};
 
 #[tokio_macros::test]
 async fn confirmations_without_token_are_rejected_with_400() {
     let app = spawn_app().await;
-    let response = reqwest::get(&format!("http://{}/subscriptions/confirm", app.address))
+    let response = reqwest::get(format!("http://{}/subscriptions/confirm", app.address))
         .await
         .unwrap();
 
@@ -25,6 +25,7 @@ async fn the_link_returned_by_subscribe_returns_200_if_called() {
         .await;
 
     app.post_subscriptions(body.into()).await;
+    app.dispatch_all_pending_emails().await;
 
     let email_request = &app.email_server.received_requests().await.unwrap()[0];
     let confirmation_links = app.get_confirmation_links(email_request);
@@ -46,6 +47,7 @@ async fn clicking_on_the_confirmation_link_confirms_a_subscriber() {
         .await;
 
     app.post_subscriptions(body.into()).await;
+    app.dispatch_all_pending_emails().await;
     let email_request = &app.email_server.received_requests().await.unwrap()[0];
     let confirmation_links = app.get_confirmation_links(email_request);
 
@@ -55,11 +57,69 @@ async fn clicking_on_the_confirmation_link_confirms_a_subscriber() {
         .error_for_status()
         .unwrap();
 
-    let saved = sqlx::query!("SELECT email, name, status FROM subscriptions")
-        .fetch_one(&app.db_pool)
-        .await
-        .expect("Failed to fetch saved subscription.");
+    let saved = sqlx::query!(
+        r#"
+        SELECT s.email, s.name, m.status
+        FROM subscriptions s
+        JOIN list_memberships m ON m.subscriber_id = s.id
+        "#
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .expect("Failed to fetch saved subscription.");
     assert_eq!(saved.email, "2hcompany@gmail.com");
     assert_eq!(saved.name, "kotleta");
     assert_eq!(saved.status, "confirmed");
 }
+
+#[tokio_macros::test]
+async fn a_confirmation_link_can_only_be_used_once() {
+    let app = spawn_app().await;
+    let confirmation_links = create_unconfirmed_subscriber(&app).await;
+
+    let response = reqwest::get(confirmation_links.html.clone()).await.unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = reqwest::get(confirmation_links.html).await.unwrap();
+    assert_eq!(response.status().as_u16(), 401);
+
+    let token = sqlx::query!("SELECT consumed_at FROM subscription_tokens")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(token.consumed_at.is_some());
+}
+
+#[tokio_macros::test]
+async fn expired_confirmation_links_are_rejected_with_410() {
+    let app = spawn_app().await;
+    let confirmation_links = create_unconfirmed_subscriber(&app).await;
+    sqlx::query!("UPDATE subscription_tokens SET created_at = now() - interval '49 hours'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    let response = reqwest::get(confirmation_links.html).await.unwrap();
+
+    assert_eq!(response.status().as_u16(), 410);
+    assert!(response.text().await.unwrap().contains("Subscribe again"));
+    let saved = sqlx::query!("SELECT status FROM list_memberships")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, "pending_confirmation");
+}
+
+#[tokio_macros::test]
+async fn unknown_confirmation_tokens_are_rejected_with_401() {
+    let app = spawn_app().await;
+
+    let response = reqwest::get(format!(
+        "http://{}/subscriptions/confirm?subscription_token=not-a-token",
+        app.address
+    ))
+    .await
+    .unwrap();
+
+    assert_eq!(response.status().as_u16(), 401);
+}
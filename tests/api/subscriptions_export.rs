@@ -0,0 +1,201 @@
+use reqwest::Url;
+use wiremock::{
+    matchers::{any, method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::helpers::{create_confirmed_subscriber, spawn_app, spawn_app_with, TestApp};
+
+/// Asks for an export of the test subscriber's data and returns the download link.
+async fn request_export_link(app: &TestApp, email: &str) -> Url {
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    app.post_data_export_request(email)
+        .await
+        .error_for_status()
+        .unwrap();
+    app.dispatch_all_pending_emails().await;
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(body["To"], "2hcompany@gmail.com");
+    app.get_confirmation_links(&email_request).html
+}
+
+async fn email_types(app: &TestApp) -> Vec<String> {
+    sqlx::query!("SELECT email_type FROM email_log ORDER BY created_at")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|row| row.email_type)
+        .collect()
+}
+
+#[tokio_macros::test]
+async fn the_export_link_downloads_everything_we_hold_about_the_subscriber() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    // The address on file is used, whatever the case of the one in the request.
+    let link = request_export_link(&app, "2HCompany@gmail.com").await;
+
+    let response = reqwest::get(link).await.unwrap();
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        response.headers()["Content-Disposition"],
+        "attachment; filename=\"subscriber-data.json\""
+    );
+    let export: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(export["subscription"]["email"], "2hcompany@gmail.com");
+    assert_eq!(export["subscription"]["name"], "kotleta");
+    assert_eq!(export["subscription"]["status"], "active");
+    assert_eq!(export["lists"].as_array().unwrap().len(), 1);
+    assert_eq!(export["lists"][0]["list"], "newsletter");
+    assert_eq!(export["lists"][0]["status"], "confirmed");
+    assert_eq!(export["subscription_tokens"].as_array().unwrap().len(), 1);
+    assert!(!export["subscription_tokens"][0]["consumed_at"].is_null());
+    let consent_events: Vec<_> = export["consent_events"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|e| e["event_type"].as_str().unwrap())
+        .collect();
+    assert_eq!(consent_events, vec!["subscribed", "confirmed"]);
+    let delivery_history: Vec<_> = export["delivery_history"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|e| e["email_type"].as_str().unwrap())
+        .collect();
+    assert_eq!(delivery_history, vec!["confirmation", "data_export"]);
+    assert!(export["suppressed_at"].is_null());
+}
+
+#[tokio_macros::test]
+async fn the_export_link_can_be_used_again_until_it_expires() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let link = request_export_link(&app, "2hcompany@gmail.com").await;
+
+    let first = reqwest::get(link.clone()).await.unwrap();
+    let second = reqwest::get(link.clone()).await.unwrap();
+    sqlx::query!("UPDATE data_export_tokens SET created_at = now() - interval '25 hours'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    let expired = reqwest::get(link).await.unwrap();
+
+    assert_eq!(first.status().as_u16(), 200);
+    assert_eq!(second.status().as_u16(), 200);
+    assert_eq!(expired.status().as_u16(), 410);
+}
+
+#[tokio_macros::test]
+async fn an_unknown_export_token_is_rejected() {
+    let app = spawn_app().await;
+
+    let response = reqwest::get(format!(
+        "http://{}/subscriptions/export?token=not-a-token",
+        app.address
+    ))
+    .await
+    .unwrap();
+
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio_macros::test]
+async fn an_export_request_for_an_unknown_address_sends_nothing() {
+    let app = spawn_app().await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.post_data_export_request("nobody@gmail.com").await;
+    app.dispatch_all_pending_emails().await;
+
+    // Nothing tells the caller whether the address is on the list.
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio_macros::test]
+async fn an_export_request_with_an_invalid_address_is_rejected() {
+    let app = spawn_app().await;
+
+    let response = app.post_data_export_request("not-an-email").await;
+
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio_macros::test]
+async fn export_emails_are_recorded_and_respect_the_suppression_list() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    sqlx::query!(
+        "INSERT INTO suppressions (email, reason, source) VALUES ('2hcompany@gmail.com', 'Test', 'test')"
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.post_data_export_request("2hcompany@gmail.com").await;
+    app.dispatch_all_pending_emails().await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(email_types(&app).await, vec!["confirmation", "data_export"]);
+}
+
+#[tokio_macros::test]
+async fn export_emails_the_provider_cannot_take_are_recorded_and_retried() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(500))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.post_data_export_request("2hcompany@gmail.com").await;
+    app.dispatch_all_pending_emails().await;
+
+    // The caller cannot tell that the email has not gone out yet.
+    assert_eq!(response.status().as_u16(), 200);
+    let logged =
+        sqlx::query_scalar!("SELECT outcome FROM email_log WHERE email_type = 'data_export'")
+            .fetch_one(&app.db_pool)
+            .await
+            .unwrap();
+    assert_eq!(logged, "deferred");
+    let n_retries = sqlx::query_scalar!("SELECT n_retries FROM transactional_email_queue")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(n_retries, 1);
+}
+
+#[tokio_macros::test]
+async fn export_requests_are_rate_limited_per_address() {
+    let app = spawn_app_with(|c| c.rate_limit.per_email.burst = 1).await;
+
+    app.post_data_export_request("nobody@gmail.com").await;
+    let response = app.post_data_export_request("NOBODY@gmail.com").await;
+
+    assert_eq!(response.status().as_u16(), 429);
+}
@@ -0,0 +1,133 @@
+use wiremock::{matchers::any, Mock, ResponseTemplate};
+
+use crate::helpers::{spawn_app_with, TestApp};
+
+fn body(local_part: &str) -> String {
+    format!("name=ursula&email={}%40example.com", local_part)
+}
+
+async fn mock_email_server(app: &TestApp) {
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+}
+
+#[tokio_macros::test]
+async fn subscribe_returns_429_once_an_ip_is_over_its_limit() {
+    let app = spawn_app_with(|c| {
+        c.rate_limit.per_ip.burst = 2;
+        c.rate_limit.per_ip.refill_interval_seconds = 60;
+    })
+    .await;
+    mock_email_server(&app).await;
+
+    for i in 0..2 {
+        let response = app.post_subscriptions(body(&format!("ursula{}", i))).await;
+        assert_eq!(response.status().as_u16(), 200);
+    }
+    let response = app.post_subscriptions(body("ursula9")).await;
+
+    assert_eq!(response.status().as_u16(), 429);
+    assert_eq!(response.headers()["Retry-After"], "60");
+}
+
+#[tokio_macros::test]
+async fn invalid_requests_count_against_the_ip_limit() {
+    let app = spawn_app_with(|c| c.rate_limit.per_ip.burst = 1).await;
+    mock_email_server(&app).await;
+
+    app.post_subscriptions("name=ursula".into()).await;
+    let response = app.post_subscriptions(body("ursula")).await;
+
+    assert_eq!(response.status().as_u16(), 429);
+}
+
+#[tokio_macros::test]
+async fn the_trusted_proxy_header_identifies_the_client() {
+    let app = spawn_app_with(|c| {
+        c.rate_limit.per_ip.burst = 1;
+        c.rate_limit.trusted_proxy_header = Some("Fly-Client-IP".into());
+    })
+    .await;
+    mock_email_server(&app).await;
+
+    let first = app
+        .post_subscriptions_with_header(body("a"), "Fly-Client-IP", "203.0.113.1")
+        .await;
+    let other_client = app
+        .post_subscriptions_with_header(body("b"), "Fly-Client-IP", "203.0.113.2")
+        .await;
+    let same_client = app
+        .post_subscriptions_with_header(body("c"), "Fly-Client-IP", "203.0.113.1")
+        .await;
+
+    assert_eq!(first.status().as_u16(), 200);
+    assert_eq!(other_client.status().as_u16(), 200);
+    assert_eq!(same_client.status().as_u16(), 429);
+}
+
+#[tokio_macros::test]
+async fn the_proxy_header_is_ignored_unless_trusted() {
+    let app = spawn_app_with(|c| c.rate_limit.per_ip.burst = 1).await;
+    mock_email_server(&app).await;
+
+    app.post_subscriptions_with_header(body("a"), "Fly-Client-IP", "203.0.113.1")
+        .await;
+    let response = app
+        .post_subscriptions_with_header(body("b"), "Fly-Client-IP", "203.0.113.2")
+        .await;
+
+    assert_eq!(response.status().as_u16(), 429);
+}
+
+#[tokio_macros::test]
+async fn subscribe_returns_429_once_an_address_is_over_its_limit() {
+    let app = spawn_app_with(|c| {
+        c.rate_limit.per_email.burst = 2;
+        c.rate_limit.per_email.refill_interval_seconds = 1200;
+    })
+    .await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(3)
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriptions(body("ursula")).await;
+    app.post_subscriptions(body("ursula")).await;
+    // The limit applies to the address, whatever its case.
+    let response = app.post_subscriptions(body("URSULA")).await;
+    let other_address = app.post_subscriptions(body("le_guin")).await;
+    app.dispatch_all_pending_emails().await;
+
+    assert_eq!(response.status().as_u16(), 429);
+    assert_eq!(response.headers()["Retry-After"], "1200");
+    assert_eq!(other_address.status().as_u16(), 200);
+}
+
+#[tokio_macros::test]
+async fn data_requests_have_their_own_ip_limit() {
+    let app = spawn_app_with(|c| {
+        c.rate_limit.per_ip.burst = 1;
+        c.rate_limit.data_requests_per_ip.burst = 2;
+        c.rate_limit.data_requests_per_ip.refill_interval_seconds = 300;
+    })
+    .await;
+    mock_email_server(&app).await;
+
+    let signup = app.post_subscriptions(body("ursula")).await;
+    let export = app.post_data_export_request("ursula@example.com").await;
+    let erasure = app.post_erasure_request("ursula@example.com").await;
+    let over_limit = app.post_data_export_request("ursula@example.com").await;
+    let another_signup = app.post_subscriptions(body("le_guin")).await;
+
+    // Signing up does not use up the data requests of the IP...
+    assert_eq!(signup.status().as_u16(), 200);
+    assert_eq!(export.status().as_u16(), 200);
+    assert_eq!(erasure.status().as_u16(), 200);
+    // ...and export and erasure requests share a bucket of their own.
+    assert_eq!(over_limit.status().as_u16(), 429);
+    assert_eq!(over_limit.headers()["Retry-After"], "300");
+    assert_eq!(another_signup.status().as_u16(), 429);
+}
@@ -0,0 +1,151 @@
+use wiremock::{
+    matchers::{any, method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::helpers::{create_confirmed_subscriber, newsletter_request_body, spawn_app, TestApp};
+
+async fn get_unsubscribe_token(app: &TestApp) -> String {
+    sqlx::query!("SELECT unsubscribe_token FROM list_memberships")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch the unsubscribe token.")
+        .unsubscribe_token
+}
+
+#[tokio_macros::test]
+async fn newsletters_carry_one_click_unsubscribe_headers() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    app.post_newsletters(newsletter_request_body(None)).await;
+    app.dispatch_all_pending_emails().await;
+
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    let expected_link = format!(
+        "{}/subscriptions/unsubscribe?token={}",
+        app.base_url,
+        get_unsubscribe_token(&app).await
+    );
+    assert_eq!(
+        body["Headers"],
+        serde_json::json!([
+            {"Name": "List-Unsubscribe", "Value": format!("<{}>", expected_link)},
+            {"Name": "List-Unsubscribe-Post", "Value": "List-Unsubscribe=One-Click"},
+        ])
+    );
+    assert!(body["TextBody"].as_str().unwrap().contains(&expected_link));
+}
+
+#[tokio_macros::test]
+async fn the_unsubscribe_page_asks_for_confirmation() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let token = get_unsubscribe_token(&app).await;
+
+    let response = app.get_unsubscribe(&token).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains(&format!(
+        r#"action="/subscriptions/unsubscribe?token={}""#,
+        token
+    )));
+    let saved = sqlx::query!("SELECT status FROM list_memberships")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, "confirmed");
+}
+
+#[tokio_macros::test]
+async fn unknown_unsubscribe_tokens_are_rejected_with_401() {
+    let app = spawn_app().await;
+
+    let response = app.get_unsubscribe("not-a-token").await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    let response = app.post_unsubscribe("not-a-token").await;
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio_macros::test]
+async fn one_click_unsubscribe_unsubscribes_the_subscriber() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let token = get_unsubscribe_token(&app).await;
+
+    let response = app.post_unsubscribe(&token).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let saved = sqlx::query!("SELECT status, unsubscribed_at FROM list_memberships")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, "unsubscribed");
+    assert!(saved.unsubscribed_at.is_some());
+
+    // Mail clients may retry: a second request is harmless.
+    let response = app.post_unsubscribe(&token).await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio_macros::test]
+async fn newsletters_are_not_delivered_to_unsubscribed_subscribers() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    let token = get_unsubscribe_token(&app).await;
+    app.post_unsubscribe(&token).await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.post_newsletters(newsletter_request_body(None)).await;
+    assert_eq!(response.status().as_u16(), 202);
+    app.dispatch_all_pending_emails().await;
+}
+
+#[tokio_macros::test]
+async fn queued_deliveries_are_skipped_after_unsubscribing() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.post_newsletters(newsletter_request_body(None)).await;
+    assert_eq!(response.status().as_u16(), 202);
+    let token = get_unsubscribe_token(&app).await;
+    app.post_unsubscribe(&token).await;
+    app.dispatch_all_pending_emails().await;
+
+    let n_tasks = sqlx::query!(r#"SELECT count(*) as "count!" FROM issue_delivery_queue"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_tasks, 0);
+}
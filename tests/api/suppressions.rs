@@ -0,0 +1,152 @@
+use wiremock::{matchers::any, Mock, ResponseTemplate};
+
+use crate::helpers::{
+    assert_is_redirect_to, create_confirmed_subscriber, newsletter_request_body, spawn_app, TestApp,
+};
+
+fn suppression(email: &str) -> serde_json::Value {
+    serde_json::json!({
+        "email": email,
+        "reason": "Asked us to stop contacting them by phone.",
+        "source": "support"
+    })
+}
+
+async fn suppress(app: &TestApp, email: &str) {
+    app.test_user.login(app).await;
+    let response = app.post_suppression(&suppression(email)).await;
+    assert_eq!(response.status().as_u16(), 201);
+}
+
+async fn email_log_outcomes(app: &TestApp) -> Vec<String> {
+    sqlx::query!("SELECT outcome FROM email_log ORDER BY created_at")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|row| row.outcome)
+        .collect()
+}
+
+#[tokio_macros::test]
+async fn you_must_be_logged_in_to_manage_suppressions() {
+    let app = spawn_app().await;
+
+    let list = app.get_suppressions().await;
+    let add = app.post_suppression(&suppression("a@example.com")).await;
+    let remove = app.delete_suppression("a@example.com").await;
+
+    for response in [list, add, remove] {
+        assert_is_redirect_to(&response, "/login");
+    }
+}
+
+#[tokio_macros::test]
+async fn suppressed_addresses_are_listed_lowercased_with_their_author() {
+    let app = spawn_app().await;
+
+    suppress(&app, "Ursula@Example.com").await;
+    let suppressions: serde_json::Value = app.get_suppressions().await.json().await.unwrap();
+
+    assert_eq!(suppressions.as_array().unwrap().len(), 1);
+    assert_eq!(suppressions[0]["email"], "ursula@example.com");
+    assert_eq!(suppressions[0]["source"], "support");
+    assert_eq!(suppressions[0]["added_by"], app.test_user.username.as_str());
+}
+
+#[tokio_macros::test]
+async fn invalid_suppressions_are_rejected() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let test_cases = [
+        (suppression("not-an-email"), "invalid email"),
+        (
+            serde_json::json!({"email": "a@example.com", "reason": " ", "source": "legal"}),
+            "empty reason",
+        ),
+        (
+            serde_json::json!({"email": "a@example.com", "reason": "Legal hold", "source": ""}),
+            "empty source",
+        ),
+    ];
+
+    for (body, description) in test_cases {
+        let response = app.post_suppression(&body).await;
+
+        assert_eq!(
+            response.status().as_u16(),
+            400,
+            "The API did not reject a suppression with an {}.",
+            description
+        );
+    }
+}
+
+#[tokio_macros::test]
+async fn suppressing_an_address_twice_is_a_conflict() {
+    let app = spawn_app().await;
+    suppress(&app, "ursula@example.com").await;
+
+    let response = app
+        .post_suppression(&suppression("URSULA@example.com"))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 409);
+}
+
+#[tokio_macros::test]
+async fn suppressions_can_be_lifted() {
+    let app = spawn_app().await;
+    suppress(&app, "ursula@example.com").await;
+
+    let first = app.delete_suppression("Ursula@example.com").await;
+    let second = app.delete_suppression("ursula@example.com").await;
+
+    assert_eq!(first.status().as_u16(), 204);
+    assert_eq!(second.status().as_u16(), 404);
+    let suppressions: serde_json::Value = app.get_suppressions().await.json().await.unwrap();
+    assert_eq!(suppressions, serde_json::json!([]));
+}
+
+#[tokio_macros::test]
+async fn suppressed_addresses_get_no_confirmation_email() {
+    let app = spawn_app().await;
+    suppress(&app, "ursula_le_guin@gmail.com").await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_subscriptions("name=ursula&email=ursula_le_guin%40gmail.com".into())
+        .await;
+    app.dispatch_all_pending_emails().await;
+
+    // Nothing tells the caller the address is blocked.
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(email_log_outcomes(&app).await, vec!["suppressed"]);
+}
+
+#[tokio_macros::test]
+async fn suppressed_subscribers_get_no_newsletter() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    suppress(&app, "2hcompany@gmail.com").await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    app.post_newsletters(newsletter_request_body(None)).await;
+    app.dispatch_all_pending_emails().await;
+
+    assert_eq!(email_log_outcomes(&app).await, vec!["sent", "suppressed"]);
+    let n_tasks = sqlx::query!(r#"SELECT count(*) as "count!" FROM issue_delivery_queue"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_tasks, 0);
+}
@@ -0,0 +1,239 @@
+use wiremock::{
+    matchers::{any, method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::helpers::{create_confirmed_subscriber, newsletter_request_body, spawn_app, TestApp};
+
+fn hard_bounce(email: &str) -> serde_json::Value {
+    serde_json::json!({
+        "RecordType": "Bounce",
+        "MessageStream": "outbound",
+        "ID": 4323372036854775807_i64,
+        "Type": "HardBounce",
+        "TypeCode": 1,
+        "Name": "Hard bounce",
+        "MessageID": "883953f4-6105-42a2-a16a-77a8eac79483",
+        "Description": "The server was unable to deliver your message (ex: unknown user, mailbox not found).",
+        "Email": email,
+        "BouncedAt": "2026-10-18T16:33:54.9070259Z",
+        "Inactive": true,
+        "CanActivate": true
+    })
+}
+
+fn spam_complaint(email: &str) -> serde_json::Value {
+    serde_json::json!({
+        "RecordType": "SpamComplaint",
+        "MessageStream": "outbound",
+        "ID": 42,
+        "Type": "SpamComplaint",
+        "TypeCode": 512,
+        "MessageID": "00000000-0000-0000-0000-000000000000",
+        "Email": email,
+        "BouncedAt": "2026-10-18T16:33:54.9070259Z",
+        "Inactive": true
+    })
+}
+
+async fn subscriber_status(app: &TestApp) -> String {
+    sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .status
+}
+
+/// Publishes an issue and runs the delivery worker, expecting no email to go out.
+async fn assert_newsletter_is_not_delivered(app: &TestApp) {
+    app.test_user.login(app).await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    let response = app.post_newsletters(newsletter_request_body(None)).await;
+    assert_eq!(response.status().as_u16(), 202);
+    app.dispatch_all_pending_emails().await;
+}
+
+#[tokio_macros::test]
+async fn requests_without_credentials_are_rejected() {
+    let app = spawn_app().await;
+
+    let response = reqwest::Client::new()
+        .post(format!("http://{}/webhooks/postmark", &app.address))
+        .json(&hard_bounce("2hcompany@gmail.com"))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 401);
+    assert_eq!(
+        response.headers()["WWW-Authenticate"],
+        r#"Basic realm="webhooks""#
+    );
+}
+
+#[tokio_macros::test]
+async fn requests_with_the_wrong_password_are_rejected() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    let response = reqwest::Client::new()
+        .post(format!("http://{}/webhooks/postmark", &app.address))
+        .basic_auth(&app.postmark_webhook.username, Some("not-the-password"))
+        .json(&hard_bounce("2hcompany@gmail.com"))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 401);
+    assert_eq!(subscriber_status(&app).await, "active");
+}
+
+#[tokio_macros::test]
+async fn a_hard_bounce_stops_newsletters_to_the_address() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    let response = app
+        .post_postmark_webhook(&hard_bounce("2hcompany@gmail.com"))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(subscriber_status(&app).await, "bounced");
+    assert_newsletter_is_not_delivered(&app).await;
+}
+
+#[tokio_macros::test]
+async fn a_soft_bounce_leaves_the_subscriber_alone() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let mut event = hard_bounce("2hcompany@gmail.com");
+    event["Type"] = "SoftBounce".into();
+    event["Inactive"] = false.into();
+
+    let response = app.post_postmark_webhook(&event).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(subscriber_status(&app).await, "active");
+}
+
+#[tokio_macros::test]
+async fn a_spam_complaint_stops_newsletters_to_the_address() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    let response = app
+        .post_postmark_webhook(&spam_complaint("2hcompany@gmail.com"))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(subscriber_status(&app).await, "complained");
+    assert_newsletter_is_not_delivered(&app).await;
+}
+
+#[tokio_macros::test]
+async fn events_match_the_address_regardless_of_case() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    let response = app
+        .post_postmark_webhook(&hard_bounce("2HCompany@Gmail.com"))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(subscriber_status(&app).await, "bounced");
+
+    let response = app
+        .post_postmark_webhook(&spam_complaint("2HCOMPANY@GMAIL.COM"))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(subscriber_status(&app).await, "complained");
+}
+
+#[tokio_macros::test]
+async fn complained_subscribers_cannot_be_unsubscribed_out_of_that_status() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let token = sqlx::query!("SELECT unsubscribe_token FROM list_memberships")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .unsubscribe_token;
+
+    app.post_postmark_webhook(&spam_complaint("2hcompany@gmail.com"))
+        .await;
+    app.post_unsubscribe(&token).await;
+
+    assert_eq!(subscriber_status(&app).await, "complained");
+}
+
+#[tokio_macros::test]
+async fn events_about_unknown_addresses_are_acknowledged() {
+    let app = spawn_app().await;
+
+    let response = app
+        .post_postmark_webhook(&hard_bounce("nobody@example.com"))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio_macros::test]
+async fn unhandled_record_types_are_acknowledged() {
+    let app = spawn_app().await;
+
+    let response = app
+        .post_postmark_webhook(&serde_json::json!({
+            "RecordType": "Open",
+            "MessageID": "883953f4-6105-42a2-a16a-77a8eac79483",
+            "Recipient": "2hcompany@gmail.com"
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio_macros::test]
+async fn deliveries_are_recorded_in_the_email_log() {
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "To": "2hcompany@gmail.com",
+            "SubmittedAt": "2026-10-18T10:00:00Z",
+            "MessageID": "883953f4-6105-42a2-a16a-77a8eac79483",
+            "ErrorCode": 0,
+            "Message": "OK"
+        })))
+        .mount(&app.email_server)
+        .await;
+    app.post_subscriptions("name=kotleta&email=2hcompany%40gmail.com".into())
+        .await;
+    app.dispatch_all_pending_emails().await;
+
+    let response = app
+        .post_postmark_webhook(&serde_json::json!({
+            "RecordType": "Delivery",
+            "ServerID": 23,
+            "MessageStream": "outbound",
+            "MessageID": "883953f4-6105-42a2-a16a-77a8eac79483",
+            "Recipient": "2hcompany@gmail.com",
+            "Tag": "",
+            "DeliveredAt": "2026-10-18T10:00:05.0000000Z",
+            "Details": "Test delivery webhook details",
+            "Metadata": {}
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let logged = sqlx::query!("SELECT delivered_at FROM email_log")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(
+        logged.delivered_at.unwrap().to_rfc3339(),
+        "2026-10-18T10:00:05+00:00"
+    );
+}